@@ -0,0 +1,59 @@
+use serde::Deserialize;
+
+/// Header of a block, as served by `/block/height/{height}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Block {
+    pub indep_hash: String,
+    pub height: u64,
+    /// Unix time in seconds the block was mined at.
+    pub timestamp: u64,
+    #[serde(default)]
+    pub previous_block: String,
+    /// Ids of the transactions mined in the block.
+    #[serde(default)]
+    pub txs: Vec<String>,
+}
+
+/// State of the chain served by `/info`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct NetworkInfo {
+    pub height: u64,
+    /// Hash of the block at `height`.
+    pub current: String,
+}
+
+impl Block {
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(json).map_err(|e| format!("Invalid block: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_block() {
+        let json = serde_json::json!({
+            "indep_hash": "block-hash",
+            "height": 1_000,
+            "timestamp": 1_700_000_000,
+            "previous_block": "previous-hash",
+            "txs": ["tx-1", "tx-2"],
+            "reward_addr": "ignored",
+        });
+
+        let block = Block::parse(json.to_string().as_bytes()).unwrap();
+
+        assert_eq!(
+            block,
+            Block {
+                indep_hash: "block-hash".to_string(),
+                height: 1_000,
+                timestamp: 1_700_000_000,
+                previous_block: "previous-hash".to_string(),
+                txs: vec!["tx-1".to_string(), "tx-2".to_string()],
+            }
+        );
+    }
+}
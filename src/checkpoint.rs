@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state;
+use crate::ProcessOptions;
+
+/// Number of bytes read between two checkpoints by default.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// Position of a bundle walk between two entries, from which `resume_bundle` continues.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Id of the root bundle transaction.
+    pub root_tx: String,
+    /// Offset in the root bundle data where the next entry starts.
+    pub offset: u64,
+    /// Bundles being walked, the root bundle first and the bundle of the next entry last.
+    pub stack: Vec<CheckpointLevel>,
+    /// Number of items sent to the channel before the checkpoint, across all runs.
+    pub items_emitted: u64,
+    /// Length of the output once the emitted items were written to it, recorded by writers
+    /// that can roll their output back to it on resume.
+    #[serde(default)]
+    pub output_len: Option<u64>,
+}
+
+/// A bundle being walked when a checkpoint was taken.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointLevel {
+    /// Id of the bundle, the `bundled_in` value of its items.
+    pub bundle_id: String,
+    /// Offset of the bundle header in the root bundle data.
+    pub start: u64,
+    /// Size of the bundle data, when known.
+    pub size: Option<u64>,
+    /// Index of the entry being read. For the last level it starts at `Checkpoint::offset`,
+    /// for the others it is the entry holding the next level.
+    pub entry: u64,
+    /// Offset of that entry in the root bundle data.
+    pub entry_start: u64,
+}
+
+impl Checkpoint {
+    /// Reads the checkpoint saved at `path`, `None` when there is no such file.
+    pub async fn load(path: &Path) -> Result<Option<Self>, String> {
+        state::load(path).await
+    }
+
+    /// Writes the checkpoint to `path`, replacing the previous one at once.
+    pub async fn save(&self, path: &Path) -> Result<(), String> {
+        state::save(path, self).await
+    }
+}
+
+// Nesting of the walk, turned into a checkpoint every `checkpoint_interval` bytes.
+pub(crate) struct Progress {
+    pub(crate) stack: Vec<CheckpointLevel>,
+    // items emitted by earlier runs
+    items_before: u64,
+    last_checkpoint: u64,
+}
+
+impl Progress {
+    pub(crate) fn new() -> Self {
+        Progress {
+            stack: Vec::new(),
+            items_before: 0,
+            last_checkpoint: 0,
+        }
+    }
+
+    pub(crate) fn resumed(checkpoint: &Checkpoint) -> Self {
+        Progress {
+            stack: checkpoint.stack.clone(),
+            items_before: checkpoint.items_emitted,
+            last_checkpoint: checkpoint.offset,
+        }
+    }
+
+    // The bundle whose entries are being read.
+    pub(crate) fn level(&mut self) -> &mut CheckpointLevel {
+        self.stack.last_mut().expect("walking a bundle")
+    }
+
+    // Ids of the bundles being walked, the root bundle first.
+    pub(crate) fn ancestry(&self) -> Vec<String> {
+        self.stack
+            .iter()
+            .map(|level| level.bundle_id.clone())
+            .collect()
+    }
+
+    // Records that entry `index` starts at `position` and sends a checkpoint when enough bytes
+    // were read since the last one.
+    pub(crate) async fn enter(
+        &mut self,
+        index: u64,
+        position: u64,
+        items: usize,
+        options: &ProcessOptions,
+    ) -> Result<(), String> {
+        let level = self.level();
+        level.entry = index;
+        level.entry_start = position;
+
+        let Some(checkpoints) = &options.checkpoints else {
+            return Ok(());
+        };
+        if position < self.last_checkpoint + options.checkpoint_interval {
+            return Ok(());
+        }
+        self.last_checkpoint = position;
+
+        let checkpoint = Checkpoint {
+            root_tx: self.stack[0].bundle_id.clone(),
+            offset: position,
+            stack: self.stack.clone(),
+            items_emitted: self.items_before + items as u64,
+            output_len: None,
+        };
+        checkpoints
+            .send(checkpoint)
+            .await
+            .map_err(|e| format!("Checkpoint send error: {}", e))
+    }
+}
@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use bytes::Bytes;
+use futures_util::stream::{BoxStream, StreamExt};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio_util::io::StreamReader;
+
+use crate::download::RetryPolicy;
+use crate::input::{Input, InputSource, ResumableSource};
+use crate::merkle::{chunk_layout, validate_path};
+use crate::node::NodeClient;
+
+/// Chunks fetched ahead of the one being read by default.
+pub const DEFAULT_PREFETCH: usize = 8;
+
+/// Transaction data assembled from the chunks served by Arweave nodes.
+///
+/// Every chunk is checked against the `data_root` of the transaction with its Merkle proof
+/// before it is handed to the reader. Failed requests and invalid chunks are retried on the
+/// next node of the list.
+#[derive(Debug, Clone)]
+pub struct ChunkSource {
+    nodes: Vec<String>,
+    tx_id: String,
+    prefetch: usize,
+    retry: RetryPolicy,
+}
+
+impl ChunkSource {
+    pub fn new(nodes: &[String], tx_id: &str) -> Self {
+        ChunkSource {
+            nodes: nodes
+                .iter()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .collect(),
+            tx_id: tx_id.to_string(),
+            prefetch: DEFAULT_PREFETCH,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Number of chunks downloaded concurrently ahead of the reader.
+    pub fn with_prefetch(mut self, prefetch: usize) -> Self {
+        self.prefetch = prefetch.max(1);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+impl InputSource for ChunkSource {
+    async fn open(&self) -> Result<Input, String> {
+        self.open_at(0).await
+    }
+}
+
+impl ResumableSource for ChunkSource {
+    async fn open_at(&self, offset: u64) -> Result<Input, String> {
+        if self.nodes.is_empty() {
+            return Err("No node to fetch chunks from".to_string());
+        }
+
+        let client = NodeClient::new(&self.nodes).with_retry(self.retry.clone());
+
+        let header = client.transaction(&self.tx_id).await?;
+        let data_root = header
+            .data_root
+            .ok_or_else(|| format!("Transaction {} has no data_root", self.tx_id))?;
+        let data_size = header.data_size;
+
+        let weave_offset = client.transaction_offset(&self.tx_id).await?;
+        let end_offset = weave_offset.end;
+        if weave_offset.size != data_size {
+            return Err(format!(
+                "Transaction offset size {} does not match data_size {}",
+                weave_offset.size, data_size
+            ));
+        }
+
+        if offset > data_size {
+            return Err(format!(
+                "Offset {} is past the {} bytes of {}",
+                offset, data_size, self.tx_id
+            ));
+        }
+
+        let fetcher = Arc::new(ChunkFetcher {
+            client,
+            data_root,
+            data_size,
+            // absolute weave offset of the first byte of the transaction data
+            start_offset: (end_offset + 1)
+                .checked_sub(data_size)
+                .ok_or_else(|| format!("Invalid transaction offset {}", end_offset))?,
+        });
+
+        let prefetched = {
+            let fetcher = fetcher.clone();
+            let ranges = chunk_layout(data_size)
+                .filter(move |range| !range.is_empty() && range.end > offset);
+            futures_util::stream::iter(ranges)
+                .map(move |range| {
+                    let fetcher = fetcher.clone();
+                    async move { fetcher.fetch(range.start).await }
+                })
+                .buffered(self.prefetch)
+                .boxed()
+        };
+
+        let assembly = Assembly {
+            fetcher,
+            prefetched,
+            pending: None,
+            cursor: offset,
+        };
+        let chunks = futures_util::stream::try_unfold(assembly, Assembly::next_chunk);
+
+        Ok(Input {
+            reader: Box::new(StreamReader::new(Box::pin(chunks))),
+            size: Some(data_size - offset),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ChunkResponse {
+    chunk: String,
+    data_path: String,
+}
+
+// A chunk whose proof and hash were checked, with its bounds in the transaction data.
+struct VerifiedChunk {
+    start: u64,
+    end: u64,
+    data: Bytes,
+}
+
+struct ChunkFetcher {
+    client: NodeClient,
+    data_root: [u8; 32],
+    data_size: u64,
+    start_offset: u64,
+}
+
+impl ChunkFetcher {
+    // Fetches the chunk holding the byte at `offset` of the transaction data.
+    async fn fetch(&self, offset: u64) -> std::io::Result<VerifiedChunk> {
+        let path = format!("/chunk/{}", self.start_offset + offset);
+        self.client
+            .get_with_retry(&path, |body| self.verify(offset, &body))
+            .await
+            .map_err(std::io::Error::other)
+    }
+
+    fn verify(&self, offset: u64, body: &[u8]) -> Result<VerifiedChunk, String> {
+        let response: ChunkResponse =
+            serde_json::from_slice(body).map_err(|e| format!("invalid chunk response: {}", e))?;
+        let data = BASE64_URL
+            .decode(&response.chunk)
+            .map_err(|e| format!("invalid chunk encoding: {}", e))?;
+        let data_path = BASE64_URL
+            .decode(&response.data_path)
+            .map_err(|e| format!("invalid data_path encoding: {}", e))?;
+
+        let proven = validate_path(&self.data_root, offset, self.data_size, &data_path)?;
+        if data.len() as u64 != proven.end - proven.start {
+            return Err(format!(
+                "chunk of {} bytes does not match its proven size {}",
+                data.len(),
+                proven.end - proven.start
+            ));
+        }
+        if <[u8; 32]>::from(Sha256::digest(&data)) != proven.data_hash {
+            return Err("chunk does not match its proven hash".to_string());
+        }
+        if !(proven.start..proven.end).contains(&offset) {
+            return Err(format!(
+                "chunk {}..{} does not hold offset {}",
+                proven.start, proven.end, offset
+            ));
+        }
+
+        Ok(VerifiedChunk {
+            start: proven.start,
+            end: proven.end,
+            data: Bytes::from(data),
+        })
+    }
+}
+
+// Puts verified chunks back in order. The prefetched chunks follow the standard layout,
+// data uploaded with another chunking leaves gaps that are filled one chunk at a time.
+struct Assembly {
+    fetcher: Arc<ChunkFetcher>,
+    prefetched: BoxStream<'static, std::io::Result<VerifiedChunk>>,
+    pending: Option<VerifiedChunk>,
+    cursor: u64,
+}
+
+impl Assembly {
+    async fn next_chunk(mut self) -> std::io::Result<Option<(Bytes, Self)>> {
+        loop {
+            if self.cursor >= self.fetcher.data_size {
+                return Ok(None);
+            }
+
+            let mut chunk = match self.pending.take() {
+                Some(chunk) => chunk,
+                None => match self.prefetched.next().await {
+                    Some(chunk) => chunk?,
+                    None => self.fetcher.fetch(self.cursor).await?,
+                },
+            };
+
+            if chunk.end <= self.cursor {
+                continue;
+            }
+            if chunk.start > self.cursor {
+                let gap = self.fetcher.fetch(self.cursor).await?;
+                self.pending = Some(chunk);
+                chunk = gap;
+            }
+
+            let data = chunk.data.slice((self.cursor - chunk.start) as usize..);
+            self.cursor = chunk.end;
+            return Ok(Some((data, self)));
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::stream::{BoxStream, StreamExt};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, StatusCode};
+
+/// How failed gateway requests are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Consecutive failures tolerated before giving up. Receiving data resets the count.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after every further failure.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn backoff(&self, failures: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(failures.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+// Body of a transaction that survives dropped connections.
+// After a failure the download continues from the last received byte with a Range request,
+// on the next gateway of the list. Gateways ignoring the Range header send the data from the
+// start again, and the bytes already received are dropped.
+pub(crate) struct Download {
+    client: Client,
+    urls: Vec<String>,
+    policy: RetryPolicy,
+    gateway: usize,
+    offset: u64,
+    discard: u64,
+    size: Option<u64>,
+    failures: u32,
+    body: Option<BoxStream<'static, reqwest::Result<Bytes>>>,
+}
+
+impl Download {
+    // Starts downloading the data from byte `offset`.
+    pub(crate) async fn start_at(
+        client: Client,
+        urls: Vec<String>,
+        policy: RetryPolicy,
+        offset: u64,
+    ) -> Result<Self, String> {
+        let mut download = Download {
+            client,
+            urls,
+            policy,
+            gateway: 0,
+            offset,
+            discard: 0,
+            size: None,
+            failures: 0,
+            body: None,
+        };
+        download.connect().await?;
+        Ok(download)
+    }
+
+    // Size of the whole data, when the gateway announced it.
+    pub(crate) fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub(crate) async fn next_chunk(mut self) -> std::io::Result<Option<(Bytes, Self)>> {
+        loop {
+            if self.body.is_none() {
+                self.connect().await.map_err(std::io::Error::other)?;
+            }
+            let Some(body) = self.body.as_mut() else {
+                continue;
+            };
+
+            let error = match body.next().await {
+                Some(Ok(mut chunk)) => {
+                    if self.discard >= chunk.len() as u64 {
+                        self.discard -= chunk.len() as u64;
+                        continue;
+                    }
+                    chunk = chunk.slice(self.discard as usize..);
+                    self.discard = 0;
+                    self.offset += chunk.len() as u64;
+                    self.failures = 0;
+                    return Ok(Some((chunk, self)));
+                }
+                Some(Err(e)) => e.to_string(),
+                None => match self.size {
+                    Some(size) if self.offset < size => {
+                        format!("body ended at byte {} of {}", self.offset, size)
+                    }
+                    _ => return Ok(None),
+                },
+            };
+
+            self.body = None;
+            self.retry(error).await.map_err(std::io::Error::other)?;
+        }
+    }
+
+    async fn connect(&mut self) -> Result<(), String> {
+        loop {
+            if self.size == Some(self.offset) && self.offset > 0 {
+                self.body = Some(futures_util::stream::empty().boxed());
+                return Ok(());
+            }
+            match self.request().await {
+                Ok(body) => {
+                    self.body = Some(body);
+                    return Ok(());
+                }
+                Err(e) => self.retry(e).await?,
+            }
+        }
+    }
+
+    async fn request(&mut self) -> Result<BoxStream<'static, reqwest::Result<Bytes>>, String> {
+        let url = &self.urls[self.gateway];
+        let mut request = self.client.get(url);
+        if self.offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", self.offset));
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", url, e))?;
+
+        match response.status() {
+            StatusCode::OK => {
+                if self.size.is_none() {
+                    self.size = response.content_length();
+                }
+                self.discard = self.offset;
+            }
+            StatusCode::PARTIAL_CONTENT if self.offset > 0 => {
+                let expected = format!("bytes {}-", self.offset);
+                let content_range = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default();
+                if !content_range.starts_with(&expected) {
+                    return Err(format!(
+                        "{}: unexpected Content-Range {:?} for offset {}",
+                        url, content_range, self.offset
+                    ));
+                }
+                if self.size.is_none() {
+                    // bytes {first}-{last}/{size}
+                    self.size = content_range
+                        .rsplit_once('/')
+                        .and_then(|(_, size)| size.parse().ok());
+                }
+                self.discard = 0;
+            }
+            status => return Err(format!("{}: responded {}", url, status)),
+        }
+
+        Ok(response.bytes_stream().boxed())
+    }
+
+    async fn retry(&mut self, error: String) -> Result<(), String> {
+        self.failures += 1;
+        if self.failures > self.policy.max_retries {
+            return Err(format!(
+                "Failed to fetch transaction after {} attempts: {}",
+                self.failures, error
+            ));
+        }
+
+        let backoff = self.policy.backoff(self.failures);
+        self.gateway = (self.gateway + 1) % self.urls.len();
+        tracing::warn!(
+            "Fetching transaction failed: {}, resuming from byte {} on {} in {:?}",
+            error,
+            self.offset,
+            self.urls[self.gateway],
+            backoff
+        );
+        tokio::time::sleep(backoff).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(64), Duration::from_secs(1));
+    }
+}
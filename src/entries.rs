@@ -0,0 +1,216 @@
+use std::io::{self, SeekFrom};
+
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+
+/// Bundles with more entries than this keep their entry table in a temporary file.
+pub const DEFAULT_SPILL_THRESHOLD: u64 = 1 << 20;
+
+// size (u64 little-endian) followed by the id
+const RECORD_SIZE: usize = 8 + 32;
+// entries loaded at once when reading a spilled table
+const CACHE_ENTRIES: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BundleEntry {
+    pub size: u64,
+    pub id: [u8; 32],
+}
+
+/// Entry table of a bundle header.
+///
+/// The table precedes every data item in the stream, so it has to be read completely before
+/// the first item. Small tables are kept in memory, large ones are written to a temporary
+/// file that is read back in chunks while the entries are walked.
+#[derive(Debug)]
+pub enum EntryTable {
+    Memory(Vec<BundleEntry>),
+    Spilled(SpilledEntries),
+}
+
+#[derive(Debug)]
+pub struct SpilledEntries {
+    file: File,
+    len: u64,
+    cache_start: u64,
+    cache: Vec<BundleEntry>,
+}
+
+impl EntryTable {
+    pub fn len(&self) -> u64 {
+        match self {
+            EntryTable::Memory(entries) => entries.len() as u64,
+            EntryTable::Spilled(spilled) => spilled.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self, EntryTable::Spilled(_))
+    }
+
+    pub async fn get(&mut self, index: u64) -> io::Result<BundleEntry> {
+        match self {
+            EntryTable::Memory(entries) => entries
+                .get(index as usize)
+                .copied()
+                .ok_or_else(|| out_of_range(index)),
+            EntryTable::Spilled(spilled) => spilled.get(index).await,
+        }
+    }
+
+    /// Up to `count` entries starting at `start`.
+    pub async fn range(&mut self, start: u64, count: u64) -> io::Result<Vec<BundleEntry>> {
+        let end = start.saturating_add(count).min(self.len());
+        let mut entries = Vec::with_capacity(end.saturating_sub(start) as usize);
+        for index in start..end {
+            entries.push(self.get(index).await?);
+        }
+        Ok(entries)
+    }
+}
+
+impl SpilledEntries {
+    async fn get(&mut self, index: u64) -> io::Result<BundleEntry> {
+        if index >= self.len {
+            return Err(out_of_range(index));
+        }
+
+        let cached = index
+            .checked_sub(self.cache_start)
+            .filter(|offset| *offset < self.cache.len() as u64);
+        if let Some(offset) = cached {
+            return Ok(self.cache[offset as usize]);
+        }
+
+        let count = CACHE_ENTRIES.min(self.len - index);
+        let mut buf = vec![0u8; count as usize * RECORD_SIZE];
+        self.file
+            .seek(SeekFrom::Start(index * RECORD_SIZE as u64))
+            .await?;
+        self.file.read_exact(&mut buf).await?;
+
+        self.cache = buf.chunks_exact(RECORD_SIZE).map(decode_record).collect();
+        self.cache_start = index;
+        Ok(self.cache[0])
+    }
+}
+
+/// Collects the entries of a header as they are read from the stream.
+pub(crate) enum EntryTableBuilder {
+    Memory(Vec<BundleEntry>),
+    Spilled { writer: BufWriter<File>, len: u64 },
+}
+
+impl EntryTableBuilder {
+    pub(crate) fn new(item_count: u64, spill_threshold: u64) -> io::Result<Self> {
+        if item_count <= spill_threshold {
+            return Ok(EntryTableBuilder::Memory(Vec::with_capacity(
+                item_count as usize,
+            )));
+        }
+
+        let file = File::from_std(tempfile::tempfile()?);
+        Ok(EntryTableBuilder::Spilled {
+            writer: BufWriter::new(file),
+            len: 0,
+        })
+    }
+
+    pub(crate) async fn push(&mut self, entry: BundleEntry) -> io::Result<()> {
+        match self {
+            EntryTableBuilder::Memory(entries) => entries.push(entry),
+            EntryTableBuilder::Spilled { writer, len } => {
+                writer.write_all(&entry.size.to_le_bytes()).await?;
+                writer.write_all(&entry.id).await?;
+                *len += 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn finish(self) -> io::Result<EntryTable> {
+        match self {
+            EntryTableBuilder::Memory(entries) => Ok(EntryTable::Memory(entries)),
+            EntryTableBuilder::Spilled { mut writer, len } => {
+                writer.flush().await?;
+                Ok(EntryTable::Spilled(SpilledEntries {
+                    file: writer.into_inner(),
+                    len,
+                    cache_start: 0,
+                    cache: Vec::new(),
+                }))
+            }
+        }
+    }
+}
+
+fn decode_record(record: &[u8]) -> BundleEntry {
+    let mut size = [0u8; 8];
+    size.copy_from_slice(&record[..8]);
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&record[8..]);
+    BundleEntry {
+        size: u64::from_le_bytes(size),
+        id,
+    }
+}
+
+fn out_of_range(index: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("entry {} is out of range", index),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(index: u64) -> BundleEntry {
+        let mut id = [0u8; 32];
+        id[..8].copy_from_slice(&index.to_le_bytes());
+        BundleEntry {
+            size: index * 3,
+            id,
+        }
+    }
+
+    async fn build(count: u64, spill_threshold: u64) -> EntryTable {
+        let mut builder = EntryTableBuilder::new(count, spill_threshold).unwrap();
+        for index in 0..count {
+            builder.push(entry(index)).await.unwrap();
+        }
+        builder.finish().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_small_table_stays_in_memory() {
+        let mut table = build(10, 10).await;
+        assert!(!table.is_spilled());
+        assert_eq!(table.get(9).await.unwrap(), entry(9));
+        assert!(table.get(10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_large_table_is_spilled_and_read_back() {
+        let count = CACHE_ENTRIES * 2 + 5;
+        let mut table = build(count, 100).await;
+        assert!(table.is_spilled());
+        assert_eq!(table.len(), count);
+
+        for index in [0, 1, CACHE_ENTRIES, count - 1, 2, count - 2] {
+            assert_eq!(table.get(index).await.unwrap(), entry(index));
+        }
+        assert!(table.get(count).await.is_err());
+
+        let range = table.range(count - 3, 10).await.unwrap();
+        assert_eq!(
+            range,
+            vec![entry(count - 3), entry(count - 2), entry(count - 1)]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::block::Block;
+use crate::indexer::{BlockReport, BundleOutcome, Indexer};
+use crate::state;
+use crate::DataItem;
+
+/// Number of blocks a `Follower` can roll back by default when the chain forks.
+pub const DEFAULT_CONFIRMATIONS: usize = 15;
+/// Number of times a `Follower` processes a failed bundle again by default, one per step.
+pub const DEFAULT_BUNDLE_RETRIES: u32 = 3;
+
+/// Progress of a `Follower`, persisted so that restarts resume where they left off.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FollowState {
+    /// Height of the next block to index.
+    pub next_height: u64,
+    /// Last indexed blocks, oldest first, checked against the chain to detect forks.
+    pub recent: Vec<IndexedBlock>,
+    /// Bundles of indexed blocks that failed, processed again by the next steps.
+    #[serde(default)]
+    pub failed: Vec<FailedBundle>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedBlock {
+    pub height: u64,
+    pub hash: String,
+    /// Ids of the bundles of the block, whose items are stale once it is orphaned.
+    #[serde(default)]
+    pub bundles: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedBundle {
+    pub tx_id: String,
+    /// Height of the block holding the transaction.
+    pub height: u64,
+    /// Number of times the bundle was processed again since it failed.
+    pub retries: u32,
+}
+
+impl FollowState {
+    pub fn starting_at(height: u64) -> Self {
+        FollowState {
+            next_height: height,
+            recent: Vec::new(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Reads the state saved at `path`, `None` when there is no such file.
+    pub async fn load(path: &Path) -> Result<Option<Self>, String> {
+        state::load(path).await
+    }
+
+    /// Writes the state to `path`, replacing the previous one at once.
+    pub async fn save(&self, path: &Path) -> Result<(), String> {
+        state::save(path, self).await
+    }
+}
+
+/// Blocks indexed and rolled back by one `Follower::step`.
+#[derive(Debug, Clone)]
+pub struct FollowStep {
+    /// Height of the chain tip when the step started.
+    pub tip: u64,
+    /// Indexed blocks no longer on the chain, oldest first. Their heights are indexed again.
+    pub orphaned: Vec<IndexedBlock>,
+    /// Failed bundles of earlier blocks processed again.
+    pub retried: Vec<BundleOutcome>,
+    pub blocks: Vec<BlockReport>,
+}
+
+/// Items written by earlier steps that are stale or written again, reported so that consumers
+/// of the output can drop the earlier copies.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum FollowEvent {
+    /// Block no longer on the chain: the items whose `root_tx` has its hash are stale.
+    Orphaned(IndexedBlock),
+    /// Failed bundle processed again: the items it wrote before failing are written again.
+    Retried { tx_id: String },
+}
+
+impl FollowStep {
+    /// Orphaned blocks then retried bundles of the step.
+    pub fn events(&self) -> Vec<FollowEvent> {
+        let orphaned = self.orphaned.iter().cloned().map(FollowEvent::Orphaned);
+        let retried = self.retried.iter().map(|bundle| FollowEvent::Retried {
+            tx_id: bundle.tx_id.clone(),
+        });
+        orphaned.chain(retried).collect()
+    }
+}
+
+/// Tails the chain, indexing every new block with an `Indexer`.
+///
+/// The hashes of the last `confirmations` indexed blocks are remembered. When the chain no
+/// longer holds one of them, the indexer rolls back to the last block still on the chain and
+/// indexes the new branch, so the items of orphaned blocks are emitted again with the block
+/// that replaced them. Forks of `confirmations` blocks or more are reported as errors.
+///
+/// A block is recorded once all its bundles were processed and, when the indexer sends events
+/// to the writer, once the writer flushed their items, so that a restart never skips items that
+/// were not written. Bundles that failed are kept in the state and processed again by the next
+/// steps, up to `bundle_retries` times, so the items they emitted before failing may be emitted
+/// again. Orphaned blocks and retried bundles are reported by `FollowStep::events`.
+#[derive(Debug)]
+pub struct Follower {
+    indexer: Indexer,
+    state: FollowState,
+    confirmations: usize,
+    bundle_retries: u32,
+    state_path: Option<PathBuf>,
+    cancel: CancellationToken,
+}
+
+impl Follower {
+    pub fn new(indexer: Indexer, state: FollowState) -> Self {
+        Follower {
+            indexer,
+            state,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            bundle_retries: DEFAULT_BUNDLE_RETRIES,
+            state_path: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    pub fn with_confirmations(mut self, confirmations: usize) -> Self {
+        self.confirmations = confirmations.max(1);
+        self
+    }
+
+    pub fn with_bundle_retries(mut self, bundle_retries: u32) -> Self {
+        self.bundle_retries = bundle_retries;
+        self
+    }
+
+    /// Stops the steps between two blocks once `cancel` is cancelled, so that a block is never
+    /// left half indexed.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Saves the state to `path` after every indexed block.
+    pub fn with_state_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.state_path = Some(path.into());
+        self
+    }
+
+    pub fn state(&self) -> &FollowState {
+        &self.state
+    }
+
+    /// Polls the chain tip once, rolls back orphaned blocks, processes the failed bundles again
+    /// and indexes the blocks up to the tip.
+    pub async fn step(&mut self, tx: mpsc::Sender<DataItem>) -> Result<FollowStep, String> {
+        let tip = self.indexer.client().info().await?.height;
+        let orphaned = self.roll_back(tip).await?;
+        let retried = self.retry_failed(tx.clone()).await?;
+
+        let mut blocks = Vec::new();
+        while self.state.next_height <= tip && !self.cancel.is_cancelled() {
+            let block = self
+                .indexer
+                .client()
+                .block_at_height(self.state.next_height)
+                .await?;
+            if let Some(last) = self.state.recent.last() {
+                // the chain forked since the tip was polled, the next step rolls back
+                if block.previous_block != last.hash {
+                    tracing::warn!(
+                        "Block {} does not follow {}, waiting for the next poll",
+                        block.height,
+                        last.hash
+                    );
+                    break;
+                }
+            }
+
+            let report = self.indexer.index_block(&block, tx.clone()).await;
+            self.indexer.flush().await?;
+            self.record(&block, &report).await?;
+            blocks.push(report);
+        }
+
+        Ok(FollowStep {
+            tip,
+            orphaned,
+            retried,
+            blocks,
+        })
+    }
+
+    // Drops the remembered blocks the chain no longer holds and moves back to their height.
+    // The state is left as it was when the fork is too deep, so every step fails again.
+    async fn roll_back(&mut self, tip: u64) -> Result<Vec<IndexedBlock>, String> {
+        let mut kept = self.state.recent.len();
+        for last in self.state.recent.iter().rev() {
+            // a node lagging behind cannot tell
+            if last.height > tip {
+                break;
+            }
+            let block = self.indexer.client().block_at_height(last.height).await?;
+            if block.indep_hash == last.hash {
+                break;
+            }
+            kept -= 1;
+        }
+
+        let Some(first) = self.state.recent.get(kept).cloned() else {
+            return Ok(Vec::new());
+        };
+        let depth = self.state.recent.len() - kept;
+        if depth >= self.confirmations {
+            return Err(format!(
+                "Fork of at least {} blocks, block {} at height {} was orphaned",
+                self.confirmations, first.hash, first.height
+            ));
+        }
+
+        tracing::warn!(
+            "{} blocks orphaned from height {}, indexing them again",
+            depth,
+            first.height
+        );
+        let orphaned = self.state.recent.split_off(kept);
+        // their bundles are processed again with the blocks that replaced them
+        self.state
+            .failed
+            .retain(|bundle| bundle.height < first.height);
+        self.state.next_height = first.height;
+        self.save().await?;
+        Ok(orphaned)
+    }
+
+    // Processes the failed bundles of the state again, forgetting the ones that succeed or ran
+    // out of retries.
+    async fn retry_failed(
+        &mut self,
+        tx: mpsc::Sender<DataItem>,
+    ) -> Result<Vec<BundleOutcome>, String> {
+        let mut outcomes = Vec::new();
+        let mut index = 0;
+        while index < self.state.failed.len() && !self.cancel.is_cancelled() {
+            let failed = self.state.failed[index].clone();
+            let block = self.indexer.client().block_at_height(failed.height).await?;
+            let outcome = match self.indexer.client().transaction(&failed.tx_id).await {
+                Ok(header) => {
+                    self.indexer
+                        .index_transaction(&header, Some(&block), tx.clone())
+                        .await
+                }
+                Err(e) => Indexer::outcome(
+                    &failed.tx_id,
+                    Err(format!("Failed to fetch the header: {}", e)),
+                    Instant::now(),
+                ),
+            };
+            self.indexer.flush().await?;
+            let retries = failed.retries + 1;
+            if outcome.result.is_ok() || retries >= self.bundle_retries {
+                if outcome.result.is_err() {
+                    tracing::error!(
+                        "Giving up on bundle {} after {} retries",
+                        failed.tx_id,
+                        retries
+                    );
+                }
+                self.state.failed.remove(index);
+            } else {
+                self.state.failed[index].retries = retries;
+                index += 1;
+            }
+            self.save().await?;
+            outcomes.push(outcome);
+        }
+        Ok(outcomes)
+    }
+
+    async fn record(&mut self, block: &Block, report: &BlockReport) -> Result<(), String> {
+        if self.bundle_retries > 0 {
+            self.state.failed.extend(
+                report
+                    .bundles
+                    .iter()
+                    .filter(|bundle| bundle.result.is_err())
+                    .map(|bundle| FailedBundle {
+                        tx_id: bundle.tx_id.clone(),
+                        height: block.height,
+                        retries: 0,
+                    }),
+            );
+        }
+        self.state.recent.push(IndexedBlock {
+            height: block.height,
+            hash: block.indep_hash.clone(),
+            bundles: report
+                .bundles
+                .iter()
+                .map(|bundle| bundle.tx_id.clone())
+                .collect(),
+        });
+        let excess = self.state.recent.len().saturating_sub(self.confirmations);
+        self.state.recent.drain(..excess);
+        self.state.next_height = block.height + 1;
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<(), String> {
+        match &self.state_path {
+            Some(path) => self.state.save(path).await,
+            None => Ok(()),
+        }
+    }
+}
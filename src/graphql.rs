@@ -0,0 +1,270 @@
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject, ID,
+};
+use axum::extract::State;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+
+use crate::store::{ItemFilter, ItemStore, TagFilter};
+use crate::DataItem;
+
+/// Default number of items of a `transactions` page, as on arweave.net.
+pub const DEFAULT_PAGE_SIZE: usize = 10;
+/// Largest number of items of a `transactions` page, as on arweave.net.
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// GraphQL schema of the gateways, over the items of an `ItemStore`.
+pub type GatewaySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn gateway_schema(store: ItemStore) -> GatewaySchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(store)
+        .finish()
+}
+
+/// Routes answering GraphQL queries posted to `/graphql`, with a playground on `GET /graphql`.
+pub fn gateway_router(store: ItemStore) -> Router {
+    Router::new()
+        .route("/graphql", get(playground).post(graphql))
+        .with_state(gateway_schema(store))
+}
+
+async fn graphql(
+    State(schema): State<GatewaySchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+async fn playground() -> Html<String> {
+    Html(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+}
+
+/// Root of the queries of a `GatewaySchema`.
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The item with this id.
+    async fn transaction(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<Option<Transaction>> {
+        let filter = ItemFilter {
+            ids: vec![id.to_string()],
+            ..ItemFilter::default()
+        };
+        let items = query(ctx, filter, None, 1).await?;
+        Ok(items.first().map(Transaction::from))
+    }
+
+    /// The items matching every argument given, and one of its values, ordered by id.
+    #[allow(clippy::too_many_arguments)]
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        ids: Option<Vec<ID>>,
+        owners: Option<Vec<String>>,
+        recipients: Option<Vec<String>>,
+        tags: Option<Vec<TagInput>>,
+        bundled_in: Option<Vec<ID>>,
+        #[graphql(default_with = "DEFAULT_PAGE_SIZE as i32")] first: i32,
+        after: Option<String>,
+    ) -> async_graphql::Result<TransactionConnection> {
+        let strings = |values: Option<Vec<ID>>| {
+            values
+                .unwrap_or_default()
+                .into_iter()
+                .map(|value| value.to_string())
+                .collect()
+        };
+        let filter = ItemFilter {
+            ids: strings(ids),
+            owners: owners.unwrap_or_default(),
+            targets: recipients.unwrap_or_default(),
+            tags: tags
+                .unwrap_or_default()
+                .into_iter()
+                .map(|tag| TagFilter {
+                    name: tag.name,
+                    values: tag.values,
+                })
+                .collect(),
+            any_tag: false,
+            bundled_in: strings(bundled_in),
+            root_txs: Vec::new(),
+        };
+        let first = first.clamp(0, MAX_PAGE_SIZE as i32) as usize;
+        // one more item tells whether there is a next page
+        let mut items = query(ctx, filter, after, first + 1).await?;
+        let has_next_page = items.len() > first;
+        items.truncate(first);
+        Ok(TransactionConnection {
+            page_info: PageInfo { has_next_page },
+            edges: items
+                .iter()
+                .map(|item| Edge {
+                    cursor: BASE64_URL.encode(item.id()),
+                    node: Transaction::from(item),
+                })
+                .collect(),
+        })
+    }
+}
+
+async fn query(
+    ctx: &Context<'_>,
+    filter: ItemFilter,
+    after: Option<String>,
+    limit: usize,
+) -> async_graphql::Result<Vec<DataItem>> {
+    let store = ctx.data::<ItemStore>()?.clone();
+    let items = tokio::task::spawn_blocking(move || store.query(&filter, after.as_deref(), limit))
+        .await??;
+    Ok(items)
+}
+
+/// Tag the items have with one of the values.
+#[derive(InputObject)]
+#[graphql(name = "TagFilter")]
+struct TagInput {
+    name: String,
+    values: Vec<String>,
+}
+
+#[derive(SimpleObject)]
+struct TransactionConnection {
+    page_info: PageInfo,
+    edges: Vec<Edge>,
+}
+
+#[derive(SimpleObject)]
+struct PageInfo {
+    has_next_page: bool,
+}
+
+#[derive(SimpleObject)]
+struct Edge {
+    /// Id of the item, to pass as `after` for the next page.
+    cursor: String,
+    node: Transaction,
+}
+
+#[derive(SimpleObject)]
+struct Transaction {
+    id: ID,
+    anchor: String,
+    signature: String,
+    recipient: String,
+    owner: Owner,
+    /// Always zero, as data items carry no fee.
+    fee: Amount,
+    /// Always zero, as data items carry no transfer.
+    quantity: Amount,
+    data: MetaData,
+    tags: Vec<Tag>,
+    /// Block of the root transaction, when it was fetched.
+    block: Option<Block>,
+    bundled_in: Option<Bundle>,
+    /// Same as `bundledIn`, kept for older clients.
+    parent: Option<Bundle>,
+}
+
+#[derive(SimpleObject)]
+struct Owner {
+    address: String,
+    key: String,
+}
+
+#[derive(SimpleObject)]
+struct Amount {
+    winston: String,
+    ar: String,
+}
+
+impl Amount {
+    fn zero() -> Self {
+        Amount {
+            winston: "0".to_string(),
+            ar: "0.000000000000".to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct MetaData {
+    size: String,
+    #[graphql(name = "type")]
+    content_type: Option<String>,
+}
+
+#[derive(SimpleObject)]
+struct Tag {
+    name: String,
+    value: String,
+}
+
+#[derive(SimpleObject)]
+struct Block {
+    id: ID,
+    height: u64,
+    timestamp: Option<u64>,
+    /// Unknown to the indexer.
+    previous: Option<ID>,
+}
+
+#[derive(SimpleObject)]
+struct Bundle {
+    id: ID,
+}
+
+impl From<&DataItem> for Transaction {
+    fn from(item: &DataItem) -> Self {
+        let encode = |bytes: Option<&[u8; 32]>| bytes.map(|b| BASE64_URL.encode(b));
+        let bundle = Bundle {
+            id: ID(item.bundled_in().to_string()),
+        };
+        let block = item.root_tx().and_then(|root_tx| {
+            Some(Block {
+                id: ID(root_tx.block_hash.clone()?),
+                height: root_tx.block_height?,
+                timestamp: root_tx.block_timestamp,
+                previous: None,
+            })
+        });
+        Transaction {
+            id: ID(BASE64_URL.encode(item.id())),
+            anchor: encode(item.anchor()).unwrap_or_default(),
+            signature: BASE64_URL.encode(item.signature()),
+            recipient: encode(item.target()).unwrap_or_default(),
+            owner: Owner {
+                address: item.owner_address(),
+                key: BASE64_URL.encode(item.owner()),
+            },
+            fee: Amount::zero(),
+            quantity: Amount::zero(),
+            data: MetaData {
+                size: item.data_size().to_string(),
+                content_type: item.content_type().map(str::to_string),
+            },
+            tags: item
+                .tags()
+                .iter()
+                .map(|tag| {
+                    let (name, value) = tag.to_text();
+                    Tag { name, value }
+                })
+                .collect(),
+            block,
+            parent: Some(Bundle {
+                id: bundle.id.clone(),
+            }),
+            bundled_in: Some(bundle),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::io::BufReader;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+
+use crate::block::Block;
+use crate::download::RetryPolicy;
+use crate::input::DataSource;
+use crate::node::NodeClient;
+use crate::sink::{BundleDone, WriterEvent};
+use crate::transaction::{RootTransaction, TransactionHeader};
+use crate::{process_bundle_with_options, BundleReport, DataItem, ProcessOptions};
+
+/// Number of bundles processed at the same time by default.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Indexes the bundles of layer 1 transactions, looked up by block or by id.
+///
+/// Headers and blocks are fetched with the `NodeClient`, bundle data is read from the
+/// `DataSource`. At most `concurrency` bundles are processed and `concurrency` headers fetched
+/// at the same time, however many blocks are indexed at once, and the items are all sent to the
+/// same channel.
+#[derive(Debug, Clone)]
+pub struct Indexer {
+    client: NodeClient,
+    data: DataSource,
+    retry: RetryPolicy,
+    concurrency: usize,
+    permits: Arc<Semaphore>,
+    header_permits: Arc<Semaphore>,
+    read_buffer_size: usize,
+    options: ProcessOptions,
+    events: Option<mpsc::Sender<WriterEvent>>,
+}
+
+/// Bundles found in a block and the outcome of processing each of them.
+#[derive(Debug, Clone)]
+pub struct BlockReport {
+    pub height: u64,
+    pub hash: String,
+    pub timestamp: u64,
+    /// Number of transactions in the block, bundles or not.
+    pub transactions: usize,
+    pub bundles: Vec<BundleOutcome>,
+}
+
+/// Outcome of processing the bundle of one transaction.
+#[derive(Debug, Clone)]
+pub struct BundleOutcome {
+    pub tx_id: String,
+    pub result: Result<BundleReport, String>,
+    pub duration: Duration,
+}
+
+impl Indexer {
+    pub fn new(client: NodeClient, data: DataSource) -> Self {
+        Indexer {
+            client,
+            data,
+            retry: RetryPolicy::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            permits: Arc::new(Semaphore::new(DEFAULT_CONCURRENCY)),
+            header_permits: Arc::new(Semaphore::new(DEFAULT_CONCURRENCY)),
+            read_buffer_size: 1024 * 1024,
+            options: ProcessOptions::default(),
+            events: None,
+        }
+    }
+
+    /// Retry policy of the bundle data downloads.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self.permits = Arc::new(Semaphore::new(self.concurrency));
+        self.header_permits = Arc::new(Semaphore::new(self.concurrency));
+        self
+    }
+
+    pub fn with_read_buffer_size(mut self, read_buffer_size: usize) -> Self {
+        self.read_buffer_size = read_buffer_size;
+        self
+    }
+
+    /// Options every bundle is processed with. The expected size, `data_root` and root
+    /// transaction are always taken from the transaction header.
+    pub fn with_options(mut self, options: ProcessOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sends the outcome of every processed bundle to `events`, after its items, so that the
+    /// writer can record it once they are written.
+    pub fn with_events(mut self, events: mpsc::Sender<WriterEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn client(&self) -> &NodeClient {
+        &self.client
+    }
+
+    /// Indexes the blocks from `from` to `to` inclusive, returning the report of each block in
+    /// order, or why it could not be fetched. A block that fails does not stop the others.
+    pub async fn index_range(
+        &self,
+        from: u64,
+        to: u64,
+        tx: mpsc::Sender<DataItem>,
+    ) -> Vec<Result<BlockReport, String>> {
+        stream::iter(from..=to)
+            .map(|height| self.index_block_at(height, tx.clone()))
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    pub async fn index_block_at(
+        &self,
+        height: u64,
+        tx: mpsc::Sender<DataItem>,
+    ) -> Result<BlockReport, String> {
+        let block = self.client.block_at_height(height).await?;
+        Ok(self.index_block(&block, tx).await)
+    }
+
+    /// Processes every transaction of `block` tagged as a bundle.
+    ///
+    /// Transactions whose header cannot be fetched may be bundles, they are reported as failed
+    /// bundles and the other transactions are still processed.
+    pub async fn index_block(&self, block: &Block, tx: mpsc::Sender<DataItem>) -> BlockReport {
+        let headers: Vec<(&String, Result<TransactionHeader, String>)> = stream::iter(&block.txs)
+            .map(|id| async move { (id, self.header(id).await) })
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+
+        let tx = &tx;
+        let bundles = stream::iter(headers.iter().filter_map(|(id, header)| match header {
+            Ok(header) => header.is_bundle().then_some(Ok(header)),
+            Err(e) => Some(Err((*id, e))),
+        }))
+        .map(|header| async move {
+            match header {
+                Ok(header) => {
+                    self.index_transaction(header, Some(block), tx.clone())
+                        .await
+                }
+                Err((id, e)) => Self::outcome(
+                    id,
+                    Err(format!("Failed to fetch the header: {}", e)),
+                    Instant::now(),
+                ),
+            }
+        })
+        .buffered(self.concurrency)
+        .collect()
+        .await;
+
+        BlockReport {
+            height: block.height,
+            hash: block.indep_hash.clone(),
+            timestamp: block.timestamp,
+            transactions: block.txs.len(),
+            bundles,
+        }
+    }
+
+    /// Processes the bundles of the transactions `ids`, returning their outcomes in order.
+    pub async fn index_ids(
+        &self,
+        ids: &[String],
+        tx: mpsc::Sender<DataItem>,
+    ) -> Vec<BundleOutcome> {
+        stream::iter(ids)
+            .map(|id| self.index_id(id, tx.clone()))
+            .buffered(self.concurrency)
+            .collect()
+            .await
+    }
+
+    /// Fetches the header and status of transaction `id` and processes its bundle.
+    pub async fn index_id(&self, id: &str, tx: mpsc::Sender<DataItem>) -> BundleOutcome {
+        let started = Instant::now();
+        let mut root_tx = None;
+        let result = async {
+            let header = self.header(id).await?;
+            if !header.is_bundle() {
+                return Err(format!(
+                    "Transaction {} is not tagged as an ANS-104 binary bundle",
+                    id
+                ));
+            }
+            let status = self
+                .client
+                .transaction_status(id)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to fetch the status of {}: {}", id, e);
+                    None
+                });
+            let processed = Arc::new(RootTransaction::new(&header, status.as_ref()));
+            root_tx = Some(processed.clone());
+            self.process_transaction(&header, processed, tx.clone())
+                .await
+        }
+        .await;
+        let outcome = Self::outcome(id, result, started);
+        // sent before `tx` is dropped, so that the writer is still running
+        self.send_done(root_tx, &outcome).await;
+        outcome
+    }
+
+    /// Processes the bundle of the transaction with header `header`, found in `block` if known.
+    pub async fn index_transaction(
+        &self,
+        header: &TransactionHeader,
+        block: Option<&Block>,
+        tx: mpsc::Sender<DataItem>,
+    ) -> BundleOutcome {
+        let started = Instant::now();
+        let root_tx = Arc::new(match block {
+            Some(block) => RootTransaction::in_block(header, block),
+            None => RootTransaction::new(header, None),
+        });
+        let result = self
+            .process_transaction(header, root_tx.clone(), tx.clone())
+            .await;
+        let outcome = Self::outcome(&header.id, result, started);
+        // sent before `tx` is dropped, so that the writer is still running
+        self.send_done(Some(root_tx), &outcome).await;
+        outcome
+    }
+
+    /// Waits until every item sent so far is durable in the output, once written and flushed
+    /// by the writer the events are sent to. Returns at once without events.
+    pub async fn flush(&self) -> Result<(), String> {
+        let Some(events) = &self.events else {
+            return Ok(());
+        };
+        let (flushed, done) = oneshot::channel();
+        let closed = || "The writer is closed".to_string();
+        events
+            .send(WriterEvent::Flush(flushed))
+            .await
+            .map_err(|_| closed())?;
+        done.await.map_err(|_| closed())?
+    }
+
+    async fn send_done(&self, root_tx: Option<Arc<RootTransaction>>, outcome: &BundleOutcome) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let done = BundleDone {
+            root_tx,
+            summary: outcome.summary(),
+        };
+        if events.send(WriterEvent::BundleDone(done)).await.is_err() {
+            tracing::warn!(
+                "Writer closed, the outcome of {} is not recorded",
+                outcome.tx_id
+            );
+        }
+    }
+
+    pub(crate) fn outcome(
+        id: &str,
+        result: Result<BundleReport, String>,
+        started: Instant,
+    ) -> BundleOutcome {
+        if let Err(e) = &result {
+            tracing::warn!("Failed to index bundle {}: {}", id, e);
+        }
+        BundleOutcome {
+            tx_id: id.to_string(),
+            result,
+            duration: started.elapsed(),
+        }
+    }
+
+    // Fetches the header of transaction `id`, sharing the limit of every block indexed at once.
+    async fn header(&self, id: &str) -> Result<TransactionHeader, String> {
+        let _permit = self
+            .header_permits
+            .acquire()
+            .await
+            .map_err(|e| format!("Indexer closed: {}", e))?;
+        self.client.transaction(id).await
+    }
+
+    async fn process_transaction(
+        &self,
+        header: &TransactionHeader,
+        root_tx: Arc<RootTransaction>,
+        tx: mpsc::Sender<DataItem>,
+    ) -> Result<BundleReport, String> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| format!("Indexer closed: {}", e))?;
+
+        let options = ProcessOptions {
+            expected_size: Some(header.data_size),
+            data_root: header.data_root,
+            root_tx: Some(root_tx),
+            ..self.options.clone()
+        };
+
+        let input = self.data.open(&header.id, &self.retry).await?;
+        let mut reader = BufReader::with_capacity(self.read_buffer_size, input.reader);
+        process_bundle_with_options(&mut reader, tx, &header.id, &options).await
+    }
+}
+
+impl BundleOutcome {
+    pub fn summary(&self) -> BundleSummary {
+        let mut summary = BundleSummary {
+            tx_id: self.tx_id.clone(),
+            items: 0,
+            skipped: 0,
+            errors: Vec::new(),
+            duration_secs: self.duration.as_secs_f64(),
+        };
+        let report = match &self.result {
+            Ok(report) => report,
+            Err(e) => {
+                summary.errors.push(e.clone());
+                return summary;
+            }
+        };
+
+        summary.items = report.items;
+        summary.skipped = report.skipped;
+        if let Some(check) = report
+            .data_root
+            .as_ref()
+            .filter(|check| !check.is_verified())
+        {
+            summary.errors.push(check.to_string());
+        }
+        for (bundle, mismatch) in &report.size_mismatches {
+            summary
+                .errors
+                .push(format!("Bundle {} size mismatch: {}", bundle, mismatch));
+        }
+        if !report.lost.is_empty() {
+            summary
+                .errors
+                .push(format!("Lost entries {:?}", report.lost));
+        }
+        summary
+    }
+}
+
+/// Per transaction line of the summary written at the end of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BundleSummary {
+    pub tx_id: String,
+    pub items: usize,
+    pub skipped: usize,
+    /// Why the bundle failed, or what went wrong while it was processed.
+    pub errors: Vec<String>,
+    pub duration_secs: f64,
+}
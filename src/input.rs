@@ -0,0 +1,339 @@
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use reqwest::Client;
+use tokio::io::{AsyncRead, AsyncSeekExt, BufReader};
+use tokio_util::io::StreamReader;
+
+use crate::chunks::ChunkSource;
+use crate::download::{Download, RetryPolicy};
+
+/// Default gateway serving transaction data at `{base_url}/{tx_id}`.
+pub const DEFAULT_GATEWAY: &str = "https://arweave.net";
+
+/// An opened bundle stream.
+pub struct Input {
+    pub reader: Box<dyn AsyncRead + Unpin + Send>,
+    /// Size of the bundle data when the source knows it upfront.
+    pub size: Option<u64>,
+}
+
+impl std::fmt::Debug for Input {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Input").field("size", &self.size).finish()
+    }
+}
+
+/// Somewhere the bytes of a bundle can be read from.
+pub trait InputSource {
+    fn open(&self) -> impl Future<Output = Result<Input, String>> + Send;
+}
+
+/// A source that can be opened again at any offset of its data, used to resume interrupted
+/// runs from a checkpoint.
+pub trait ResumableSource: InputSource {
+    /// Opens the data from byte `offset`, the size of the input is what is left after it.
+    fn open_at(&self, offset: u64) -> impl Future<Output = Result<Input, String>> + Send;
+}
+
+/// Compression applied to a bundle file or to stdin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression from the file extension (`.gz`, `.gzip`, `.zst`, `.zstd`).
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz" | "gzip") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    fn decode(self, reader: impl AsyncRead + Unpin + Send + 'static) -> Input {
+        match self {
+            Compression::None => Input {
+                reader: Box::new(reader),
+                size: None,
+            },
+            Compression::Gzip => Input {
+                reader: Box::new(GzipDecoder::new(BufReader::new(reader))),
+                size: None,
+            },
+            Compression::Zstd => Input {
+                reader: Box::new(ZstdDecoder::new(BufReader::new(reader))),
+                size: None,
+            },
+        }
+    }
+}
+
+/// Transaction data served by one or more Arweave gateways.
+///
+/// Dropped connections are retried according to the `RetryPolicy`, resuming from the last
+/// received byte on the next gateway of the list, so the reader only fails once every retry
+/// is exhausted.
+#[derive(Debug, Clone)]
+pub struct GatewaySource {
+    client: Client,
+    base_urls: Vec<String>,
+    tx_id: String,
+    retry: RetryPolicy,
+}
+
+impl GatewaySource {
+    pub fn new(base_url: &str, tx_id: &str) -> Self {
+        Self::with_gateways(&[base_url.to_string()], tx_id)
+    }
+
+    /// A source failing over across `base_urls`, tried in order.
+    pub fn with_gateways(base_urls: &[String], tx_id: &str) -> Self {
+        let base_urls = if base_urls.is_empty() {
+            vec![DEFAULT_GATEWAY.to_string()]
+        } else {
+            base_urls
+                .iter()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .collect()
+        };
+
+        GatewaySource {
+            client: Client::new(),
+            base_urls,
+            tx_id: tx_id.to_string(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        self.base_urls
+            .iter()
+            .map(|base_url| format!("{}/{}", base_url, self.tx_id))
+            .collect()
+    }
+}
+
+impl InputSource for GatewaySource {
+    async fn open(&self) -> Result<Input, String> {
+        self.open_at(0).await
+    }
+}
+
+impl ResumableSource for GatewaySource {
+    async fn open_at(&self, offset: u64) -> Result<Input, String> {
+        let download =
+            Download::start_at(self.client.clone(), self.urls(), self.retry.clone(), offset)
+                .await?;
+        let size = download.size().map(|size| size.saturating_sub(offset));
+        let chunks = futures_util::stream::try_unfold(download, Download::next_chunk);
+
+        Ok(Input {
+            reader: Box::new(StreamReader::new(Box::pin(chunks))),
+            size,
+        })
+    }
+}
+
+/// Where the data of transactions found on chain is downloaded from.
+#[derive(Debug, Clone)]
+pub enum DataSource {
+    /// Raw data served by gateways, see `GatewaySource`.
+    Gateways(Vec<String>),
+    /// Verified chunks served by nodes, see `ChunkSource`.
+    Nodes { urls: Vec<String>, prefetch: usize },
+}
+
+impl DataSource {
+    pub async fn open(&self, tx_id: &str, retry: &RetryPolicy) -> Result<Input, String> {
+        match self {
+            DataSource::Gateways(urls) => {
+                GatewaySource::with_gateways(urls, tx_id)
+                    .with_retry(retry.clone())
+                    .open()
+                    .await
+            }
+            DataSource::Nodes { urls, prefetch } => {
+                ChunkSource::new(urls, tx_id)
+                    .with_prefetch(*prefetch)
+                    .with_retry(retry.clone())
+                    .open()
+                    .await
+            }
+        }
+    }
+}
+
+/// A bundle stored in a local file, possibly compressed.
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    path: PathBuf,
+    compression: Compression,
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>, compression: Compression) -> Self {
+        FileSource {
+            path: path.into(),
+            compression,
+        }
+    }
+
+    /// A file whose compression is guessed from its extension.
+    pub fn detect(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let compression = Compression::from_path(&path);
+        FileSource { path, compression }
+    }
+}
+
+impl InputSource for FileSource {
+    async fn open(&self) -> Result<Input, String> {
+        let file = tokio::fs::File::open(&self.path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?;
+
+        let mut input = self.compression.decode(file);
+        if self.compression == Compression::None {
+            let metadata = tokio::fs::metadata(&self.path)
+                .await
+                .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+            input.size = Some(metadata.len());
+        }
+        Ok(input)
+    }
+}
+
+impl ResumableSource for FileSource {
+    async fn open_at(&self, offset: u64) -> Result<Input, String> {
+        if self.compression != Compression::None {
+            return Err(format!(
+                "{} is compressed and cannot be read from an offset",
+                self.path.display()
+            ));
+        }
+
+        let mut file = tokio::fs::File::open(&self.path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", self.path.display(), e))?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?
+            .len();
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(|e| format!("Failed to seek {}: {}", self.path.display(), e))?;
+
+        Ok(Input {
+            reader: Box::new(file),
+            size: Some(size.saturating_sub(offset)),
+        })
+    }
+}
+
+/// A bundle piped through the standard input, possibly compressed.
+#[derive(Debug, Clone)]
+pub struct StdinSource {
+    compression: Compression,
+}
+
+impl StdinSource {
+    pub fn new(compression: Compression) -> Self {
+        StdinSource { compression }
+    }
+}
+
+impl InputSource for StdinSource {
+    async fn open(&self) -> Result<Input, String> {
+        Ok(self.compression.decode(tokio::io::stdin()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_compression::tokio::write::{GzipEncoder, ZstdEncoder};
+    use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    const CONTENT: &[u8] = b"bundle bytes, not a real bundle";
+
+    async fn read_all(source: &impl InputSource) -> (Vec<u8>, Option<u64>) {
+        let mut input = source.open().await.unwrap();
+        let mut bytes = Vec::new();
+        input.reader.read_to_end(&mut bytes).await.unwrap();
+        (bytes, input.size)
+    }
+
+    async fn compress(mut encoder: impl AsyncWrite + Unpin) {
+        encoder.write_all(CONTENT).await.unwrap();
+        encoder.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(
+            Compression::from_path(Path::new("a/bundle.gz")),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("bundle.zst")),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_path(Path::new("bundle")),
+            Compression::None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_file_reports_its_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle");
+        tokio::fs::write(&path, CONTENT).await.unwrap();
+
+        let (bytes, size) = read_all(&FileSource::detect(&path)).await;
+
+        assert_eq!(bytes, CONTENT);
+        assert_eq!(size, Some(CONTENT.len() as u64));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_files_are_decoded() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let gzip = dir.path().join("bundle.gz");
+        let file = tokio::fs::File::create(&gzip).await.unwrap();
+        compress(GzipEncoder::new(file)).await;
+
+        let zstd = dir.path().join("bundle.zst");
+        let file = tokio::fs::File::create(&zstd).await.unwrap();
+        compress(ZstdEncoder::new(file)).await;
+
+        for path in [gzip, zstd] {
+            let (bytes, size) = read_all(&FileSource::detect(&path)).await;
+            assert_eq!(bytes, CONTENT);
+            assert_eq!(size, None);
+        }
+    }
+
+    #[test]
+    fn test_gateway_urls_ignore_trailing_slash() {
+        let source = GatewaySource::new("http://localhost:1984/", "abc");
+        assert_eq!(source.urls(), vec!["http://localhost:1984/abc"]);
+
+        let gateways = ["https://a.io".to_string(), "https://b.io/".to_string()];
+        let source = GatewaySource::with_gateways(&gateways, "abc");
+        assert_eq!(source.urls(), vec!["https://a.io/abc", "https://b.io/abc"]);
+    }
+}
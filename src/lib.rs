@@ -1,7 +1,25 @@
+mod block;
+mod checkpoint;
+mod chunks;
+mod download;
+mod entries;
+mod follow;
+mod graphql;
+mod indexer;
+mod input;
+mod merkle;
+mod node;
+mod payload;
+mod reader;
+mod salvage;
+mod schema;
+mod sink;
+mod state;
+mod store;
 mod tags;
+mod transaction;
 mod utils;
 
-use serde::ser::SerializeStruct;
 use serde::Serialize;
 use serde::Serializer;
 
@@ -9,13 +27,55 @@ use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
 use base64::Engine;
 use sha2::{Digest, Sha256};
 
+use std::sync::Arc;
+
 use tokio::io::AsyncRead;
 use tokio::io::AsyncReadExt;
+use tokio::io::BufReader;
 use tokio::sync::mpsc;
 
 use async_recursion::async_recursion;
 
-use tags::Tag;
+use checkpoint::Progress;
+use entries::EntryTableBuilder;
+use merkle::DataRootReader;
+use reader::TrackedReader;
+use schema::DataItemRecord;
+use utils::U256;
+
+pub use block::{Block, NetworkInfo};
+pub use checkpoint::{Checkpoint, CheckpointLevel, DEFAULT_CHECKPOINT_INTERVAL};
+pub use chunks::{ChunkSource, DEFAULT_PREFETCH};
+pub use download::RetryPolicy;
+pub use entries::{BundleEntry, EntryTable, SpilledEntries, DEFAULT_SPILL_THRESHOLD};
+pub use follow::{
+    FailedBundle, FollowEvent, FollowState, FollowStep, Follower, IndexedBlock,
+    DEFAULT_BUNDLE_RETRIES, DEFAULT_CONFIRMATIONS,
+};
+pub use graphql::{
+    gateway_router, gateway_schema, GatewaySchema, QueryRoot, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
+pub use indexer::{BlockReport, BundleOutcome, BundleSummary, Indexer, DEFAULT_CONCURRENCY};
+pub use input::{
+    Compression, DataSource, FileSource, GatewaySource, Input, InputSource, ResumableSource,
+    StdinSource, DEFAULT_GATEWAY,
+};
+pub use merkle::{
+    chunk_layout, chunk_proofs, data_root, validate_path, ChunkLayout, ChunkProof, DataRootBuilder,
+    ValidatedChunk, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE,
+};
+pub use node::{NodeClient, TransactionOffset};
+pub use payload::data_router;
+pub use schema::{data_item_schema, DATA_ITEM_SCHEMA_VERSION};
+pub use sink::{
+    parquet_schema, write_items, write_items_with_events, AvroReader, AvroSink, BundleDone,
+    CheckpointLog, JsonFormat, JsonSink, MessagePackReader, MessagePackSink, ParquetOptions,
+    ParquetSink, PostgresSink, Sink, SqliteSink, WriterEvent, AVRO_SCHEMA, DEFAULT_BATCH_SIZE,
+    DEFAULT_ROW_GROUP_BYTES, DEFAULT_ROW_GROUP_ROWS, SQLITE_SCHEMA,
+};
+pub use store::{ItemFilter, ItemIndex, ItemStore, TagFilter};
+pub use tags::Tag;
+pub use transaction::{RootTransaction, TransactionHeader, TransactionStatus};
 
 #[derive(Debug)]
 pub enum StreamParseError {
@@ -43,6 +103,13 @@ pub struct DataItem {
     tags: Vec<Tag>,
     bundled_in: String,
     is_bundle: bool,
+    root_tx: Option<Arc<RootTransaction>>,
+    signature_type: u16,
+    // position in the root bundle data, set by the walk
+    offset: u64,
+    size: u64,
+    header_size: u64,
+    ancestry: Vec<String>,
 }
 
 impl Serialize for DataItem {
@@ -50,30 +117,19 @@ impl Serialize for DataItem {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("DataItem", 8)?;
-
-        let id = self.calculate_id();
-        state.serialize_field("id", &BASE64_URL.encode(id))?;
-
-        state.serialize_field("signature", &BASE64_URL.encode(&self.signature))?;
-        state.serialize_field("owner", &BASE64_URL.encode(&self.owner))?;
-
-        if let Some(target) = &self.target {
-            state.serialize_field("target", &BASE64_URL.encode(target))?;
-        } else {
-            state.serialize_field("target", "")?;
-        }
-
-        if let Some(anchor) = &self.anchor {
-            state.serialize_field("anchor", &BASE64_URL.encode(anchor))?;
-        } else {
-            state.serialize_field("anchor", "")?;
+        let encode = |bytes: Option<&[u8; 32]>| bytes.map(|b| BASE64_URL.encode(b));
+        DataItemRecord {
+            id: BASE64_URL.encode(self.calculate_id()),
+            signature: BASE64_URL.encode(&self.signature),
+            owner: BASE64_URL.encode(&self.owner),
+            target: encode(self.target.as_ref()).unwrap_or_default(),
+            anchor: encode(self.anchor.as_ref()).unwrap_or_default(),
+            tags: &self.tags,
+            bundled_in: &self.bundled_in,
+            is_bundle: self.is_bundle,
+            root_tx: self.root_tx.as_deref(),
         }
-
-        state.serialize_field("tags", &self.tags)?;
-        state.serialize_field("bundled_in", &self.bundled_in)?;
-        state.serialize_field("is_bundle", &self.is_bundle)?;
-        state.end()
+        .serialize(serializer)
     }
 }
 
@@ -84,10 +140,88 @@ impl DataItem {
         hasher.finalize().into()
     }
 
+    pub fn id(&self) -> [u8; 32] {
+        self.calculate_id()
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    pub fn signature_type(&self) -> u16 {
+        self.signature_type
+    }
+
+    pub fn owner(&self) -> &[u8] {
+        &self.owner
+    }
+
+    /// Address of the owner, the base64url SHA-256 of its public key.
+    pub fn owner_address(&self) -> String {
+        BASE64_URL.encode(Sha256::digest(&self.owner))
+    }
+
+    pub fn target(&self) -> Option<&[u8; 32]> {
+        self.target.as_ref()
+    }
+
+    pub fn anchor(&self) -> Option<&[u8; 32]> {
+        self.anchor.as_ref()
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    pub fn bundled_in(&self) -> &str {
+        &self.bundled_in
+    }
+
+    pub fn is_bundle(&self) -> bool {
+        self.is_bundle
+    }
+
+    /// The layer 1 transaction holding the root bundle, when it was given to the processing.
+    pub fn root_tx(&self) -> Option<&RootTransaction> {
+        self.root_tx.as_deref()
+    }
+
+    /// Ids of the bundles holding the item, from the root bundle to `bundled_in`.
+    pub fn ancestry(&self) -> &[String] {
+        &self.ancestry
+    }
+
+    /// Offset of the item in the data of the root bundle.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Size of the item, header and data.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Offset of the item data in the data of the root bundle.
+    pub fn data_offset(&self) -> u64 {
+        self.offset + self.header_size
+    }
+
+    pub fn data_size(&self) -> u64 {
+        self.size.saturating_sub(self.header_size)
+    }
+
+    /// Value of the first `Content-Type` tag, when it is UTF-8.
+    pub fn content_type(&self) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.name().eq_ignore_ascii_case(b"Content-Type"))
+            .and_then(|tag| std::str::from_utf8(tag.value()).ok())
+    }
+
     pub async fn parse_stream<R: AsyncRead + Unpin>(
         stream: &mut R,
         bundled_in: String,
-        size: usize,
+        size: u64,
     ) -> Result<Self, StreamParseError> {
         let mut bytes_read = 0;
 
@@ -219,6 +353,13 @@ impl DataItem {
             }
         })?;
 
+        if tags_length as u64 > size.saturating_sub(bytes_read as u64) {
+            return Err(StreamParseError::ParseError {
+                message: format!("Tags length {} exceeds the entry size", tags_length),
+                bytes_read,
+            });
+        }
+
         let mut tags_bytes = vec![0u8; tags_length];
         stream
             .read_exact(&mut tags_bytes)
@@ -226,11 +367,15 @@ impl DataItem {
             .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
         bytes_read += tags_length;
 
-        let (tags, is_bundle) =
+        // items without tags have a zero tags length and no Avro block at all
+        let (tags, is_bundle) = if tags_bytes.is_empty() {
+            (Vec::new(), false)
+        } else {
             parse_avro_tags(&tags_bytes).map_err(|e| StreamParseError::ParseError {
                 message: format!("Failed to parse tags: {}", e),
                 bytes_read,
-            })?;
+            })?
+        };
 
         if tags.len() != tag_count {
             return Err(StreamParseError::ParseError {
@@ -251,25 +396,32 @@ impl DataItem {
             tags,
             bundled_in,
             is_bundle,
+            root_tx: None,
+            signature_type,
+            offset: 0,
+            size,
+            header_size: bytes_read as u64,
+            ancestry: Vec::new(),
         };
 
         if !is_bundle {
-            let remaining = size - bytes_read;
-            let mut skip_buf = vec![0u8; remaining];
-            stream
-                .read_exact(&mut skip_buf)
+            let remaining = size.checked_sub(bytes_read as u64).ok_or_else(|| {
+                StreamParseError::ParseError {
+                    message: format!("Data item header exceeds the entry size {}", size),
+                    bytes_read,
+                }
+            })?;
+            skip_bytes(stream, remaining)
                 .await
-                .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
+                .map_err(StreamParseError::FatalError)?;
         }
 
         Ok(item)
     }
 }
 
-// This function is used to parse the tags from the bytes of the tags field in the Arweave transaction.
-// The function returns a tuple containing a vector of tags and a boolean indicating whether the tags are bundled.
-// The reason for this is to traverse the tags only once and filter and get all information we need.
-// The reason function does not prune the invalid tags is that a lot of existing transactions have some invalid tags (mostly empty value tags).
+// Parses the tags field of a data item, returning its valid tags and whether they mark it as a
+// bundle. Invalid tags are logged and dropped.
 fn parse_avro_tags(bytes: &[u8]) -> Result<(Vec<Tag>, bool), String> {
     let schema = tags::TAGS_SCHEMA
         .parse()
@@ -282,64 +434,144 @@ fn parse_avro_tags(bytes: &[u8]) -> Result<(Vec<Tag>, bool), String> {
     }
 
     let mut valid_tags = Vec::with_capacity(tags.len());
-    let mut bundle_format_found = false;
-    let mut bundle_version_found = false;
-
     for tag in tags {
         if let Err(e) = tag.validate() {
             tracing::warn!("Invalid tag found: {:?}, Error: {}", tag, e);
             continue;
         }
-
-        if let Ok((name, value)) = tag.try_to_utf8() {
-            if name == "Bundle-Format" && value == "binary" {
-                bundle_format_found = true;
-            }
-            if name == "Bundle-Version" && value == "2.0.0" {
-                bundle_version_found = true;
-            }
-        }
-
         valid_tags.push(tag);
     }
 
-    let is_bundle = bundle_format_found && bundle_version_found;
+    let is_bundle = tags::marks_bundle(&valid_tags);
     Ok((valid_tags, is_bundle))
 }
 
 #[derive(Debug)]
 pub struct Bundle {
-    pub item_count: usize,
-    pub entries: Vec<BundleEntry>,
+    pub item_count: u64,
+    pub entries: EntryTable,
+    declared_size: u64,
 }
 
-#[derive(Debug)]
-pub struct BundleEntry {
-    pub size: usize,
-    pub id: [u8; 32],
+/// Difference between the size a bundle header declares and the size of the data holding it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SizeMismatch {
+    /// The entry table alone is larger than the data.
+    HeaderOverlong { header: u64, expected: u64 },
+    /// The entries declare more bytes than the data holds.
+    Truncated { declared: u64, expected: u64 },
+    /// The data continues after the last declared entry.
+    TrailingBytes { declared: u64, expected: u64 },
+}
+
+impl std::fmt::Display for SizeMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SizeMismatch::HeaderOverlong { header, expected } => write!(
+                f,
+                "entry table of {} bytes does not fit in {} bytes",
+                header, expected
+            ),
+            SizeMismatch::Truncated { declared, expected } => write!(
+                f,
+                "truncated: header declares {} bytes, data has {}",
+                declared, expected
+            ),
+            SizeMismatch::TrailingBytes { declared, expected } => write!(
+                f,
+                "trailing bytes: header declares {} bytes, data has {}",
+                declared, expected
+            ),
+        }
+    }
 }
 
 impl Bundle {
+    /// Size of the count field and the entry table for `item_count` entries.
+    pub fn header_size(item_count: u64) -> u64 {
+        item_count.saturating_mul(64).saturating_add(32)
+    }
+
+    /// Total size declared by the header, entry table included.
+    pub fn declared_size(&self) -> u64 {
+        self.declared_size
+    }
+
+    /// Compares the declared size with the size of the data holding the bundle.
+    pub fn size_mismatch(&self, expected: u64) -> Option<SizeMismatch> {
+        let declared = self.declared_size();
+        match declared.cmp(&expected) {
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(SizeMismatch::Truncated { declared, expected }),
+            std::cmp::Ordering::Less => Some(SizeMismatch::TrailingBytes { declared, expected }),
+        }
+    }
+
     pub async fn parse_stream<R: AsyncRead + Unpin>(
         stream: &mut R,
+    ) -> Result<Self, StreamParseError> {
+        Self::parse_stream_with_size(stream, None).await
+    }
+
+    /// Parses the header of a bundle held by `expected_size` bytes, when known.
+    /// An entry table that cannot fit is rejected before any entry is read.
+    pub async fn parse_stream_with_size<R: AsyncRead + Unpin>(
+        stream: &mut R,
+        expected_size: Option<u64>,
+    ) -> Result<Self, StreamParseError> {
+        Self::parse_stream_with_threshold(stream, expected_size, DEFAULT_SPILL_THRESHOLD).await
+    }
+
+    /// Like `parse_stream_with_size`, with the entry table written to a temporary file
+    /// when the bundle has more than `spill_threshold` entries.
+    pub async fn parse_stream_with_threshold<R: AsyncRead + Unpin>(
+        stream: &mut R,
+        expected_size: Option<u64>,
+        spill_threshold: u64,
     ) -> Result<Self, StreamParseError> {
         let mut count_buf = [0u8; 32];
         stream
             .read_exact(&mut count_buf)
             .await
             .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
-        let item_count = utils::bytes_to_number(&count_buf)
-            .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
+        let count = U256::from_le_bytes(count_buf);
+
+        if let Some(expected) = expected_size {
+            // the table of a count this large has no size in bytes to report
+            let Some(header) = count
+                .to_u64()
+                .ok()
+                .and_then(|count| count.checked_mul(64)?.checked_add(32))
+            else {
+                return Err(StreamParseError::FatalError(format!(
+                    "entry table of {} entries does not fit in {} bytes",
+                    count, expected
+                )));
+            };
+            if header > expected {
+                return Err(StreamParseError::FatalError(
+                    SizeMismatch::HeaderOverlong { header, expected }.to_string(),
+                ));
+            }
+        }
+
+        let item_count = count.to_u64().map_err(|e| {
+            StreamParseError::FatalError(format!("Bundle item count unsupported: {}", e))
+        })?;
 
-        let mut entries = Vec::with_capacity(item_count as usize);
-        for _ in 0..item_count {
+        let mut entries = EntryTableBuilder::new(item_count, spill_threshold).map_err(|e| {
+            StreamParseError::FatalError(format!("Failed to create entry table: {}", e))
+        })?;
+        let mut declared_size = Self::header_size(item_count);
+        for index in 0..item_count {
             let mut size_buf = [0u8; 32];
             stream
                 .read_exact(&mut size_buf)
                 .await
                 .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
-            let size = utils::bytes_to_number(&size_buf)
-                .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
+            let size = U256::from_le_bytes(size_buf).to_u64().map_err(|e| {
+                StreamParseError::FatalError(format!("Size of entry {} unsupported: {}", index, e))
+            })?;
 
             let mut id = [0u8; 32];
             stream
@@ -347,25 +579,281 @@ impl Bundle {
                 .await
                 .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
 
-            entries.push(BundleEntry { size, id });
+            entries
+                .push(BundleEntry { size, id })
+                .await
+                .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
+            declared_size = declared_size.saturating_add(size);
         }
 
+        let entries = entries
+            .finish()
+            .await
+            .map_err(|e| StreamParseError::FatalError(e.to_string()))?;
+
         Ok(Bundle {
             item_count,
             entries,
+            declared_size,
         })
     }
 }
 
-#[async_recursion]
+/// Options controlling how a bundle is walked by `process_bundle_with_options`.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    /// When an entry is damaged (truncated, overlong or not matching its header id),
+    /// try to relocate the following entries instead of aborting the whole bundle.
+    pub salvage: bool,
+    /// How many already consumed bytes are kept to relocate entries in salvage mode,
+    /// and how far past the damage the relocation keeps looking.
+    pub salvage_window: usize,
+    /// Size of the root bundle data (e.g. the transaction data_size), checked against the header.
+    pub expected_size: Option<u64>,
+    /// Bundles with more entries than this keep their entry table in a temporary file.
+    pub spill_threshold: u64,
+    /// Layer 1 transaction holding the root bundle, attached to every item.
+    pub root_tx: Option<Arc<RootTransaction>>,
+    /// `data_root` of the root transaction. When set, the root of the streamed bytes is
+    /// computed with the chunking of `expected_size` bytes and compared with it.
+    pub data_root: Option<[u8; 32]>,
+    /// Channel a `Checkpoint` is sent to at the first entry boundary after every
+    /// `checkpoint_interval` bytes. Not supported in salvage mode.
+    pub checkpoints: Option<mpsc::Sender<Checkpoint>>,
+    pub checkpoint_interval: u64,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions {
+            salvage: false,
+            salvage_window: 16 * 1024 * 1024,
+            expected_size: None,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            root_tx: None,
+            data_root: None,
+            checkpoints: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        }
+    }
+}
+
+/// Result of comparing the streamed bytes with the `data_root` of the root transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataRootCheck {
+    Verified,
+    Mismatch {
+        expected: [u8; 32],
+        computed: [u8; 32],
+    },
+    /// The stream did not hold exactly `expected_size` bytes.
+    Incomplete(String),
+}
+
+impl DataRootCheck {
+    pub fn is_verified(&self) -> bool {
+        *self == DataRootCheck::Verified
+    }
+}
+
+impl std::fmt::Display for DataRootCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataRootCheck::Verified => write!(f, "data_root verified"),
+            DataRootCheck::Mismatch { expected, computed } => write!(
+                f,
+                "data_root mismatch: expected {}, computed {}",
+                BASE64_URL.encode(expected),
+                BASE64_URL.encode(computed)
+            ),
+            DataRootCheck::Incomplete(e) => write!(f, "data_root not computed: {}", e),
+        }
+    }
+}
+
+/// Summary of a processed bundle, including all of its nested bundles.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct BundleReport {
+    /// Number of data items sent to the channel.
+    pub items: usize,
+    /// Number of entries skipped because their data item could not be parsed.
+    pub skipped: usize,
+    /// Ids of entries found again by salvage mode after a damaged entry.
+    pub recovered: Vec<String>,
+    /// Ids of entries that could not be indexed because of stream damage.
+    pub lost: Vec<String>,
+    /// Bundles whose header does not add up to the size of their data, by bundle id.
+    pub size_mismatches: Vec<(String, SizeMismatch)>,
+    /// Outcome of the `data_root` verification, when `ProcessOptions::data_root` is set.
+    pub data_root: Option<DataRootCheck>,
+}
+
 pub async fn process_bundle(
     stream: &mut (impl AsyncRead + Unpin + Send),
     tx: mpsc::Sender<DataItem>,
     bundled_in: &str,
 ) -> Result<(), String> {
-    let bundle = Bundle::parse_stream(stream)
+    process_bundle_with_options(stream, tx, bundled_in, &ProcessOptions::default())
+        .await
+        .map(|_| ())
+}
+
+pub async fn process_bundle_with_options(
+    stream: &mut (impl AsyncRead + Unpin + Send),
+    tx: mpsc::Sender<DataItem>,
+    bundled_in: &str,
+    options: &ProcessOptions,
+) -> Result<BundleReport, String> {
+    if options.data_root.is_some() && options.expected_size.is_none() {
+        return Err("Verifying the data_root requires the expected size".to_string());
+    }
+    if options.salvage && options.checkpoints.is_some() {
+        return Err("Checkpoints are not supported in salvage mode".to_string());
+    }
+
+    let history = if options.salvage {
+        options.salvage_window
+    } else {
+        0
+    };
+    let hashed = DataRootReader::new(stream, options.data_root.and(options.expected_size));
+    let mut reader = TrackedReader::new(hashed, history);
+    let mut report = BundleReport::default();
+
+    walk_bundle(
+        &mut reader,
+        &tx,
+        bundled_in,
+        options.expected_size,
+        options,
+        &mut report,
+        &mut Progress::new(),
+    )
+    .await?;
+
+    if let Some(expected) = options.data_root {
+        // bytes after the last entry are part of the data as well
+        drain(&mut reader).await?;
+        let check = match reader.get_mut().finish() {
+            Some(Ok(computed)) if computed == expected => DataRootCheck::Verified,
+            Some(Ok(computed)) => DataRootCheck::Mismatch { expected, computed },
+            Some(Err(e)) => DataRootCheck::Incomplete(e),
+            None => DataRootCheck::Incomplete("nothing was hashed".to_string()),
+        };
+        if !check.is_verified() {
+            tracing::warn!("Bundle {}: {}", bundled_in, check);
+        }
+        report.data_root = Some(check);
+    }
+
+    Ok(report)
+}
+
+/// Continues the walk of a bundle from `checkpoint`, reading its data from `source`.
+///
+/// The headers of the bundles on the checkpoint stack are read again from their offsets, then
+/// the walk goes on from the checkpoint offset, so only the items after the checkpoint are sent.
+/// The report only covers them, and the `data_root` is not verified since the data is not
+/// read in full.
+pub async fn resume_bundle(
+    source: &impl ResumableSource,
+    tx: mpsc::Sender<DataItem>,
+    checkpoint: &Checkpoint,
+    options: &ProcessOptions,
+) -> Result<BundleReport, String> {
+    if options.salvage {
+        return Err("Resuming is not supported in salvage mode".to_string());
+    }
+    if checkpoint.stack.is_empty() {
+        return Err(format!(
+            "Checkpoint of {} holds no bundle",
+            checkpoint.root_tx
+        ));
+    }
+
+    let mut bundles = Vec::with_capacity(checkpoint.stack.len());
+    for level in &checkpoint.stack {
+        let mut input = source.open_at(level.start).await?;
+        let bundle = Bundle::parse_stream_with_threshold(
+            &mut input.reader,
+            level.size,
+            options.spill_threshold,
+        )
         .await
-        .map_err(|e| format!("Parse bundle fatal error: {}", e))?;
+        .map_err(|e| {
+            format!(
+                "Failed to read the header of bundle {}: {}",
+                level.bundle_id, e
+            )
+        })?;
+        bundles.push(bundle);
+    }
+
+    tracing::info!(
+        "Resuming bundle {} at offset {}, {} levels deep",
+        checkpoint.root_tx,
+        checkpoint.offset,
+        checkpoint.stack.len()
+    );
+    let input = source.open_at(checkpoint.offset).await?;
+    let mut reader = TrackedReader::starting_at(BufReader::new(input.reader), checkpoint.offset);
+    let mut report = BundleReport::default();
+    let mut progress = Progress::resumed(checkpoint);
+
+    // the innermost bundle first, then the rest of the bundles holding it
+    for (depth, bundle) in bundles.iter_mut().enumerate().rev() {
+        if depth + 1 < checkpoint.stack.len() {
+            let level = progress.level();
+            let (index, entry_start) = (level.entry, level.entry_start);
+            let entry = bundle
+                .entries
+                .get(index)
+                .await
+                .map_err(|e| format!("Failed to read entry {}: {}", index, e))?;
+            check_nested_end(reader.position(), entry_start + entry.size)?;
+            skip_to(&mut reader, entry_start + entry.size).await?;
+            progress.level().entry = index + 1;
+        }
+        walk_entries(
+            &mut reader,
+            &tx,
+            bundle,
+            options,
+            &mut report,
+            &mut progress,
+        )
+        .await?;
+        progress.stack.pop();
+    }
+
+    Ok(report)
+}
+
+// Number of entries following the last good one that salvage mode tries to relocate.
+const SALVAGE_CANDIDATES: u64 = 65536;
+
+#[async_recursion]
+async fn walk_bundle(
+    reader: &mut TrackedReader<impl AsyncRead + Unpin + Send>,
+    tx: &mpsc::Sender<DataItem>,
+    bundled_in: &str,
+    expected_size: Option<u64>,
+    options: &ProcessOptions,
+    report: &mut BundleReport,
+    progress: &mut Progress,
+) -> Result<(), String> {
+    let start = reader.position();
+    let mut bundle =
+        Bundle::parse_stream_with_threshold(reader, expected_size, options.spill_threshold)
+            .await
+            .map_err(|e| format!("Parse bundle fatal error: {}", e))?;
+
+    if let Some(mismatch) = expected_size.and_then(|expected| bundle.size_mismatch(expected)) {
+        tracing::warn!("Bundle {} size mismatch: {}", bundled_in, mismatch);
+        report
+            .size_mismatches
+            .push((bundled_in.to_string(), mismatch));
+    }
 
     tracing::info!(
         "Processing bundle with {} entries, bundled in {}",
@@ -373,42 +861,283 @@ pub async fn process_bundle(
         bundled_in
     );
 
-    for entry in bundle.entries {
-        match DataItem::parse_stream(stream, bundled_in.to_string(), entry.size).await {
-            Ok(data_item) => {
-                if data_item.is_bundle {
-                    tx.send(data_item)
-                        .await
-                        .map_err(|e| format!("Channel send error: {}", e))?;
-                    process_bundle(stream, tx.clone(), &BASE64_URL.encode(entry.id)).await?;
+    progress.stack.push(CheckpointLevel {
+        bundle_id: bundled_in.to_string(),
+        start,
+        size: expected_size,
+        entry: 0,
+        entry_start: reader.position(),
+    });
+    walk_entries(reader, tx, &mut bundle, options, report, progress).await?;
+    progress.stack.pop();
+    Ok(())
+}
+
+// Reads the entries of `bundle` from the current entry of the innermost level of `progress`.
+async fn walk_entries(
+    reader: &mut TrackedReader<impl AsyncRead + Unpin + Send>,
+    tx: &mpsc::Sender<DataItem>,
+    bundle: &mut Bundle,
+    options: &ProcessOptions,
+    report: &mut BundleReport,
+    progress: &mut Progress,
+) -> Result<(), String> {
+    let bundled_in = progress.level().bundle_id.clone();
+    let bundled_in = bundled_in.as_str();
+
+    // In salvage mode an entry only counts as good once its recomputed id matches the header.
+    // A good entry can still have a truncated or overlong payload, which only shows up when
+    // the following entries are read from a wrong offset, so after damage the relocation
+    // restarts right after the start of the last good entry.
+    let mut index = progress.level().entry;
+    let mut last_good_index = index;
+    let mut last_good_start = reader.position();
+
+    while index < bundle.item_count {
+        progress
+            .enter(index, reader.position(), report.items, options)
+            .await?;
+        let entry = bundle
+            .entries
+            .get(index)
+            .await
+            .map_err(|e| format!("Failed to read entry {}: {}", index, e))?;
+        let entry_id = BASE64_URL.encode(entry.id);
+        let entry_start = reader.position();
+        let mut parse_failed = false;
+
+        let damage = match DataItem::parse_stream(reader, bundled_in.to_string(), entry.size).await
+        {
+            Ok(data_item) if options.salvage && data_item.calculate_id() != entry.id => {
+                "data item does not match the header id".to_string()
+            }
+            Ok(mut data_item) => {
+                data_item.root_tx = options.root_tx.clone();
+                data_item.offset = entry_start;
+                data_item.ancestry = progress.ancestry();
+                let is_bundle = data_item.is_bundle;
+                tx.send(data_item)
+                    .await
+                    .map_err(|e| format!("Channel send error: {}", e))?;
+                report.items += 1;
+
+                let payload_end = entry_start + entry.size;
+                let nested = if is_bundle {
+                    let payload_size = payload_end.saturating_sub(reader.position());
+                    walk_bundle(
+                        reader,
+                        tx,
+                        &entry_id,
+                        Some(payload_size),
+                        options,
+                        report,
+                        progress,
+                    )
+                    .await
+                    .and_then(|_| check_nested_end(reader.position(), payload_end))
                 } else {
-                    tx.send(data_item)
-                        .await
-                        .map_err(|e| format!("Channel send error: {}", e))?;
+                    Ok(())
+                };
+
+                // trailing bytes after the last nested entry still belong to this entry
+                let nested = match nested {
+                    Ok(()) => skip_to(reader, payload_end).await,
+                    other => other,
+                };
+
+                match nested {
+                    Ok(()) => {
+                        index += 1;
+                        last_good_index = index;
+                        last_good_start = entry_start;
+                        continue;
+                    }
+                    Err(e) if options.salvage => format!("nested bundle failed: {}", e),
+                    Err(e) => return Err(e),
                 }
             }
+            Err(StreamParseError::FatalError(e)) if options.salvage => {
+                format!("Stream read error: {}", e)
+            }
             Err(StreamParseError::FatalError(e)) => {
                 return Err(format!("Stream read error: {}", e));
             }
+            // a parse error is also what reading from a wrong offset looks like,
+            // so in salvage mode the relocation decides whether the entry was merely invalid
+            Err(StreamParseError::ParseError { message, .. })
+                if options.salvage && index + 1 < bundle.item_count =>
+            {
+                parse_failed = true;
+                format!("Parse error: {}", message)
+            }
             Err(StreamParseError::ParseError {
                 message,
                 bytes_read,
             }) => {
-                let remaining = entry.size - bytes_read;
+                let remaining = entry.size.checked_sub(bytes_read as u64).ok_or_else(|| {
+                    format!(
+                        "Parse error: {}, entry {} is smaller than its header",
+                        message, entry_id
+                    )
+                });
+
                 tracing::warn!(
                     "Parse error: {}, skipping {} bytes for entry {}",
                     message,
-                    remaining,
-                    BASE64_URL.encode(entry.id)
+                    remaining.as_ref().copied().unwrap_or_default(),
+                    entry_id
                 );
-                let mut skip_buf = vec![0u8; remaining];
-                stream
-                    .read_exact(&mut skip_buf)
+
+                let skipped = match remaining {
+                    Ok(remaining) => skip_bytes(reader, remaining).await,
+                    Err(e) => Err(e),
+                };
+
+                match skipped {
+                    Ok(()) => {
+                        report.skipped += 1;
+                        index += 1;
+                        continue;
+                    }
+                    Err(e) if options.salvage => e,
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+
+        // only a bounded part of the entry table is searched for, so huge bundles
+        // do not have to load their whole table for every damaged entry
+        let candidates = bundle
+            .entries
+            .range(last_good_index, SALVAGE_CANDIDATES)
+            .await
+            .map_err(|e| format!("Failed to read entries: {}", e))?;
+        let mut after = last_good_start;
+        let relocated = loop {
+            let found =
+                salvage::relocate(reader, after, &candidates, options.salvage_window as u64)
+                    .await?
+                    .map(|found| found as u64);
+
+            // finding the damaged entry where it was just read from does not help
+            if found == Some(index - last_good_index) && reader.position() == entry_start {
+                after = entry_start;
+                continue;
+            }
+            break found;
+        };
+
+        // the next entry is exactly where the header says, so the stream is still in sync
+        let next_in_place = relocated == Some(index + 1 - last_good_index)
+            && reader.position() == entry_start + entry.size;
+        if parse_failed && next_in_place {
+            tracing::warn!("{}, skipped entry {}", damage, entry_id);
+            report.skipped += 1;
+            index += 1;
+            last_good_index = index;
+            last_good_start = reader.position();
+            continue;
+        }
+
+        tracing::warn!("Damaged entry {}: {}", entry_id, damage);
+
+        let Some(found) = relocated else {
+            let remaining = bundle.item_count - last_good_index;
+            for lost in last_good_index..bundle.item_count {
+                let entry = bundle
+                    .entries
+                    .get(lost)
                     .await
-                    .map_err(|e| format!("Failed to skip bytes: {}", e))?;
+                    .map_err(|e| format!("Failed to read entry {}: {}", lost, e))?;
+                report.lost.push(BASE64_URL.encode(entry.id));
             }
+            tracing::warn!(
+                "Could not relocate any of the {} remaining entries of bundle {}",
+                remaining,
+                bundled_in
+            );
+            break;
+        };
+
+        report.lost.extend(
+            candidates[..found as usize]
+                .iter()
+                .map(|e| BASE64_URL.encode(e.id)),
+        );
+
+        let recovered = BASE64_URL.encode(candidates[found as usize].id);
+        tracing::info!(
+            "Recovered entry {} at stream offset {}",
+            recovered,
+            reader.position()
+        );
+        report.recovered.push(recovered);
+        index = last_good_index + found;
+        last_good_index = index;
+        last_good_start = reader.position();
+    }
+
+    Ok(())
+}
+
+// Fails when a nested bundle was read past the end of the entry holding it.
+fn check_nested_end(position: u64, entry_end: u64) -> Result<(), String> {
+    if position > entry_end {
+        return Err(format!(
+            "nested bundle read {} bytes past its entry",
+            position - entry_end
+        ));
+    }
+    Ok(())
+}
+
+// Skips what is left of the entry ending at `entry_end`.
+async fn skip_to(
+    reader: &mut TrackedReader<impl AsyncRead + Unpin>,
+    entry_end: u64,
+) -> Result<(), String> {
+    match entry_end.checked_sub(reader.position()) {
+        Some(trailing) if trailing > 0 => skip_bytes(reader, trailing).await,
+        _ => Ok(()),
+    }
+}
+
+// Reads and discards everything left in the stream.
+async fn drain(reader: &mut (impl AsyncRead + Unpin)) -> Result<(), String> {
+    let mut drain_buf = vec![0u8; SKIP_BUFFER_SIZE as usize];
+    loop {
+        let read = reader
+            .read(&mut drain_buf)
+            .await
+            .map_err(|e| format!("Failed to read the end of the stream: {}", e))?;
+        if read == 0 {
+            return Ok(());
         }
     }
+}
+
+// Largest buffer used to discard skipped bytes.
+const SKIP_BUFFER_SIZE: u64 = 64 * 1024;
 
+// Reads and discards `count` bytes through a small fixed buffer, so skipping a large payload
+// does not allocate it.
+async fn skip_bytes(reader: &mut (impl AsyncRead + Unpin), count: u64) -> Result<(), String> {
+    let mut skip_buf = vec![0u8; count.min(SKIP_BUFFER_SIZE) as usize];
+    let mut remaining = count;
+    while remaining > 0 {
+        let len = remaining.min(skip_buf.len() as u64) as usize;
+        let read = reader
+            .read(&mut skip_buf[..len])
+            .await
+            .map_err(|e| format!("Failed to skip bytes: {}", e))?;
+        if read == 0 {
+            return Err(format!(
+                "Failed to skip bytes: stream ended after {} of {} bytes",
+                count - remaining,
+                count
+            ));
+        }
+        remaining -= read as u64;
+    }
     Ok(())
 }
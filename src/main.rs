@@ -1,22 +1,371 @@
-use futures_util::TryStreamExt;
-use reqwest::Client;
-use tokio::{io::BufReader, sync::mpsc};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::{io::BufReader, sync::mpsc, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 
 use tracing::Level;
 use tracing_subscriber::FmtSubscriber;
 
-use clap::Parser as ClapParser;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use clap::{Parser as ClapParser, ValueEnum};
+
+use arweave_ans_1040_indexer::{
+    data_item_schema, data_router, gateway_router, process_bundle_with_options, resume_bundle,
+    write_items_with_events, AvroSink, BlockReport, BundleDone, BundleOutcome, BundleReport,
+    BundleSummary, Checkpoint, CheckpointLog, ChunkSource, Compression, DataItem, DataSource,
+    FileSource, FollowEvent, FollowState, Follower, GatewaySource, Indexer, InputSource,
+    ItemFilter, ItemStore, JsonFormat, JsonSink, MessagePackSink, NodeClient, ParquetOptions,
+    ParquetSink, PostgresSink, ProcessOptions, RetryPolicy, RootTransaction, Sink, SqliteSink,
+    StdinSource, TagFilter, WriterEvent, DEFAULT_BATCH_SIZE, DEFAULT_CHECKPOINT_INTERVAL,
+    DEFAULT_CONCURRENCY, DEFAULT_CONFIRMATIONS, DEFAULT_GATEWAY, DEFAULT_PREFETCH,
+    DEFAULT_ROW_GROUP_BYTES, DEFAULT_ROW_GROUP_ROWS,
+};
 
-use tokio::io::AsyncWriteExt;
-use tokio_util::io::StreamReader;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 #[derive(ClapParser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    tx_id: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(required = true)]
+    tx_id: Option<String>,
+
+    /// Read the bundle from a local file instead of the gateway
+    #[arg(long, conflicts_with_all = ["stdin", "nodes"])]
+    file: Option<std::path::PathBuf>,
+
+    /// Read the bundle from the standard input instead of the gateway
+    #[arg(long, conflicts_with = "nodes")]
+    stdin: bool,
+
+    /// Compression of the file or standard input, guessed from the file extension by default
+    #[arg(long, value_enum)]
+    compression: Option<CompressionArg>,
+
+    /// Do not fetch the root transaction header to check its bundle tags before parsing
+    #[arg(long)]
+    no_tx_header: bool,
+
+    /// Save the position of the run to this file while it progresses
+    #[arg(long, conflicts_with = "salvage")]
+    checkpoint: Option<PathBuf>,
+
+    /// Number of bytes read between two checkpoints
+    #[arg(long, default_value_t = DEFAULT_CHECKPOINT_INTERVAL)]
+    checkpoint_interval: u64,
+
+    /// Continue from the saved checkpoint, if there is one, instead of starting over
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    #[command(flatten)]
+    shared: SharedArgs,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Index every bundle mined in a range of blocks
+    IndexBlocks(IndexBlocksArgs),
+    /// Index the bundles of new blocks as they are mined
+    Follow(FollowArgs),
+    /// Index a list of transactions into the same output
+    Batch(BatchArgs),
+    /// Print the JSON Schema of the items written by the JSON formats
+    Schema,
+    /// Search the items of a local redb store
+    Query(QueryArgs),
+    /// Serve the items of a local redb store through the GraphQL API of the gateways
+    Serve(ServeArgs),
+}
+
+#[derive(clap::Args)]
+struct IndexBlocksArgs {
+    /// First block height of the range
+    #[arg(long)]
+    from: u64,
+
+    /// Last block height of the range, included
+    #[arg(long)]
+    to: u64,
 
+    /// Number of bundles processed, and of transaction headers fetched, at the same time
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    #[command(flatten)]
+    shared: SharedArgs,
+}
+
+#[derive(clap::Args)]
+struct FollowArgs {
+    /// Block height to start from when there is no saved state, the chain tip by default
+    #[arg(long)]
+    from: Option<u64>,
+
+    /// File the progress is saved to after every block and resumed from on restart
+    #[arg(long, default_value = "follow-state.json")]
+    state: std::path::PathBuf,
+
+    /// Number of indexed blocks checked for forks, and so the deepest fork that is rolled back
+    #[arg(long, default_value_t = DEFAULT_CONFIRMATIONS)]
+    confirmations: usize,
+
+    /// Seconds between two polls of the chain tip
+    #[arg(long, default_value_t = 60)]
+    poll_interval: u64,
+
+    /// Append a JSON line to this file for every orphaned block and every failed bundle
+    /// processed again, whose earlier items are stale or written again
+    #[arg(long)]
+    events: Option<std::path::PathBuf>,
+
+    /// Number of bundles processed, and of transaction headers fetched, at the same time
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    #[command(flatten)]
+    shared: SharedArgs,
+}
+
+#[derive(clap::Args)]
+struct BatchArgs {
+    /// File listing one transaction id per line, `-` for the standard input
+    ids: std::path::PathBuf,
+
+    /// Number of bundles processed, and of transaction headers fetched, at the same time
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// Write the per transaction summary to this file as JSON
+    #[arg(long)]
+    summary: Option<std::path::PathBuf>,
+
+    #[command(flatten)]
+    shared: SharedArgs,
+}
+
+#[derive(clap::Args)]
+struct QueryArgs {
+    /// Store written with `--format redb`
+    store: PathBuf,
+
+    // base64url ids and addresses may start with a dash
+    /// Id of the item, repeat to match any of them
+    #[arg(long = "id", allow_hyphen_values = true)]
+    ids: Vec<String>,
+
+    /// Owner address of the items, repeat to match any of them
+    #[arg(long = "owner", allow_hyphen_values = true)]
+    owners: Vec<String>,
+
+    /// Target of the items, repeat to match any of them
+    #[arg(long = "target", allow_hyphen_values = true)]
+    targets: Vec<String>,
+
+    /// Tag of the items as NAME=VALUE, repeat to match all the tag names and any value of each
+    #[arg(long = "tag", value_parser = parse_tag)]
+    tags: Vec<(String, String)>,
+
+    /// Match the items having any of the tags instead of all of them
+    #[arg(long)]
+    any_tag: bool,
+
+    /// Id of the bundle directly holding the items, repeat to match any of them
+    #[arg(long = "bundled-in", allow_hyphen_values = true)]
+    bundled_in: Vec<String>,
+
+    /// Id of the root transaction of the items, repeat to match any of them
+    #[arg(long = "root-tx", allow_hyphen_values = true)]
+    root_txs: Vec<String>,
+
+    /// Maximum number of items found
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Write the items found to this output instead of printing them
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Format the items are written or printed in, only JSON ones can be printed
+    #[arg(long, value_enum, default_value_t = FormatArg::Ndjson)]
+    format: FormatArg,
+}
+
+impl QueryArgs {
+    fn filter(&self) -> ItemFilter {
+        let mut tags: Vec<TagFilter> = Vec::new();
+        for (name, value) in &self.tags {
+            match tags.iter_mut().find(|tag| tag.name == *name) {
+                Some(tag) => tag.values.push(value.clone()),
+                None => tags.push(TagFilter {
+                    name: name.clone(),
+                    values: vec![value.clone()],
+                }),
+            }
+        }
+        ItemFilter {
+            ids: self.ids.clone(),
+            owners: self.owners.clone(),
+            targets: self.targets.clone(),
+            tags,
+            any_tag: self.any_tag,
+            bundled_in: self.bundled_in.clone(),
+            root_txs: self.root_txs.clone(),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Store written with `--format redb`
+    store: PathBuf,
+
+    /// Address the server listens on
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    listen: std::net::SocketAddr,
+
+    /// Also serve the data of the items on `/{item_id}`, read from the bundles of this directory
+    #[arg(long)]
+    bundles: Option<PathBuf>,
+}
+
+fn parse_tag(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("{} is not a NAME=VALUE tag", arg))
+}
+
+#[derive(clap::Args)]
+struct SharedArgs {
     #[arg(short, long, default_value = "bundle")]
     output: std::path::PathBuf,
+
+    /// Format the items are written in
+    #[arg(long, value_enum, default_value_t = FormatArg::Ndjson)]
+    format: FormatArg,
+
+    /// Maximum number of items in a Parquet row group
+    #[arg(long, default_value_t = DEFAULT_ROW_GROUP_ROWS)]
+    row_group_rows: usize,
+
+    /// Size in bytes of the buffered items after which a Parquet row group is written
+    #[arg(long, default_value_t = DEFAULT_ROW_GROUP_BYTES)]
+    row_group_bytes: usize,
+
+    /// Gateway the transaction data is fetched from, repeat to fail over across several gateways
+    #[arg(long = "gateway", default_value = DEFAULT_GATEWAY)]
+    gateways: Vec<String>,
+
+    /// Consecutive failed requests tolerated before giving up on the gateways
+    #[arg(long, default_value_t = RetryPolicy::default().max_retries)]
+    retries: u32,
+
+    /// Download verified chunks from this Arweave node instead of the gateway, repeat to fail over
+    #[arg(long = "node")]
+    nodes: Vec<String>,
+
+    /// Number of chunks downloaded ahead of the parser when reading from nodes
+    #[arg(long, default_value_t = DEFAULT_PREFETCH)]
+    prefetch: usize,
+
+    /// Only warn when the bytes read do not hash to the data_root of the root transaction
+    #[arg(long)]
+    allow_data_root_mismatch: bool,
+
+    /// Try to recover the following entries when an entry is damaged instead of aborting
+    #[arg(long)]
+    salvage: bool,
+
+    /// Size in bytes of the buffer the response is read through
+    #[arg(long, default_value_t = 1024 * 1024)]
+    read_buffer_size: usize,
+}
+
+impl SharedArgs {
+    fn retry(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.retries,
+            ..RetryPolicy::default()
+        }
+    }
+
+    // Urls of the nodes transaction headers and blocks are fetched from.
+    fn node_urls(&self) -> &[String] {
+        if self.nodes.is_empty() {
+            &self.gateways
+        } else {
+            &self.nodes
+        }
+    }
+
+    fn indexer(&self, concurrency: usize) -> Indexer {
+        let retry = self.retry();
+        Indexer::new(
+            NodeClient::new(self.node_urls()).with_retry(retry.clone()),
+            self.data_source(),
+        )
+        .with_retry(retry)
+        .with_concurrency(concurrency)
+        .with_read_buffer_size(self.read_buffer_size)
+        .with_options(ProcessOptions {
+            salvage: self.salvage,
+            ..ProcessOptions::default()
+        })
+    }
+
+    fn data_source(&self) -> DataSource {
+        if self.nodes.is_empty() {
+            DataSource::Gateways(self.gateways.clone())
+        } else {
+            DataSource::Nodes {
+                urls: self.nodes.clone(),
+                prefetch: self.prefetch,
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    /// Newline-delimited JSON, one object per line
+    Ndjson,
+    /// A JSON array of objects
+    Json,
+    /// Apache Parquet, one row per item with the tags as a nested list
+    Parquet,
+    /// An Avro object container file keeping the raw bytes of the items
+    Avro,
+    /// MessagePack maps keeping the raw bytes of the items, one after the other
+    Msgpack,
+    /// A SQLite database of items, tags and bundles, updated in place
+    Sqlite,
+    /// A PostgreSQL database, whose connection string is given as the output
+    Postgres,
+    /// An embedded redb key-value store of the items, indexed by owner, target and tag
+    Redb,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressionArg {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl From<CompressionArg> for Compression {
+    fn from(arg: CompressionArg) -> Self {
+        match arg {
+            CompressionArg::None => Compression::None,
+            CompressionArg::Gzip => Compression::Gzip,
+            CompressionArg::Zstd => Compression::Zstd,
+        }
+    }
 }
 
 #[tokio::main]
@@ -29,67 +378,800 @@ async fn main() {
         .expect("Failed to set global default subscriber");
 
     let args = Args::parse();
-    let file = match tokio::fs::File::create(&args.output).await {
-        Ok(file) => file,
+    match args.command {
+        Some(Command::IndexBlocks(ref command)) => index_blocks(command).await,
+        Some(Command::Follow(ref command)) => follow(command).await,
+        Some(Command::Batch(ref command)) => batch(command).await,
+        Some(Command::Query(ref command)) => query(command).await,
+        Some(Command::Serve(ref command)) => serve(command).await,
+        Some(Command::Schema) => {
+            let schema = serde_json::to_string_pretty(&data_item_schema())
+                .expect("Failed to serialize the schema");
+            println!("{}", schema);
+        }
+        None => index_transaction(&args).await,
+    }
+}
+
+async fn index_transaction(args: &Args) {
+    let shared = &args.shared;
+    let tx_id = args.tx_id.as_deref().unwrap_or_default();
+
+    let resume_from = match &args.checkpoint {
+        Some(path) if args.resume => match Checkpoint::load(path).await {
+            Ok(Some(checkpoint)) if checkpoint.root_tx == tx_id => Some(checkpoint),
+            Ok(Some(checkpoint)) => {
+                tracing::error!(
+                    "{} holds a checkpoint of {}, not of {}",
+                    path.display(),
+                    checkpoint.root_tx,
+                    tx_id
+                );
+                return;
+            }
+            Ok(None) => {
+                tracing::info!("No checkpoint in {}, starting over", path.display());
+                None
+            }
+            Err(e) => {
+                tracing::error!("Failed to load the checkpoint: {}", e);
+                return;
+            }
+        },
+        _ => None,
+    };
+
+    let (checkpoints, log) = match &args.checkpoint {
+        Some(path) => {
+            let (checkpoints, rx) = mpsc::channel(16);
+            let log = CheckpointLog {
+                checkpoints: rx,
+                path: path.clone(),
+                items: resume_from.as_ref().map_or(0, |c| c.items_emitted),
+            };
+            (Some(checkpoints), Some(log))
+        }
+        None => (None, None),
+    };
+    // checked before the output is opened, so that a wrong id leaves it untouched. Local inputs
+    // are indexed offline, without the root transaction header
+    let online = args.file.is_none() && !args.stdin;
+    let (root_tx, data_root) = if online && !args.no_tx_header {
+        let client = NodeClient::new(shared.node_urls()).with_retry(shared.retry());
+        match fetch_root_tx(&client, tx_id).await {
+            Ok((root_tx, data_root)) => (Some(root_tx), data_root),
+            Err(e) => {
+                tracing::error!("Failed to check the root transaction: {}", e);
+                return;
+            }
+        }
+    } else {
+        (None, None)
+    };
+
+    // items written after the checkpoint are dropped, they are sent again
+    let mode = match &resume_from {
+        Some(Checkpoint {
+            output_len: Some(len),
+            ..
+        }) => WriteMode::Resume(*len),
+        Some(_) => WriteMode::Append,
+        None => WriteMode::Create,
+    };
+    let Some(Writer {
+        tx,
+        events,
+        handle: write_handle,
+    }) = start_writer_with(shared, mode, log).await
+    else {
+        return;
+    };
+
+    tracing::info!("Starting processing for transaction ID: {}", tx_id);
+
+    let options = ProcessOptions {
+        salvage: shared.salvage,
+        root_tx: root_tx.clone().map(Arc::new),
+        checkpoints,
+        checkpoint_interval: args.checkpoint_interval,
+        ..ProcessOptions::default()
+    };
+    let started = Instant::now();
+    let processed = options.root_tx.clone();
+    let result = match &resume_from {
+        Some(checkpoint) => resume(args, tx.clone(), checkpoint, &options).await,
+        None => {
+            let options = ProcessOptions {
+                data_root,
+                ..options
+            };
+            process(args, tx.clone(), root_tx.as_ref(), options).await
+        }
+    };
+    // sent before `tx` is dropped, so that the writer records it
+    let outcome = BundleOutcome {
+        tx_id: tx_id.to_string(),
+        result,
+        duration: started.elapsed(),
+    };
+    let done = BundleDone {
+        root_tx: processed,
+        summary: outcome.summary(),
+    };
+    if events.send(WriterEvent::BundleDone(done)).await.is_err() {
+        tracing::warn!("Writer closed, the outcome of {} is not recorded", tx_id);
+    }
+    drop(tx);
+    let result = outcome.result;
+    let ok = match result {
+        Ok(report) => log_report(tx_id, &report, shared.allow_data_root_mismatch),
         Err(e) => {
-            tracing::error!("Failed to open output file: {}", e);
-            return;
+            tracing::error!("Processing failed: {}", e);
+            false
         }
     };
 
-    let (tx, rx) = mpsc::channel(128);
+    let written = finish_writer(write_handle).await;
+    // a complete run leaves nothing to resume
+    if let (true, true, Some(path)) = (ok, written, &args.checkpoint) {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            tracing::warn!("Failed to remove the checkpoint {}: {}", path.display(), e);
+        }
+    }
+}
 
-    let write_handle = tokio::spawn(write_task(rx, file));
+// Reads the bundle of `args` from its start.
+async fn process(
+    args: &Args,
+    tx: mpsc::Sender<DataItem>,
+    root_tx: Option<&RootTransaction>,
+    options: ProcessOptions,
+) -> Result<BundleReport, String> {
+    let shared = &args.shared;
+    let tx_id = args.tx_id.as_deref().unwrap_or_default();
+    let compression = args.compression.map(Compression::from);
 
-    tracing::info!("Starting processing for transaction ID: {}", args.tx_id);
+    let input = match (&args.file, args.stdin) {
+        (Some(path), _) => {
+            let compression = compression.unwrap_or_else(|| Compression::from_path(path));
+            FileSource::new(path, compression).open().await
+        }
+        (None, true) => {
+            StdinSource::new(compression.unwrap_or(Compression::None))
+                .open()
+                .await
+        }
+        (None, false) => shared.data_source().open(tx_id, &shared.retry()).await,
+    };
+    let input = input.map_err(|e| format!("Failed to open input: {}", e))?;
 
-    let client = Client::new();
-    let response = match client
-        .get(format!("https://arweave.net/{}", args.tx_id))
-        .send()
-        .await
-    {
-        Ok(response) => response,
+    let mut buffered = BufReader::with_capacity(shared.read_buffer_size, input.reader);
+
+    let mut expected_size = input.size;
+    if let Some(root_tx) = root_tx {
+        tracing::info!(
+            "Root transaction owned by {}, {} bytes, block {:?}",
+            root_tx.owner_address,
+            root_tx.data_size,
+            root_tx.block_height
+        );
+        if input.size.is_some_and(|size| size != root_tx.data_size) {
+            tracing::warn!(
+                "Input holds {:?} bytes, the transaction data_size is {}",
+                input.size,
+                root_tx.data_size
+            );
+        }
+        expected_size = Some(root_tx.data_size);
+    }
+
+    let options = ProcessOptions {
+        expected_size,
+        ..options
+    };
+    process_bundle_with_options(&mut buffered, tx, tx_id, &options).await
+}
+
+// Continues reading the bundle of `args` from `checkpoint`.
+async fn resume(
+    args: &Args,
+    tx: mpsc::Sender<DataItem>,
+    checkpoint: &Checkpoint,
+    options: &ProcessOptions,
+) -> Result<BundleReport, String> {
+    let shared = &args.shared;
+    let tx_id = args.tx_id.as_deref().unwrap_or_default();
+    let compression = args.compression.map(Compression::from);
+
+    match (&args.file, args.stdin) {
+        (Some(path), _) => {
+            let compression = compression.unwrap_or_else(|| Compression::from_path(path));
+            let source = FileSource::new(path, compression);
+            resume_bundle(&source, tx, checkpoint, options).await
+        }
+        (None, true) => Err("The standard input cannot be resumed".to_string()),
+        (None, false) if !shared.nodes.is_empty() => {
+            let source = ChunkSource::new(&shared.nodes, tx_id)
+                .with_prefetch(shared.prefetch)
+                .with_retry(shared.retry());
+            resume_bundle(&source, tx, checkpoint, options).await
+        }
+        (None, false) => {
+            let source =
+                GatewaySource::with_gateways(&shared.gateways, tx_id).with_retry(shared.retry());
+            resume_bundle(&source, tx, checkpoint, options).await
+        }
+    }
+}
+
+async fn index_blocks(args: &IndexBlocksArgs) {
+    let shared = &args.shared;
+    let Some(Writer {
+        tx,
+        events,
+        handle: write_handle,
+    }) = start_writer(shared).await
+    else {
+        return;
+    };
+
+    tracing::info!("Indexing blocks {} to {}", args.from, args.to);
+
+    let indexer = shared.indexer(args.concurrency).with_events(events);
+    let reports = indexer.index_range(args.from, args.to, tx).await;
+
+    let (mut failed, mut failed_blocks) = (0, 0);
+    for (height, report) in (args.from..).zip(&reports) {
+        match report {
+            Ok(block) => failed += log_block(block, shared.allow_data_root_mismatch),
+            Err(e) => {
+                tracing::error!("Block {} failed: {}", height, e);
+                failed_blocks += 1;
+            }
+        }
+    }
+    if failed_blocks > 0 {
+        tracing::error!("{} blocks failed", failed_blocks);
+    }
+    if failed > 0 {
+        tracing::error!("{} bundles failed", failed);
+    }
+
+    finish_writer(write_handle).await;
+}
+
+async fn follow(args: &FollowArgs) {
+    let shared = &args.shared;
+    let indexer = shared.indexer(args.concurrency);
+
+    let state = match FollowState::load(&args.state).await {
+        Ok(Some(state)) => {
+            tracing::info!(
+                "Resuming from block {} saved in {}",
+                state.next_height,
+                args.state.display()
+            );
+            state
+        }
+        Ok(None) => match args.from {
+            Some(height) => FollowState::starting_at(height),
+            None => match indexer.client().info().await {
+                Ok(info) => FollowState::starting_at(info.height),
+                Err(e) => {
+                    tracing::error!("Failed to fetch the chain tip: {}", e);
+                    return;
+                }
+            },
+        },
         Err(e) => {
-            tracing::error!("Failed to fetch transaction: {}", e);
+            tracing::error!("Failed to load the state: {}", e);
             return;
         }
     };
 
-    let response_bytes = response.bytes_stream().map_err(|e| {
-        tracing::error!("Failed to fetch transaction: {}", e);
-        std::io::Error::new(std::io::ErrorKind::Other, "Failed to fetch transaction")
+    // items of earlier runs are kept, the output grows across restarts
+    let Some(Writer {
+        tx,
+        events,
+        handle: write_handle,
+    }) = start_writer_with(shared, WriteMode::Append, None).await
+    else {
+        return;
+    };
+
+    tracing::info!("Following the chain from block {}", state.next_height);
+    // the block being indexed is finished on Ctrl-C, so that it is neither lost nor written twice
+    let cancel = CancellationToken::new();
+    let interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            tracing::info!("Interrupted, finishing the current block, Ctrl-C again to abort");
+            interrupt.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
     });
+    let mut follower = Follower::new(indexer.with_events(events), state)
+        .with_confirmations(args.confirmations)
+        .with_state_file(&args.state)
+        .with_cancellation(cancel.clone());
+    let poll_interval = std::time::Duration::from_secs(args.poll_interval);
 
-    let stream = StreamReader::new(response_bytes);
-    let mut buffered = BufReader::with_capacity(512 * 1024 * 1024, stream);
+    while !cancel.is_cancelled() {
+        match follower.step(tx.clone()).await {
+            Ok(step) => {
+                for block in &step.orphaned {
+                    tracing::warn!(
+                        "Block {} at height {} was orphaned, with bundles {:?}",
+                        block.hash,
+                        block.height,
+                        block.bundles
+                    );
+                }
+                if let Some(path) = &args.events {
+                    if let Err(e) = append_events(path, &step.events()).await {
+                        tracing::error!("Failed to write the events to {}: {}", path.display(), e);
+                    }
+                }
+                for bundle in &step.retried {
+                    match &bundle.result {
+                        Ok(report) => {
+                            log_report(&bundle.tx_id, report, shared.allow_data_root_mismatch);
+                        }
+                        Err(e) => tracing::error!("Bundle {} failed again: {}", bundle.tx_id, e),
+                    }
+                }
+                let failed: usize = step
+                    .blocks
+                    .iter()
+                    .map(|block| log_block(block, shared.allow_data_root_mismatch))
+                    .sum();
+                if failed > 0 {
+                    tracing::error!("{} bundles failed", failed);
+                }
+            }
+            Err(e) => tracing::error!("Following failed: {}, retrying in {:?}", e, poll_interval),
+        }
 
-    match arweave_ans_1040_indexer::process_bundle(&mut buffered, tx, &args.tx_id).await {
-        Ok(_) => tracing::info!("Processing complete"),
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(poll_interval) => {}
+        }
+    }
+
+    tracing::info!("Stopped at block {}", follower.state().next_height);
+    drop(tx);
+    finish_writer(write_handle).await;
+}
+
+async fn batch(args: &BatchArgs) {
+    let shared = &args.shared;
+    let ids = match read_ids(&args.ids).await {
+        Ok(ids) => ids,
         Err(e) => {
-            tracing::error!("Processing failed: {}", e);
+            tracing::error!("Failed to read the transaction ids: {}", e);
+            return;
+        }
+    };
+    let Some(Writer {
+        tx,
+        events,
+        handle: write_handle,
+    }) = start_writer(shared).await
+    else {
+        return;
+    };
+
+    tracing::info!("Indexing {} transactions", ids.len());
+    let outcomes = shared
+        .indexer(args.concurrency)
+        .with_events(events)
+        .index_ids(&ids, tx)
+        .await;
+
+    let summaries: Vec<BundleSummary> = outcomes.iter().map(BundleOutcome::summary).collect();
+    let mut failed = 0;
+    for (outcome, summary) in outcomes.iter().zip(&summaries) {
+        tracing::info!(
+            "{}: {} items, {} skipped, {} errors, {:.1}s",
+            summary.tx_id,
+            summary.items,
+            summary.skipped,
+            summary.errors.len(),
+            summary.duration_secs
+        );
+        let ok = match &outcome.result {
+            Ok(report) => log_report(&outcome.tx_id, report, shared.allow_data_root_mismatch),
+            Err(e) => {
+                tracing::error!("Bundle {} failed: {}", outcome.tx_id, e);
+                false
+            }
+        };
+        if !ok {
+            failed += 1;
+        }
+    }
+    tracing::info!(
+        "Batch complete: {} of {} transactions indexed",
+        ids.len() - failed,
+        ids.len()
+    );
+
+    if let Some(path) = &args.summary {
+        let written = match serde_json::to_vec_pretty(&summaries) {
+            Ok(json) => tokio::fs::write(path, json)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = written {
+            tracing::error!("Failed to write the summary: {}", e);
+        }
+    }
+
+    finish_writer(write_handle).await;
+}
+
+// Number of items read from the store at once by `query`.
+const QUERY_PAGE: usize = 1024;
+
+async fn query(args: &QueryArgs) {
+    let store = match ItemStore::open(&args.store) {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::error!("Failed to open the store: {}", e);
+            return;
+        }
+    };
+    // without an output the items are printed, and so must not be mixed with the logs
+    let writer = match &args.output {
+        Some(output) => {
+            let options = ParquetOptions::default();
+            let output = output.clone();
+            let Some(writer) =
+                open_writer(args.format, output, &options, WriteMode::Create, None).await
+            else {
+                return;
+            };
+            Some(writer)
+        }
+        None if matches!(args.format, FormatArg::Ndjson | FormatArg::Json) => None,
+        None => {
+            tracing::error!("Only the JSON formats can be printed, give an output with -o");
+            return;
+        }
+    };
+    let array = writer.is_none() && matches!(args.format, FormatArg::Json);
+
+    let filter = Arc::new(args.filter());
+    let mut remaining = args.limit.unwrap_or(usize::MAX);
+    let mut after: Option<String> = None;
+    let mut printed = 0;
+    if array {
+        print!("[");
+    }
+    while remaining > 0 {
+        let page = {
+            let (store, filter, after) = (store.clone(), filter.clone(), after.clone());
+            let limit = remaining.min(QUERY_PAGE);
+            tokio::task::spawn_blocking(move || store.query(&filter, after.as_deref(), limit))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|page| page)
+        };
+        let items = match page {
+            Ok(items) => items,
+            Err(e) => {
+                tracing::error!("Query failed: {}", e);
+                break;
+            }
+        };
+        let Some(last) = items.last() else {
+            break;
+        };
+        remaining -= items.len();
+        after = Some(BASE64_URL.encode(last.id()));
+        for item in items {
+            match &writer {
+                Some(Writer { tx, .. }) => {
+                    if tx.send(item).await.is_err() {
+                        // the write task failed, its error is logged by finish_writer
+                        remaining = 0;
+                        break;
+                    }
+                }
+                None => {
+                    let json = serde_json::to_string(&item).expect("Failed to serialize an item");
+                    if array {
+                        print!("{}\n{}", if printed == 0 { "" } else { "," }, json);
+                    } else {
+                        println!("{}", json);
+                    }
+                    printed += 1;
+                }
+            }
+        }
+    }
+    if array {
+        println!("{}]", if printed == 0 { "" } else { "\n" });
+    }
+    if let Some(writer) = writer {
+        drop(writer.tx);
+        finish_writer(writer.handle).await;
+    }
+}
+
+async fn serve(args: &ServeArgs) {
+    let store = match ItemStore::open(&args.store) {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::error!("Failed to open the store: {}", e);
+            return;
+        }
+    };
+    let listener = match tokio::net::TcpListener::bind(args.listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("Failed to listen on {}: {}", args.listen, e);
             return;
         }
+    };
+    tracing::info!("Serving GraphQL on http://{}/graphql", args.listen);
+    let mut router = gateway_router(store.clone());
+    if let Some(dir) = &args.bundles {
+        tracing::info!(
+            "Serving the data of the bundles of {} on http://{}/{{item_id}}",
+            dir.display(),
+            args.listen
+        );
+        router = router.merge(data_router(store, dir));
+    }
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    if let Err(e) = axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown)
+        .await
+    {
+        tracing::error!("Server failed: {}", e);
     }
+}
 
-    match write_handle.await {
-        Ok(_) => tracing::info!("Write task complete"),
-        Err(e) => tracing::error!("Write task failed: {}", e),
+// Appends one JSON line per event to `path`.
+async fn append_events(path: &std::path::Path, events: &[FollowEvent]) -> std::io::Result<()> {
+    if events.is_empty() {
+        return Ok(());
     }
+    let mut lines = Vec::new();
+    for event in events {
+        serde_json::to_writer(&mut lines, event)?;
+        lines.push(b'\n');
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&lines).await?;
+    file.sync_all().await
 }
 
-// the output format is not optimized for performance
-// the goal was simplicity and readability
-// the performance can be vastly improved if the intended use case is for machine to machine communication
-// in this case we would choose one of the more efficient binary serialization formats (such as Protocol Buffers, Apache Avro, or MessagePack, BSON, ...)
-async fn write_task(
-    mut rx: mpsc::Receiver<arweave_ans_1040_indexer::DataItem>,
-    mut file: tokio::fs::File,
-) -> std::io::Result<()> {
-    while let Some(item) = rx.recv().await {
-        file.write_all(serde_json::to_string_pretty(&item)?.as_bytes())
-            .await?;
-        file.write_all(b"\n").await?;
+// Reads one transaction id per line, skipping blank lines and `#` comments.
+async fn read_ids(path: &std::path::Path) -> std::io::Result<Vec<String>> {
+    let text = if path == std::path::Path::new("-") {
+        let mut text = String::new();
+        tokio::io::stdin().read_to_string(&mut text).await?;
+        text
+    } else {
+        tokio::fs::read_to_string(path).await?
+    };
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+// Logs the bundles of an indexed block, returns the number of bundles that failed.
+fn log_block(block: &BlockReport, allow_data_root_mismatch: bool) -> usize {
+    tracing::info!(
+        "Block {}: {} transactions, {} bundles",
+        block.height,
+        block.transactions,
+        block.bundles.len()
+    );
+    let mut failed = 0;
+    for bundle in &block.bundles {
+        let ok = match &bundle.result {
+            Ok(report) => log_report(&bundle.tx_id, report, allow_data_root_mismatch),
+            Err(e) => {
+                tracing::error!("Bundle {} failed: {}", bundle.tx_id, e);
+                false
+            }
+        };
+        if !ok {
+            failed += 1;
+        }
     }
-    Ok(())
+    failed
+}
+
+// Channels to the task writing the items, and its handle.
+struct Writer {
+    tx: mpsc::Sender<DataItem>,
+    events: mpsc::Sender<WriterEvent>,
+    handle: JoinHandle<Result<u64, String>>,
+}
+
+async fn start_writer(shared: &SharedArgs) -> Option<Writer> {
+    start_writer_with(shared, WriteMode::Create, None).await
+}
+
+// Waits for the write task, returns false when it failed.
+async fn finish_writer(handle: JoinHandle<Result<u64, String>>) -> bool {
+    match handle.await {
+        Ok(Ok(items)) => {
+            tracing::info!("Write task complete, {} items written", items);
+            true
+        }
+        Ok(Err(e)) => {
+            tracing::error!("Write task failed: {}", e);
+            false
+        }
+        Err(e) => {
+            tracing::error!("Write task failed: {}", e);
+            false
+        }
+    }
+}
+
+// How the output is opened when it already exists.
+enum WriteMode {
+    Create,
+    Append,
+    // cut back to the length recorded by a checkpoint
+    Resume(u64),
+}
+
+// Opens the sink selected by `--format` and spawns the task writing the items sent to the
+// returned channel.
+async fn start_writer_with(
+    shared: &SharedArgs,
+    mode: WriteMode,
+    log: Option<CheckpointLog>,
+) -> Option<Writer> {
+    let options = ParquetOptions {
+        row_group_rows: shared.row_group_rows,
+        row_group_bytes: shared.row_group_bytes,
+    };
+    open_writer(shared.format, shared.output.clone(), &options, mode, log).await
+}
+
+async fn open_writer(
+    format: FormatArg,
+    output: PathBuf,
+    parquet: &ParquetOptions,
+    mode: WriteMode,
+    log: Option<CheckpointLog>,
+) -> Option<Writer> {
+    let (tx, rx) = mpsc::channel(128);
+    let (events, events_rx) = mpsc::channel(128);
+    let rx = (rx, events_rx);
+    let handle = match (format, mode) {
+        (FormatArg::Ndjson | FormatArg::Json, mode) => {
+            let format = match format {
+                FormatArg::Json => JsonFormat::Array,
+                _ => JsonFormat::Lines,
+            };
+            let sink = match mode {
+                WriteMode::Create => JsonSink::create(output, format).await,
+                WriteMode::Append => JsonSink::append(output, format).await,
+                WriteMode::Resume(len) => JsonSink::resume(output, format, len).await,
+            };
+            spawn_writer(sink, rx, log)
+        }
+        (FormatArg::Avro, mode) => {
+            let sink = match mode {
+                WriteMode::Create => AvroSink::create(output).await,
+                WriteMode::Append => AvroSink::append(output).await,
+                WriteMode::Resume(len) => AvroSink::resume(output, len).await,
+            };
+            spawn_writer(sink, rx, log)
+        }
+        (FormatArg::Msgpack, mode) => {
+            let sink = match mode {
+                WriteMode::Create => MessagePackSink::create(output).await,
+                WriteMode::Append => MessagePackSink::append(output).await,
+                WriteMode::Resume(len) => MessagePackSink::resume(output, len).await,
+            };
+            spawn_writer(sink, rx, log)
+        }
+        // items are upserted by id, so databases are continued whatever the mode
+        (FormatArg::Sqlite, _) => spawn_writer(SqliteSink::open(output).await, rx, log),
+        (FormatArg::Redb, _) => spawn_writer(ItemStore::open(output), rx, log),
+        (FormatArg::Postgres, _) => {
+            let sink = PostgresSink::connect(&output.to_string_lossy()).await;
+            spawn_writer(sink, rx, log)
+        }
+        (FormatArg::Parquet, WriteMode::Create) if log.is_none() => {
+            spawn_writer(ParquetSink::create(output, parquet).await, rx, log)
+        }
+        (FormatArg::Parquet, _) => {
+            Err("Parquet outputs cannot be appended to, checkpointed or resumed".to_string())
+        }
+    };
+    match handle {
+        Ok(handle) => Some(Writer { tx, events, handle }),
+        Err(e) => {
+            tracing::error!("Failed to open the output: {}", e);
+            None
+        }
+    }
+}
+
+fn spawn_writer<S: Sink + 'static>(
+    sink: Result<S, String>,
+    (rx, events): (mpsc::Receiver<DataItem>, mpsc::Receiver<WriterEvent>),
+    log: Option<CheckpointLog>,
+) -> Result<JoinHandle<Result<u64, String>>, String> {
+    sink.map(|sink| {
+        let written = write_items_with_events(rx, sink, DEFAULT_BATCH_SIZE, log, Some(events));
+        tokio::spawn(written)
+    })
+}
+
+// Logs the outcome of a processed bundle, returns false when the bundle failed verification.
+fn log_report(tx_id: &str, report: &BundleReport, allow_data_root_mismatch: bool) -> bool {
+    tracing::info!(
+        "Processing of {} complete: {} items, {} skipped",
+        tx_id,
+        report.items,
+        report.skipped
+    );
+    for (bundle, mismatch) in &report.size_mismatches {
+        tracing::warn!("Bundle {} size mismatch: {}", bundle, mismatch);
+    }
+    if !report.recovered.is_empty() || !report.lost.is_empty() {
+        tracing::warn!(
+            "Salvage recovered entries {:?}, lost entries {:?}",
+            report.recovered,
+            report.lost
+        );
+    }
+    match &report.data_root {
+        Some(check) if check.is_verified() => tracing::info!("{}", check),
+        Some(check) if allow_data_root_mismatch => tracing::warn!("{}", check),
+        Some(check) => {
+            tracing::error!("Processing of {} failed: {}", tx_id, check);
+            return false;
+        }
+        None => {}
+    }
+    true
+}
+
+// Fetches the header and status of the root transaction and checks that it holds a bundle.
+// Returns the `data_root` of the header along with the transaction.
+async fn fetch_root_tx(
+    client: &NodeClient,
+    tx_id: &str,
+) -> Result<(RootTransaction, Option<[u8; 32]>), String> {
+    let header = client.transaction(tx_id).await?;
+    if !header.is_bundle() {
+        return Err(format!(
+            "Transaction {} is not tagged as an ANS-104 binary bundle",
+            tx_id
+        ));
+    }
+
+    let status = client.transaction_status(tx_id).await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to fetch the status of {}: {}", tx_id, e);
+        None
+    });
+    Ok((
+        RootTransaction::new(&header, status.as_ref()),
+        header.data_root,
+    ))
 }
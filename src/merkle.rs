@@ -0,0 +1,507 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::utils::U256;
+
+/// Largest chunk of the standard Arweave chunking.
+pub const MAX_CHUNK_SIZE: u64 = 256 * 1024;
+/// Smallest chunk the standard chunking leaves at the end of the data.
+pub const MIN_CHUNK_SIZE: u64 = 32 * 1024;
+
+const HASH_SIZE: usize = 32;
+const NOTE_SIZE: usize = 32;
+
+/// Byte ranges of the chunks the data of `size` bytes is split into by Arweave clients.
+///
+/// As in the reference implementation, data whose size is a multiple of `MAX_CHUNK_SIZE` ends
+/// with an empty chunk. It is part of the `data_root` but is never uploaded.
+pub fn chunk_layout(size: u64) -> ChunkLayout {
+    ChunkLayout {
+        size,
+        cursor: 0,
+        done: false,
+    }
+}
+
+/// Iterator returned by `chunk_layout`.
+#[derive(Debug, Clone)]
+pub struct ChunkLayout {
+    size: u64,
+    cursor: u64,
+    done: bool,
+}
+
+impl Iterator for ChunkLayout {
+    type Item = std::ops::Range<u64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let rest = self.size - self.cursor;
+        let chunk = if rest >= MAX_CHUNK_SIZE {
+            let next = rest - MAX_CHUNK_SIZE;
+            if next > 0 && next < MIN_CHUNK_SIZE {
+                rest.div_ceil(2)
+            } else {
+                MAX_CHUNK_SIZE
+            }
+        } else {
+            self.done = true;
+            rest
+        };
+        let range = self.cursor..self.cursor + chunk;
+        self.cursor += chunk;
+        Some(range)
+    }
+}
+
+/// Chunk bounds proven by a data path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidatedChunk {
+    /// Offset of the first byte of the chunk in the transaction data.
+    pub start: u64,
+    /// Offset right after the last byte of the chunk.
+    pub end: u64,
+    /// SHA-256 of the chunk bytes.
+    pub data_hash: [u8; 32],
+}
+
+/// Checks that `data_path` proves a chunk holding the byte at `offset` of the data
+/// committed to by `data_root`.
+pub fn validate_path(
+    data_root: &[u8; 32],
+    offset: u64,
+    data_size: u64,
+    data_path: &[u8],
+) -> Result<ValidatedChunk, String> {
+    if data_size == 0 {
+        return Err("Cannot prove a chunk of empty data".to_string());
+    }
+
+    let dest = offset.min(data_size - 1);
+    let mut id = *data_root;
+    let mut start = 0;
+    let mut end = data_size;
+    let mut path = data_path;
+
+    loop {
+        if path.len() == HASH_SIZE + NOTE_SIZE {
+            let (data_hash, note) = path.split_at(HASH_SIZE);
+            if hash_all(&[data_hash, note]) != id {
+                return Err("Chunk proof leaf does not match its parent".to_string());
+            }
+            return Ok(ValidatedChunk {
+                start,
+                end,
+                data_hash: data_hash.try_into().expect("hash size"),
+            });
+        }
+
+        if path.len() < 2 * HASH_SIZE + NOTE_SIZE {
+            return Err(format!(
+                "Chunk proof has an invalid length {}",
+                data_path.len()
+            ));
+        }
+
+        let (left, rest) = path.split_at(HASH_SIZE);
+        let (right, rest) = rest.split_at(HASH_SIZE);
+        let (note, rest) = rest.split_at(NOTE_SIZE);
+        if hash_all(&[left, right, note]) != id {
+            return Err("Chunk proof branch does not match its parent".to_string());
+        }
+
+        let boundary = note_to_u64(note)?;
+        if dest < boundary {
+            id = left.try_into().expect("hash size");
+            end = end.min(boundary);
+        } else {
+            id = right.try_into().expect("hash size");
+            start = start.max(boundary);
+        }
+        path = rest;
+    }
+}
+
+/// Data path of a single chunk, as served by `/chunk/{offset}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkProof {
+    pub start: u64,
+    pub end: u64,
+    pub data_path: Vec<u8>,
+}
+
+/// Computes the `data_root` of chunks added in order, without keeping their data: one leaf
+/// node, the hash and end offset of the chunk, is kept per chunk until `finish`.
+#[derive(Debug, Default)]
+pub struct DataRootBuilder {
+    leaves: Vec<Node>,
+}
+
+impl DataRootBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the chunk ending at `end` whose bytes hash to `data_hash`.
+    pub fn add_chunk(&mut self, data_hash: [u8; 32], end: u64) {
+        self.leaves.push(Node::leaf(data_hash, end));
+    }
+
+    pub fn finish(self) -> [u8; 32] {
+        build_tree(self.leaves).id
+    }
+}
+
+// Computes the `data_root` of the bytes read through it, chunked with the standard layout
+// for `size` bytes. The bytes are not kept: the current chunk is hashed as it is read, and one
+// leaf node is kept per finished chunk.
+pub(crate) struct DataRootReader<R> {
+    inner: R,
+    hashing: Option<ChunkHashing>,
+}
+
+struct ChunkHashing {
+    size: u64,
+    layout: ChunkLayout,
+    chunk_end: u64,
+    position: u64,
+    hasher: Sha256,
+    builder: DataRootBuilder,
+}
+
+impl ChunkHashing {
+    fn new(size: u64) -> Self {
+        let mut hashing = ChunkHashing {
+            size,
+            layout: chunk_layout(size),
+            chunk_end: 0,
+            position: 0,
+            hasher: Sha256::new(),
+            builder: DataRootBuilder::new(),
+        };
+        hashing.next_chunk();
+        hashing
+    }
+
+    // Moves to the next chunk, adding empty chunks right away.
+    fn next_chunk(&mut self) {
+        for range in self.layout.by_ref() {
+            if range.is_empty() {
+                self.builder.add_chunk(Sha256::digest([]).into(), range.end);
+                continue;
+            }
+            self.chunk_end = range.end;
+            return;
+        }
+        self.chunk_end = self.size;
+    }
+
+    fn update(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() && self.position < self.size {
+            let take = bytes.len().min((self.chunk_end - self.position) as usize);
+            self.hasher.update(&bytes[..take]);
+            self.position += take as u64;
+            bytes = &bytes[take..];
+
+            if self.position == self.chunk_end {
+                let data_hash = self.hasher.finalize_reset().into();
+                self.builder.add_chunk(data_hash, self.chunk_end);
+                self.next_chunk();
+            }
+        }
+        // bytes past the declared size cannot be part of the data
+        self.position += bytes.len() as u64;
+    }
+}
+
+impl<R> DataRootReader<R> {
+    pub(crate) fn new(inner: R, size: Option<u64>) -> Self {
+        DataRootReader {
+            inner,
+            hashing: size.map(ChunkHashing::new),
+        }
+    }
+
+    // The `data_root` of everything read, when it was computed and exactly `size` bytes were read.
+    pub(crate) fn finish(&mut self) -> Option<Result<[u8; 32], String>> {
+        let hashing = self.hashing.take()?;
+        if hashing.position != hashing.size {
+            return Some(Err(format!(
+                "read {} bytes, the data_size is {}",
+                hashing.position, hashing.size
+            )));
+        }
+        Some(Ok(hashing.builder.finish()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DataRootReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(hashing)) = (&poll, this.hashing.as_mut()) {
+            hashing.update(&buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+/// `data_root` of `data` chunked with the standard layout.
+pub fn data_root(data: &[u8]) -> [u8; 32] {
+    chunk_proofs(data).0
+}
+
+/// `data_root` of `data` and the proofs of its non-empty chunks.
+pub fn chunk_proofs(data: &[u8]) -> ([u8; 32], Vec<ChunkProof>) {
+    let leaves = chunk_layout(data.len() as u64)
+        .map(|range| {
+            let chunk = &data[range.start as usize..range.end as usize];
+            Node::leaf(Sha256::digest(chunk).into(), range.end)
+        })
+        .collect();
+    let root = build_tree(leaves);
+
+    let mut proofs = Vec::new();
+    collect_proofs(&root, 0, Vec::new(), &mut proofs);
+    proofs.retain(|proof| proof.end > proof.start);
+    (root.id, proofs)
+}
+
+#[derive(Debug)]
+struct Node {
+    id: [u8; 32],
+    end: u64,
+    kind: NodeKind,
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Leaf {
+        data_hash: [u8; 32],
+    },
+    Branch {
+        left: Box<Node>,
+        right: Box<Node>,
+        boundary: u64,
+    },
+}
+
+impl Node {
+    fn leaf(data_hash: [u8; 32], end: u64) -> Self {
+        Node {
+            id: hash_all(&[&data_hash, &u64_to_note(end)]),
+            end,
+            kind: NodeKind::Leaf { data_hash },
+        }
+    }
+
+    fn branch(left: Node, right: Node) -> Self {
+        let boundary = left.end;
+        Node {
+            id: hash_all(&[&left.id, &right.id, &u64_to_note(boundary)]),
+            end: right.end,
+            kind: NodeKind::Branch {
+                left: Box::new(left),
+                right: Box::new(right),
+                boundary,
+            },
+        }
+    }
+}
+
+// Pairs the nodes of every layer until one is left, an odd node is carried to the next layer.
+fn build_tree(mut layer: Vec<Node>) -> Node {
+    if layer.is_empty() {
+        layer.push(Node::leaf(Sha256::digest([]).into(), 0));
+    }
+    while layer.len() > 1 {
+        let mut next = Vec::with_capacity(layer.len().div_ceil(2));
+        let mut nodes = layer.into_iter();
+        while let Some(left) = nodes.next() {
+            match nodes.next() {
+                Some(right) => next.push(Node::branch(left, right)),
+                None => next.push(left),
+            }
+        }
+        layer = next;
+    }
+    layer.pop().expect("one node left")
+}
+
+fn collect_proofs(node: &Node, start: u64, path: Vec<u8>, proofs: &mut Vec<ChunkProof>) {
+    match &node.kind {
+        NodeKind::Leaf { data_hash } => {
+            let mut data_path = path;
+            data_path.extend_from_slice(data_hash);
+            data_path.extend_from_slice(&u64_to_note(node.end));
+            proofs.push(ChunkProof {
+                start,
+                end: node.end,
+                data_path,
+            });
+        }
+        NodeKind::Branch {
+            left,
+            right,
+            boundary,
+        } => {
+            let mut path = path;
+            path.extend_from_slice(&left.id);
+            path.extend_from_slice(&right.id);
+            path.extend_from_slice(&u64_to_note(*boundary));
+            collect_proofs(left, start, path.clone(), proofs);
+            collect_proofs(right, *boundary, path, proofs);
+        }
+    }
+}
+
+// Merkle nodes hash the hashes of their parts.
+fn hash_all(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(Sha256::digest(part));
+    }
+    hasher.finalize().into()
+}
+
+// Offsets are stored as 32-byte big-endian numbers.
+fn u64_to_note(value: u64) -> [u8; NOTE_SIZE] {
+    let mut note = [0u8; NOTE_SIZE];
+    note[NOTE_SIZE - 8..].copy_from_slice(&value.to_be_bytes());
+    note
+}
+
+fn note_to_u64(note: &[u8]) -> Result<u64, String> {
+    let mut little_endian = [0u8; NOTE_SIZE];
+    little_endian.copy_from_slice(note);
+    little_endian.reverse();
+    U256::from_le_bytes(little_endian)
+        .to_u64()
+        .map_err(|e| format!("Chunk proof offset unsupported: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_layout_follows_the_reference_chunking() {
+        let layout: Vec<_> = chunk_layout(100).collect();
+        assert_eq!(layout, vec![0..100]);
+
+        let layout: Vec<_> = chunk_layout(MAX_CHUNK_SIZE).collect();
+        assert_eq!(
+            layout,
+            vec![0..MAX_CHUNK_SIZE, MAX_CHUNK_SIZE..MAX_CHUNK_SIZE]
+        );
+
+        // a last chunk smaller than the minimum is avoided by splitting the remainder in two
+        let size = MAX_CHUNK_SIZE * 2 + 10;
+        let layout: Vec<_> = chunk_layout(size).collect();
+        let half = (MAX_CHUNK_SIZE + 10).div_ceil(2);
+        assert_eq!(
+            layout,
+            vec![
+                0..MAX_CHUNK_SIZE,
+                MAX_CHUNK_SIZE..MAX_CHUNK_SIZE + half,
+                MAX_CHUNK_SIZE + half..size
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proofs_validate_against_the_root() {
+        let data: Vec<u8> = (0..300 * 1024).map(|i| i as u8).collect();
+        let (root, proofs) = chunk_proofs(&data);
+
+        assert_eq!(proofs.len(), 2);
+        for proof in &proofs {
+            let chunk =
+                validate_path(&root, proof.start, data.len() as u64, &proof.data_path).unwrap();
+            assert_eq!(chunk.start, proof.start);
+            assert_eq!(chunk.end, proof.end);
+            let bytes = &data[proof.start as usize..proof.end as usize];
+            assert_eq!(chunk.data_hash, <[u8; 32]>::from(Sha256::digest(bytes)));
+        }
+    }
+
+    #[test]
+    fn test_every_offset_is_proven_by_its_chunk() {
+        let data: Vec<u8> = (0..MAX_CHUNK_SIZE as usize * 3 + 5000)
+            .map(|i| (i * 7) as u8)
+            .collect();
+        let size = data.len() as u64;
+        let (root, proofs) = chunk_proofs(&data);
+
+        for proof in &proofs {
+            for offset in [proof.start, proof.end - 1, (proof.start + proof.end) / 2] {
+                let chunk = validate_path(&root, offset, size, &proof.data_path).unwrap();
+                assert_eq!((chunk.start, chunk.end), (proof.start, proof.end));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tampered_path_is_rejected() {
+        let data = vec![9u8; MAX_CHUNK_SIZE as usize + 100_000];
+        let size = data.len() as u64;
+        let (root, proofs) = chunk_proofs(&data);
+
+        let mut path = proofs[1].data_path.clone();
+        path[5] ^= 1;
+        assert!(validate_path(&root, proofs[1].start, size, &path).is_err());
+
+        // a valid proof does not prove an offset outside of its chunk
+        assert!(validate_path(&root, 0, size, &proofs[1].data_path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reader_computes_the_root_of_streamed_bytes() {
+        use tokio::io::AsyncReadExt;
+
+        for size in [
+            0,
+            1000,
+            MAX_CHUNK_SIZE as usize,
+            MAX_CHUNK_SIZE as usize * 2 + 7,
+        ] {
+            let data: Vec<u8> = (0..size).map(|i| (i * 13) as u8).collect();
+            let mut reader = DataRootReader::new(&data[..], Some(size as u64));
+            let mut sink = Vec::new();
+            reader.read_to_end(&mut sink).await.unwrap();
+
+            assert_eq!(
+                reader.finish().unwrap().unwrap(),
+                data_root(&data),
+                "{}",
+                size
+            );
+        }
+
+        let data = vec![1u8; 500];
+        let mut reader = DataRootReader::new(&data[..], Some(600));
+        reader.read_to_end(&mut Vec::new()).await.unwrap();
+        assert!(reader.finish().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_builder_matches_the_tree_root() {
+        let data: Vec<u8> = (0..MAX_CHUNK_SIZE as usize * 2).map(|i| i as u8).collect();
+        let mut builder = DataRootBuilder::new();
+        for range in chunk_layout(data.len() as u64) {
+            let chunk = &data[range.start as usize..range.end as usize];
+            builder.add_chunk(Sha256::digest(chunk).into(), range.end);
+        }
+        assert_eq!(builder.finish(), data_root(&data));
+    }
+}
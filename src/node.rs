@@ -0,0 +1,147 @@
+use bytes::Bytes;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::block::{Block, NetworkInfo};
+use crate::download::RetryPolicy;
+use crate::transaction::{TransactionHeader, TransactionStatus};
+
+/// Client for the HTTP API served by Arweave nodes and gateways.
+///
+/// Requests are tried on every url in turn, with the backoff of the `RetryPolicy`
+/// between failed attempts.
+#[derive(Debug, Clone)]
+pub struct NodeClient {
+    client: Client,
+    urls: Vec<String>,
+    retry: RetryPolicy,
+}
+
+/// Position of the transaction data in the weave, as served by `/tx/{id}/offset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransactionOffset {
+    pub size: u64,
+    /// Weave offset of the last byte of the data.
+    pub end: u64,
+}
+
+#[derive(Deserialize)]
+struct RawOffset {
+    size: String,
+    offset: String,
+}
+
+impl NodeClient {
+    pub fn new(urls: &[String]) -> Self {
+        NodeClient {
+            client: Client::new(),
+            urls: urls
+                .iter()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .collect(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    pub async fn transaction(&self, id: &str) -> Result<TransactionHeader, String> {
+        self.get_with_retry(&format!("/tx/{}", id), |body| {
+            TransactionHeader::parse(&body)
+        })
+        .await
+    }
+
+    /// Confirmation status of a transaction, `None` while it is pending.
+    pub async fn transaction_status(&self, id: &str) -> Result<Option<TransactionStatus>, String> {
+        self.get_with_retry(&format!("/tx/{}/status", id), |body| {
+            if body.as_ref() == b"Pending" {
+                return Ok(None);
+            }
+            serde_json::from_slice(&body)
+                .map(Some)
+                .map_err(|e| format!("invalid status: {}", e))
+        })
+        .await
+    }
+
+    pub async fn transaction_offset(&self, id: &str) -> Result<TransactionOffset, String> {
+        let raw: RawOffset = self.get_json(&format!("/tx/{}/offset", id)).await?;
+        Ok(TransactionOffset {
+            size: parse_number("size", &raw.size)?,
+            end: parse_number("offset", &raw.offset)?,
+        })
+    }
+
+    pub async fn info(&self) -> Result<NetworkInfo, String> {
+        self.get_json("/info").await
+    }
+
+    pub async fn block_at_height(&self, height: u64) -> Result<Block, String> {
+        self.get_with_retry(&format!("/block/height/{}", height), |body| {
+            Block::parse(&body)
+        })
+        .await
+    }
+
+    pub(crate) async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.get_with_retry(path, |body| {
+            serde_json::from_slice(&body).map_err(|e| format!("invalid response: {}", e))
+        })
+        .await
+    }
+
+    // Requests `path` from the urls in turn until `accept` takes the response body.
+    pub(crate) async fn get_with_retry<T>(
+        &self,
+        path: &str,
+        accept: impl Fn(Bytes) -> Result<T, String>,
+    ) -> Result<T, String> {
+        if self.urls.is_empty() {
+            return Err(format!("No node to fetch {} from", path));
+        }
+
+        let mut failures = 0;
+        loop {
+            let url = format!("{}{}", self.urls[failures as usize % self.urls.len()], path);
+            let result = match self.client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => match response.bytes().await {
+                    Ok(body) => accept(body),
+                    Err(e) => Err(e.to_string()),
+                },
+                Ok(response) => Err(format!("responded {}", response.status())),
+                Err(e) => Err(e.to_string()),
+            };
+
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+
+            failures += 1;
+            if failures > self.retry.max_retries {
+                return Err(format!(
+                    "Failed to fetch {} after {} attempts: {}",
+                    path, failures, error
+                ));
+            }
+            let backoff = self.retry.backoff(failures);
+            tracing::warn!("{}: {}, retrying in {:?}", url, error, backoff);
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+pub(crate) fn parse_number(field: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {} {:?}: {}", field, value, e))
+}
@@ -0,0 +1,146 @@
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::store::{root_tx_id, ItemStore};
+
+#[derive(Clone)]
+struct Bundles {
+    store: ItemStore,
+    dir: Arc<PathBuf>,
+}
+
+/// Routes serving the data of the items of an `ItemStore` on `/{item_id}`, read from the
+/// bundles stored in `dir` as files named after their root transaction id.
+///
+/// The data is found at the offsets recorded during indexing, so the files must hold the
+/// uncompressed data of the root transactions. The `Content-Type` is the one of the item tags,
+/// and single `Range` requests are answered with partial content.
+pub fn data_router(store: ItemStore, dir: impl Into<PathBuf>) -> Router {
+    let bundles = Bundles {
+        store,
+        dir: Arc::new(dir.into()),
+    };
+    Router::new()
+        .route("/{item_id}", get(item_data))
+        .with_state(bundles)
+}
+
+async fn item_data(
+    State(bundles): State<Bundles>,
+    Path(item_id): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let store = bundles.store.clone();
+    let item = match tokio::task::spawn_blocking(move || store.get(&item_id)).await {
+        Ok(Ok(Some(item))) => item,
+        Ok(Ok(None)) => return StatusCode::NOT_FOUND.into_response(),
+        Ok(Err(e)) => return server_error(e),
+        Err(e) => return server_error(e),
+    };
+    // the root transaction id comes from the store, it must not lead out of the directory
+    let root_tx = root_tx_id(&item);
+    if root_tx.is_empty()
+        || !root_tx
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let path = bundles.dir.join(root_tx);
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return StatusCode::NOT_FOUND.into_response()
+        }
+        Err(e) => return server_error(format!("Failed to open {}: {}", path.display(), e)),
+    };
+    let (offset, size) = (item.data_offset(), item.data_size());
+    match file.metadata().await {
+        Ok(metadata) if metadata.len() >= offset + size => {}
+        Ok(_) => {
+            return server_error(format!(
+                "{} is shorter than the data of the item",
+                path.display()
+            ))
+        }
+        Err(e) => return server_error(format!("Failed to read {}: {}", path.display(), e)),
+    }
+
+    let content_type = item
+        .content_type()
+        .and_then(|content_type| HeaderValue::from_str(content_type).ok())
+        .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| parse_range(range, size));
+    let (status, start, len) = match range {
+        None => (StatusCode::OK, 0, size),
+        Some(Some((start, end))) => (StatusCode::PARTIAL_CONTENT, start, end + 1 - start),
+        Some(None) => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", size))],
+            )
+                .into_response()
+        }
+    };
+    if let Err(e) = file.seek(SeekFrom::Start(offset + start)).await {
+        return server_error(format!("Failed to read {}: {}", path.display(), e));
+    }
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, start + len - 1, size),
+        );
+    }
+    response
+        .body(Body::from_stream(ReaderStream::new(file.take(len))))
+        .unwrap_or_else(server_error)
+}
+
+fn server_error(e: impl std::fmt::Display) -> Response {
+    tracing::error!("Failed to serve an item: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+// Inclusive bounds of a single `bytes` range in data of `size` bytes, None in the outer option
+// when the header is ignored and the whole data served (other units, several ranges or syntax
+// errors), and in the inner one when the range is not satisfiable.
+fn parse_range(range: &str, size: u64) -> Option<Option<(u64, u64)>> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let bounds = if start.is_empty() {
+        // the last bytes of the data
+        let len: u64 = end.parse().ok()?;
+        (len > 0 && size > 0).then(|| (size.saturating_sub(len), size - 1))
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            end.parse().ok()?
+        };
+        if end < start {
+            return None;
+        }
+        (start < size).then(|| (start, end.min(size - 1)))
+    };
+    Some(bounds)
+}
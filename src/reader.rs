@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+// Reader wrapper used by the bundle walker.
+// It keeps track of the absolute stream position and, when a history capacity is set,
+// remembers the last consumed bytes so the walker can rewind into them (used by salvage mode).
+pub struct TrackedReader<R> {
+    inner: R,
+    position: u64,
+    // the remembered bytes are `history[history_start..]`, the ones before are dropped when
+    // they take as much room as the capacity, so that appending stays a plain copy
+    history: Vec<u8>,
+    history_start: usize,
+    history_capacity: usize,
+    replay: VecDeque<u8>,
+}
+
+impl<R: AsyncRead + Unpin> TrackedReader<R> {
+    pub fn new(inner: R, history_capacity: usize) -> Self {
+        TrackedReader {
+            inner,
+            position: 0,
+            history: Vec::new(),
+            history_start: 0,
+            history_capacity,
+            replay: VecDeque::new(),
+        }
+    }
+
+    // A reader without history whose first byte is at `position` of the stream.
+    pub fn starting_at(inner: R, position: u64) -> Self {
+        TrackedReader {
+            position,
+            ..Self::new(inner, 0)
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Moves the reader back to `position`, or as far back as the history allows.
+    // Returns the position the reader ended up at.
+    pub fn rewind_to(&mut self, position: u64) -> u64 {
+        let remembered = self.history.len() - self.history_start;
+        let earliest = self.position - remembered as u64;
+        let target = position.clamp(earliest, self.position);
+
+        let count = (self.position - target) as usize;
+        let kept = self.history.len() - count;
+        for byte in self.history.drain(kept..).rev() {
+            self.replay.push_front(byte);
+        }
+        self.position = target;
+        target
+    }
+
+    // Returns up to `len` upcoming bytes without consuming them.
+    // The result is shorter than `len` only when the stream ends.
+    pub async fn peek(&mut self, len: usize) -> io::Result<&[u8]> {
+        let mut buf = [0u8; 8192];
+        while self.replay.len() < len {
+            let wanted = (len - self.replay.len()).min(buf.len());
+            let n = self.inner.read(&mut buf[..wanted]).await?;
+            if n == 0 {
+                break;
+            }
+            self.replay.extend(&buf[..n]);
+        }
+
+        let available = self.replay.len().min(len);
+        Ok(&self.replay.make_contiguous()[..available])
+    }
+
+    // Consumes up to `count` bytes previously made available by `peek`.
+    pub fn advance(&mut self, count: usize) {
+        let count = count.min(self.replay.len());
+        let replay = std::mem::take(&mut self.replay);
+        let (front, back) = replay.as_slices();
+        let from_front = count.min(front.len());
+        self.remember(&front[..from_front]);
+        self.remember(&back[..count - from_front]);
+        self.replay = replay;
+        self.replay.drain(..count);
+        self.position += count as u64;
+    }
+
+    fn remember(&mut self, bytes: &[u8]) {
+        if self.history_capacity == 0 {
+            return;
+        }
+        let bytes = &bytes[bytes.len().saturating_sub(self.history_capacity)..];
+        self.history.extend_from_slice(bytes);
+        let remembered = self.history.len() - self.history_start;
+        self.history_start += remembered.saturating_sub(self.history_capacity);
+        if self.history_start >= self.history_capacity {
+            self.history.drain(..self.history_start);
+            self.history_start = 0;
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TrackedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled_before = buf.filled().len();
+
+        if !this.replay.is_empty() {
+            let count = this.replay.len().min(buf.remaining());
+            let (front, back) = this.replay.as_slices();
+            let from_front = count.min(front.len());
+            buf.put_slice(&front[..from_front]);
+            buf.put_slice(&back[..count - from_front]);
+            this.replay.drain(..count);
+        } else {
+            match Pin::new(&mut this.inner).poll_read(cx, buf) {
+                Poll::Ready(Ok(())) => {}
+                other => return other,
+            }
+        }
+
+        let filled = &buf.filled()[filled_before..];
+        this.position += filled.len() as u64;
+        this.remember(filled);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rewind_replays_history() {
+        let mut reader = TrackedReader::new(&b"0123456789"[..], 4);
+        let mut buf = [0u8; 8];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(reader.position(), 8);
+
+        assert_eq!(reader.rewind_to(2), 4);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"456789");
+        assert_eq!(reader.position(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_peek_does_not_consume() {
+        let mut reader = TrackedReader::new(&b"abcdef"[..], 0);
+        assert_eq!(reader.peek(3).await.unwrap(), b"abc");
+        reader.advance(1);
+        assert_eq!(reader.peek(10).await.unwrap(), b"bcdef");
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"bcdef");
+        assert_eq!(reader.position(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_the_last_bytes_across_reads() {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut reader = TrackedReader::new(&data[..], 10);
+        let mut buf = [0u8; 7];
+        for _ in 0..30 {
+            reader.read_exact(&mut buf).await.unwrap();
+        }
+        reader.peek(20).await.unwrap();
+        reader.advance(20);
+        assert_eq!(reader.position(), 230);
+
+        // only the capacity is kept
+        assert_eq!(reader.rewind_to(0), 220);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, &data[220..]);
+    }
+}
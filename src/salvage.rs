@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncRead;
+
+use crate::reader::TrackedReader;
+use crate::BundleEntry;
+
+// Largest possible signature (RSA) plus the signature type prefix.
+const MAX_PROBE_LEN: usize = 2 + 512;
+// Number of candidate positions scanned per read from the reader.
+const SCAN_LEN: usize = 64 * 1024;
+
+// Searches for the start of one of `entries` after the stream position `after`.
+// Candidates are every byte position past `after` that is still available in the reader history,
+// up to `window` bytes past the point where the damage was detected.
+// A candidate matches when the signature found there hashes to one of the header ids.
+// Blocks of candidates are scanned at once for the signature type prefixes, and only the
+// positions starting with one are hashed.
+// On success the reader is positioned at the start of the entry and its index in `entries` is returned.
+pub async fn relocate<R: AsyncRead + Unpin>(
+    reader: &mut TrackedReader<R>,
+    after: u64,
+    entries: &[BundleEntry],
+    window: u64,
+) -> Result<Option<usize>, String> {
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let ids: HashMap<[u8; 32], usize> = entries
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, entry)| (entry.id, index))
+        .collect();
+    let read_error = |e: std::io::Error| format!("Failed to read while relocating: {}", e);
+
+    let limit = reader.position() + window;
+    let start = after + 1;
+    let position = reader.rewind_to(start);
+    if position < start {
+        let len = (start - position) as usize;
+        if reader.peek(len).await.map_err(read_error)?.len() < len {
+            return Ok(None);
+        }
+        reader.advance(len);
+    }
+
+    while reader.position() < limit {
+        let scan_len = SCAN_LEN.min((limit - reader.position()) as usize);
+        // the signatures of the last candidates are read with them
+        let probe = reader
+            .peek(scan_len + MAX_PROBE_LEN)
+            .await
+            .map_err(read_error)?;
+        if probe.len() < 2 {
+            return Ok(None);
+        }
+
+        let candidates = scan_len.min(probe.len() - 1);
+        let found = (0..candidates).find_map(|offset| {
+            let length = match probe[offset..offset + 2] {
+                [1, 0] => 512,
+                [2, 0] => 64,
+                _ => return None,
+            };
+            let signature = probe.get(offset + 2..offset + 2 + length)?;
+            let id: [u8; 32] = Sha256::digest(signature).into();
+            ids.get(&id).map(|index| (offset, *index))
+        });
+        if let Some((offset, index)) = found {
+            reader.advance(offset);
+            return Ok(Some(index));
+        }
+        reader.advance(candidates);
+    }
+
+    Ok(None)
+}
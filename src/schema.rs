@@ -0,0 +1,62 @@
+use std::borrow::Cow;
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::Serialize;
+
+use crate::tags::Tag;
+use crate::transaction::RootTransaction;
+
+/// Version of the JSON Schema of the item records, bumped on every incompatible change.
+pub const DATA_ITEM_SCHEMA_VERSION: u32 = 1;
+
+// Record written by the JSON sinks for every `DataItem`, the JSON Schema is generated from it.
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "DataItem")]
+pub(crate) struct DataItemRecord<'a> {
+    /// Id of the item, the base64url SHA-256 hash of its signature.
+    pub(crate) id: String,
+    /// Signature of the item, base64url encoded.
+    pub(crate) signature: String,
+    /// Public key of the owner, base64url encoded.
+    pub(crate) owner: String,
+    /// Base64url target address, empty when the item has none.
+    pub(crate) target: String,
+    /// Base64url anchor, empty when the item has none.
+    pub(crate) anchor: String,
+    pub(crate) tags: &'a [Tag],
+    /// Id of the bundle holding the item, the root transaction or a nested bundle item.
+    pub(crate) bundled_in: &'a str,
+    /// Whether the item is itself tagged as a bundle, whose items follow it.
+    pub(crate) is_bundle: bool,
+    /// Layer 1 transaction of the root bundle, when its header was fetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) root_tx: Option<&'a RootTransaction>,
+}
+
+impl JsonSchema for Tag {
+    fn schema_name() -> Cow<'static, str> {
+        "Tag".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "description": "A tag of the item. The name and the value are UTF-8 text, or both base64url encoded when either is not valid UTF-8.",
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "value": { "type": "string" }
+            },
+            "required": ["name", "value"]
+        })
+    }
+}
+
+/// JSON Schema of the records written by the JSON sinks, one per `DataItem`.
+pub fn data_item_schema() -> serde_json::Value {
+    let mut schema = schemars::schema_for!(DataItemRecord<'static>);
+    schema.insert(
+        "$id".to_string(),
+        format!("data-item.v{}.schema.json", DATA_ITEM_SCHEMA_VERSION).into(),
+    );
+    schema.to_value()
+}
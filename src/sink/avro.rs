@@ -0,0 +1,251 @@
+use std::io::{BufRead, SeekFrom};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use serde_avro_fast::de::read::ReaderRead;
+use serde_avro_fast::object_container_file_encoding::{Compression, Reader, Writer, WriterBuilder};
+use serde_avro_fast::ser::SerializerConfig;
+use serde_avro_fast::Schema;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::record::{BinaryRecord, OwnedBinaryRecord};
+use super::{open_output, Sink};
+use crate::DataItem;
+
+/// Avro schema of the records written by an `AvroSink`, also embedded in every file.
+pub const AVRO_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "DataItem",
+  "namespace": "ans104",
+  "fields": [
+    { "name": "signature", "type": "bytes" },
+    { "name": "signature_type", "type": "int" },
+    { "name": "owner", "type": "bytes" },
+    { "name": "target", "type": ["null", "bytes"] },
+    { "name": "anchor", "type": ["null", "bytes"] },
+    {
+      "name": "tags",
+      "type": {
+        "type": "array",
+        "items": {
+          "type": "record",
+          "name": "Tag",
+          "fields": [
+            { "name": "name", "type": "bytes" },
+            { "name": "value", "type": "bytes" }
+          ]
+        }
+      }
+    },
+    { "name": "bundled_in", "type": "string" },
+    { "name": "is_bundle", "type": "boolean" },
+    {
+      "name": "root_tx",
+      "type": [
+        "null",
+        {
+          "type": "record",
+          "name": "RootTransaction",
+          "fields": [
+            { "name": "id", "type": "string" },
+            { "name": "owner_address", "type": "string" },
+            { "name": "data_size", "type": "long" },
+            { "name": "block_height", "type": ["null", "long"] },
+            { "name": "block_hash", "type": ["null", "string"] },
+            { "name": "block_timestamp", "type": ["null", "long"] }
+          ]
+        }
+      ]
+    },
+    { "name": "offset", "type": "long" },
+    { "name": "size", "type": "long" },
+    { "name": "header_size", "type": "long" },
+    { "name": "ancestry", "type": { "type": "array", "items": "string" } }
+  ]
+}"#;
+
+static SCHEMA: LazyLock<Schema> =
+    LazyLock::new(|| AVRO_SCHEMA.parse().expect("the Avro schema is valid"));
+
+// every block of an object container file ends with the sync marker of the file
+const SYNC_MARKER_LEN: u64 = 16;
+
+/// Writes the items to an Avro object container file following `AVRO_SCHEMA`, which keeps
+/// the signature, owner, target, anchor and tags as raw bytes.
+///
+/// Items are written in blocks, and the file is complete after every block, so it can be
+/// appended to or cut back to the length returned by `flush`.
+pub struct AvroSink {
+    file: File,
+    path: PathBuf,
+    len: u64,
+    // encodes the blocks in memory, they are moved to the file once complete
+    writer: Writer<'static, 'static, Vec<u8>>,
+}
+
+impl std::fmt::Debug for AvroSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AvroSink")
+            .field("path", &self.path)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl AvroSink {
+    /// Creates the file, truncating it when it exists.
+    pub async fn create(path: impl Into<PathBuf>) -> Result<Self, String> {
+        Self::open(path.into(), Some(0)).await
+    }
+
+    /// Writes after the items already in the file.
+    pub async fn append(path: impl Into<PathBuf>) -> Result<Self, String> {
+        Self::open(path.into(), None).await
+    }
+
+    /// Cuts the file back to `len` bytes, the length a checkpoint recorded, and writes after.
+    pub async fn resume(path: impl Into<PathBuf>, len: u64) -> Result<Self, String> {
+        Self::open(path.into(), Some(len)).await
+    }
+
+    async fn open(path: PathBuf, keep: Option<u64>) -> Result<Self, String> {
+        let (file, len) = open_output(&path, keep).await?;
+        let mut sink = AvroSink {
+            file,
+            path,
+            len,
+            writer: build_writer(None)?,
+        };
+        if len == 0 {
+            // the header is written by the builder
+            sink.write_blocks().await?;
+        } else {
+            sink.reopen().await?;
+        }
+        Ok(sink)
+    }
+
+    // Continues the file with its own sync marker, once its header is known to match ours.
+    async fn reopen(&mut self) -> Result<(), String> {
+        let not_items = || {
+            format!(
+                "{} is not an Avro file of items with the same schema",
+                self.path.display()
+            )
+        };
+        if self.len < SYNC_MARKER_LEN {
+            return Err(not_items());
+        }
+        let mut sync_marker = [0; SYNC_MARKER_LEN as usize];
+        self.file
+            .seek(SeekFrom::Start(self.len - SYNC_MARKER_LEN))
+            .await
+            .map_err(|e| self.error(e))?;
+        self.file
+            .read_exact(&mut sync_marker)
+            .await
+            .map_err(|e| self.error(e))?;
+
+        // the header the file would start with, had it been written by this sink
+        let mut writer = build_writer(Some(sync_marker))?;
+        let header = std::mem::take(writer.inner_mut());
+        let mut start = vec![0; header.len()];
+        self.file
+            .seek(SeekFrom::Start(0))
+            .await
+            .map_err(|e| self.error(e))?;
+        if header.len() as u64 > self.len
+            || self.file.read_exact(&mut start).await.is_err()
+            || start != header
+        {
+            return Err(not_items());
+        }
+        self.writer = writer;
+        self.file
+            .seek(SeekFrom::Start(self.len))
+            .await
+            .map_err(|e| self.error(e))?;
+        Ok(())
+    }
+
+    // Moves the complete blocks to the file.
+    async fn write_blocks(&mut self) -> Result<(), String> {
+        let blocks = std::mem::take(self.writer.inner_mut());
+        self.file
+            .write_all(&blocks)
+            .await
+            .map_err(|e| self.error(e))?;
+        self.len += blocks.len() as u64;
+        Ok(())
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        format!("Failed to write {}: {}", self.path.display(), e)
+    }
+}
+
+fn build_writer(
+    sync_marker: Option<[u8; 16]>,
+) -> Result<Writer<'static, 'static, Vec<u8>>, String> {
+    let mut builder = WriterBuilder::with_owned_config(SerializerConfig::new(&SCHEMA))
+        .compression(Compression::Null);
+    if let Some(sync_marker) = sync_marker {
+        builder = builder.sync_marker(sync_marker);
+    }
+    builder
+        .build(Vec::new())
+        .map_err(|e| format!("Failed to start an Avro file: {}", e))
+}
+
+impl Sink for AvroSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        for item in items {
+            self.writer
+                .serialize(BinaryRecord::from(item))
+                .map_err(|e| self.error(e))?;
+        }
+        self.write_blocks().await
+    }
+
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        self.writer.finish_block().map_err(|e| self.error(e))?;
+        self.write_blocks().await?;
+        self.file.flush().await.map_err(|e| self.error(e))?;
+        self.file.sync_data().await.map_err(|e| self.error(e))?;
+        Ok(Some(self.len))
+    }
+
+    async fn finish(mut self) -> Result<(), String> {
+        self.writer.finish_block().map_err(|e| self.error(e))?;
+        self.write_blocks().await?;
+        self.file.flush().await.map_err(|e| self.error(e))
+    }
+}
+
+/// Reads back the items of an Avro file written by an `AvroSink`, decoded with the schema
+/// embedded in the file.
+pub struct AvroReader<R: BufRead> {
+    reader: Reader<ReaderRead<R>>,
+}
+
+impl<R: BufRead> AvroReader<R> {
+    /// Reads the header of the file.
+    pub fn new(reader: R) -> Result<Self, String> {
+        let reader = Reader::from_reader(reader)
+            .map_err(|e| format!("Failed to read the Avro header: {}", e))?;
+        Ok(AvroReader { reader })
+    }
+}
+
+impl<R: BufRead> Iterator for AvroReader<R> {
+    type Item = Result<DataItem, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader
+            .deserialize_next::<OwnedBinaryRecord>()
+            .map_err(|e| format!("Failed to decode an item: {}", e))
+            .and_then(|record| record.map(DataItem::try_from).transpose())
+            .transpose()
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::SeekFrom;
+use std::path::PathBuf;
+
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::{open_output, Sink};
+use crate::DataItem;
+
+/// Layout of the items in the file of a `JsonSink`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// Newline-delimited JSON, one compact object per line.
+    Lines,
+    /// A JSON array, one compact object per line.
+    Array,
+}
+
+/// Writes every item to a file as a JSON object following `data_item_schema`.
+///
+/// In the array format the closing bracket is only written by `finish`. Appending to an array
+/// or resuming it continues the same array, whether or not a previous run closed it.
+#[derive(Debug)]
+pub struct JsonSink {
+    file: File,
+    path: PathBuf,
+    format: JsonFormat,
+    // length of the file without the closing bracket of an array
+    len: u64,
+    // whether the array already holds an item, so the next one needs a separator
+    separated: bool,
+}
+
+// start of a file holding a JSON array
+const ARRAY_START: &[u8] = b"[\n";
+// bytes read back at the end of an array to find where the next item goes
+const ARRAY_TAIL: u64 = 64;
+
+impl JsonSink {
+    /// Creates the file, truncating it when it exists.
+    pub async fn create(path: impl Into<PathBuf>, format: JsonFormat) -> Result<Self, String> {
+        Self::open(path.into(), format, Some(0)).await
+    }
+
+    /// Writes after the items already in the file.
+    pub async fn append(path: impl Into<PathBuf>, format: JsonFormat) -> Result<Self, String> {
+        Self::open(path.into(), format, None).await
+    }
+
+    /// Cuts the file back to `len` bytes, the length a checkpoint recorded, and writes after.
+    pub async fn resume(
+        path: impl Into<PathBuf>,
+        format: JsonFormat,
+        len: u64,
+    ) -> Result<Self, String> {
+        Self::open(path.into(), format, Some(len)).await
+    }
+
+    // Opens the file cut to `keep` bytes, or whole.
+    async fn open(path: PathBuf, format: JsonFormat, keep: Option<u64>) -> Result<Self, String> {
+        let (file, len) = open_output(&path, keep).await?;
+
+        let mut sink = JsonSink {
+            file,
+            path,
+            format,
+            len,
+            separated: false,
+        };
+        if format == JsonFormat::Array {
+            sink.reopen_array().await?;
+        }
+        sink.file
+            .seek(SeekFrom::Start(sink.len))
+            .await
+            .map_err(|e| sink.error(e))?;
+        Ok(sink)
+    }
+
+    // Finds where the next item of the array in the file goes, starting the array when the
+    // file is empty.
+    async fn reopen_array(&mut self) -> Result<(), String> {
+        if self.len == 0 {
+            self.file
+                .write_all(ARRAY_START)
+                .await
+                .map_err(|e| self.error(e))?;
+            self.len = ARRAY_START.len() as u64;
+            return Ok(());
+        }
+
+        let start = self.len.saturating_sub(ARRAY_TAIL);
+        let mut tail = Vec::new();
+        self.file
+            .seek(SeekFrom::Start(start))
+            .await
+            .map_err(|e| self.error(e))?;
+        (&mut self.file)
+            .take(self.len - start)
+            .read_to_end(&mut tail)
+            .await
+            .map_err(|e| self.error(e))?;
+
+        let mut end = tail.len();
+        while end > 0 && tail[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        // a closed array is reopened
+        if end > 0 && tail[end - 1] == b']' {
+            end -= 1;
+            while end > 0 && tail[end - 1].is_ascii_whitespace() {
+                end -= 1;
+            }
+        }
+        match tail[..end].last() {
+            Some(b'[') => self.separated = false,
+            Some(b'}') => self.separated = true,
+            _ => {
+                return Err(format!(
+                    "{} does not end like a JSON array of items",
+                    self.path.display()
+                ))
+            }
+        }
+        self.len = start + end as u64;
+        self.file.set_len(self.len).await.map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        format!("Failed to write {}: {}", self.path.display(), e)
+    }
+}
+
+impl Sink for JsonSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        let mut json = Vec::new();
+        for item in items {
+            match self.format {
+                JsonFormat::Lines => {}
+                JsonFormat::Array if self.separated => json.extend_from_slice(b",\n"),
+                JsonFormat::Array => self.separated = true,
+            }
+            serde_json::to_writer(&mut json, item).map_err(|e| self.error(e))?;
+            if self.format == JsonFormat::Lines {
+                json.push(b'\n');
+            }
+        }
+        self.file
+            .write_all(&json)
+            .await
+            .map_err(|e| self.error(e))?;
+        self.len += json.len() as u64;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        self.file.flush().await.map_err(|e| self.error(e))?;
+        self.file.sync_data().await.map_err(|e| self.error(e))?;
+        Ok(Some(self.len))
+    }
+
+    async fn finish(mut self) -> Result<(), String> {
+        if self.format == JsonFormat::Array {
+            let end: &[u8] = if self.separated { b"\n]\n" } else { b"]\n" };
+            self.file.write_all(end).await.map_err(|e| self.error(e))?;
+        }
+        self.file.flush().await.map_err(|e| self.error(e))
+    }
+}
@@ -0,0 +1,302 @@
+mod avro;
+mod json;
+mod msgpack;
+mod parquet;
+mod postgres;
+mod record;
+mod sqlite;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::fs::{File, OpenOptions};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::checkpoint::Checkpoint;
+use crate::indexer::BundleSummary;
+use crate::transaction::RootTransaction;
+use crate::DataItem;
+
+pub use avro::{AvroReader, AvroSink, AVRO_SCHEMA};
+pub use json::{JsonFormat, JsonSink};
+pub use msgpack::{MessagePackReader, MessagePackSink};
+pub use parquet::{
+    parquet_schema, ParquetOptions, ParquetSink, DEFAULT_ROW_GROUP_BYTES, DEFAULT_ROW_GROUP_ROWS,
+};
+pub use postgres::PostgresSink;
+pub(crate) use record::{BinaryRecord, OwnedBinaryRecord};
+pub use sqlite::{SqliteSink, SQLITE_SCHEMA};
+
+/// Number of items handed to `Sink::write` at once by `write_items`.
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
+/// Destination of the parsed items, such as a file or a database.
+///
+/// Items are written in batches, in the order they were parsed. Sinks may buffer them until
+/// `flush`, and `finish` is called once when the run is over, even when it failed, so that
+/// the items written until then are kept.
+pub trait Sink: Send {
+    fn write(&mut self, items: &[DataItem]) -> impl Future<Output = Result<(), String>> + Send;
+
+    /// Makes every written item durable. Sinks whose output can be cut back return its
+    /// length, which checkpoints record so that a resumed run drops what was written after
+    /// them and writes every item exactly once.
+    fn flush(&mut self) -> impl Future<Output = Result<Option<u64>, String>> + Send;
+
+    /// Writes the last items of a root bundle, possibly none, and records its outcome. Sinks
+    /// keeping the state of the bundles record it in the same transaction as the items.
+    fn finish_bundle(
+        &mut self,
+        items: &[DataItem],
+        _done: &BundleDone,
+    ) -> impl Future<Output = Result<(), String>> + Send {
+        async move {
+            if items.is_empty() {
+                return Ok(());
+            }
+            self.write(items).await
+        }
+    }
+
+    /// Flushes the remaining items and closes the sink.
+    fn finish(self) -> impl Future<Output = Result<(), String>> + Send
+    where
+        Self: Sized;
+}
+
+/// Checkpoints of a run, saved by `write_items` once the items they count are written.
+#[derive(Debug)]
+pub struct CheckpointLog {
+    pub checkpoints: mpsc::Receiver<Checkpoint>,
+    /// File the checkpoints are saved to.
+    pub path: PathBuf,
+    /// Items written by earlier runs of the same bundle.
+    pub items: u64,
+}
+
+/// Outcome of a root bundle, handed to the sink once all its items are written.
+#[derive(Debug, Clone)]
+pub struct BundleDone {
+    /// Header of the root transaction, when it was fetched.
+    pub root_tx: Option<Arc<RootTransaction>>,
+    pub summary: BundleSummary,
+}
+
+/// Message to `write_items_with_events` other than an item, handled once every item sent
+/// before it is written. Producers send it after their items, on a channel of its own, and
+/// before dropping their item sender: events left once every item sender is dropped are
+/// handled at once, later ones are lost.
+#[derive(Debug)]
+pub enum WriterEvent {
+    BundleDone(BundleDone),
+    /// Flushes the sink, then answers with the outcome of the flush, so that the sender knows
+    /// that every item it sent before is durable.
+    Flush(oneshot::Sender<Result<(), String>>),
+}
+
+/// Writes the items received on `rx` to `sink` in batches of up to `batch_size`, until every
+/// sender is dropped, then finishes the sink. Returns the number of items written.
+///
+/// With a `CheckpointLog`, a checkpoint is saved once the sink holds exactly the items it
+/// counts, after a flush, along with the length of the output returned by the flush.
+pub async fn write_items(
+    rx: mpsc::Receiver<DataItem>,
+    sink: impl Sink,
+    batch_size: usize,
+    log: Option<CheckpointLog>,
+) -> Result<u64, String> {
+    write_items_with_events(rx, sink, batch_size, log, None).await
+}
+
+/// Same as `write_items`, also handling the `events` in order with the items: the items sent
+/// before an event are written first, the last of them along with it.
+pub async fn write_items_with_events(
+    mut rx: mpsc::Receiver<DataItem>,
+    mut sink: impl Sink,
+    batch_size: usize,
+    log: Option<CheckpointLog>,
+    events: Option<mpsc::Receiver<WriterEvent>>,
+) -> Result<u64, String> {
+    let written = write_batches(&mut rx, &mut sink, batch_size.max(1), log, events).await;
+    let finished = sink.finish().await;
+    let written = written?;
+    finished.map(|_| written)
+}
+
+// Checkpoints received but not saved yet.
+struct PendingCheckpoints {
+    log: CheckpointLog,
+    pending: VecDeque<Checkpoint>,
+    open: bool,
+}
+
+async fn write_batches(
+    rx: &mut mpsc::Receiver<DataItem>,
+    sink: &mut impl Sink,
+    batch_size: usize,
+    log: Option<CheckpointLog>,
+    mut events: Option<mpsc::Receiver<WriterEvent>>,
+) -> Result<u64, String> {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut written = 0;
+    let mut checkpoints = log.map(|log| PendingCheckpoints {
+        log,
+        pending: VecDeque::new(),
+        open: true,
+    });
+    loop {
+        let checkpoints_open = checkpoints.as_ref().is_some_and(|c| c.open);
+        let event = tokio::select! {
+            received = rx.recv_many(&mut batch, batch_size) => {
+                if received == 0 {
+                    break;
+                }
+                None
+            }
+            // queued while no item arrives, so that the walk never waits on them
+            checkpoint = recv(checkpoints.as_mut().map(|c| &mut c.log.checkpoints)),
+                if checkpoints_open =>
+            {
+                let checkpoints = checkpoints.as_mut().expect("checkpoints are open");
+                match checkpoint {
+                    Some(checkpoint) => checkpoints.pending.push_back(checkpoint),
+                    None => checkpoints.open = false,
+                }
+                continue;
+            }
+            event = recv(events.as_mut()), if events.is_some() => {
+                let Some(event) = event else {
+                    events = None;
+                    continue;
+                };
+                // the items sent before the event are queued already
+                while let Ok(item) = rx.try_recv() {
+                    batch.push(item);
+                }
+                Some(event)
+            }
+        };
+        written += write_batch(sink, &batch, checkpoints.as_mut(), event).await?;
+        batch.clear();
+    }
+
+    // events sent after the last item
+    while let Some(event) = events.as_mut().and_then(|events| events.try_recv().ok()) {
+        write_batch(sink, &[], checkpoints.as_mut(), Some(event)).await?;
+    }
+    let Some(mut checkpoints) = checkpoints else {
+        return Ok(written);
+    };
+    // checkpoints sent after the last item
+    while let Ok(checkpoint) = checkpoints.log.checkpoints.try_recv() {
+        checkpoints.pending.push_back(checkpoint);
+    }
+    let log = &checkpoints.log;
+    if let Some(checkpoint) = checkpoints
+        .pending
+        .into_iter()
+        .rfind(|checkpoint| checkpoint.items_emitted == log.items)
+    {
+        save_checkpoint(sink, checkpoint, log).await?;
+    }
+    Ok(written)
+}
+
+async fn recv<T>(rx: Option<&mut mpsc::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Writes a batch, saving the last checkpoint it reaches, then handles the event that follows
+// it. Returns the number of items written.
+async fn write_batch(
+    sink: &mut impl Sink,
+    batch: &[DataItem],
+    checkpoints: Option<&mut PendingCheckpoints>,
+    event: Option<WriterEvent>,
+) -> Result<u64, String> {
+    let mut rest = batch;
+    if let Some(checkpoints) = checkpoints {
+        // checkpoints are sent before the items that follow them, so every checkpoint the
+        // batch reaches is already queued
+        while let Ok(checkpoint) = checkpoints.log.checkpoints.try_recv() {
+            checkpoints.pending.push_back(checkpoint);
+        }
+
+        // the batch is split at the last checkpoint it reaches, so that the saved output
+        // holds exactly the items the checkpoint counts
+        let log = &mut checkpoints.log;
+        let end = log.items + batch.len() as u64;
+        let mut ready = None;
+        while let Some(checkpoint) = checkpoints.pending.pop_front() {
+            if checkpoint.items_emitted > end {
+                checkpoints.pending.push_front(checkpoint);
+                break;
+            }
+            if checkpoint.items_emitted >= log.items {
+                ready = Some(checkpoint);
+            }
+        }
+        if let Some(checkpoint) = ready {
+            let split = (checkpoint.items_emitted - log.items) as usize;
+            if split > 0 {
+                sink.write(&batch[..split]).await?;
+            }
+            log.items += split as u64;
+            save_checkpoint(sink, checkpoint, log).await?;
+            rest = &batch[split..];
+        }
+        log.items += rest.len() as u64;
+    }
+
+    match event {
+        Some(WriterEvent::BundleDone(done)) => sink.finish_bundle(rest, &done).await?,
+        Some(WriterEvent::Flush(flushed)) => {
+            if !rest.is_empty() {
+                sink.write(rest).await?;
+            }
+            let result = sink.flush().await.map(|_| ());
+            // the sender may have stopped waiting
+            let _ = flushed.send(result.clone());
+            result?;
+        }
+        None if !rest.is_empty() => sink.write(rest).await?,
+        None => {}
+    }
+    Ok(batch.len() as u64)
+}
+
+async fn save_checkpoint(
+    sink: &mut impl Sink,
+    mut checkpoint: Checkpoint,
+    log: &CheckpointLog,
+) -> Result<(), String> {
+    checkpoint.output_len = sink.flush().await?;
+    checkpoint.save(&log.path).await
+}
+
+// Opens the output file cut to `keep` bytes, or whole, and returns its length.
+async fn open_output(path: &Path, keep: Option<u64>) -> Result<(File, u64), String> {
+    let opened = async {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(keep == Some(0))
+            .open(path)
+            .await?;
+        let len = match keep {
+            Some(len) => len,
+            None => file.metadata().await?.len(),
+        };
+        file.set_len(len).await?;
+        Ok::<_, std::io::Error>((file, len))
+    };
+    opened
+        .await
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
@@ -0,0 +1,110 @@
+use std::io::{BufRead, SeekFrom};
+use std::path::PathBuf;
+
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use super::record::{BinaryRecord, OwnedBinaryRecord};
+use super::{open_output, Sink};
+use crate::DataItem;
+
+/// Writes every item to a file as a MessagePack map, one after the other.
+///
+/// The maps hold the fields of the JSON output, except that the signature, owner, target,
+/// anchor and tags keep their raw bytes, plus the `signature_type`, `offset`, `size`,
+/// `header_size` and `ancestry` of the item. `MessagePackReader` reads them back.
+#[derive(Debug)]
+pub struct MessagePackSink {
+    file: File,
+    path: PathBuf,
+    len: u64,
+}
+
+impl MessagePackSink {
+    /// Creates the file, truncating it when it exists.
+    pub async fn create(path: impl Into<PathBuf>) -> Result<Self, String> {
+        Self::open(path.into(), Some(0)).await
+    }
+
+    /// Writes after the items already in the file.
+    pub async fn append(path: impl Into<PathBuf>) -> Result<Self, String> {
+        Self::open(path.into(), None).await
+    }
+
+    /// Cuts the file back to `len` bytes, the length a checkpoint recorded, and writes after.
+    pub async fn resume(path: impl Into<PathBuf>, len: u64) -> Result<Self, String> {
+        Self::open(path.into(), Some(len)).await
+    }
+
+    async fn open(path: PathBuf, keep: Option<u64>) -> Result<Self, String> {
+        let (mut file, len) = open_output(&path, keep).await?;
+        file.seek(SeekFrom::Start(len))
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Ok(MessagePackSink { file, path, len })
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        format!("Failed to write {}: {}", self.path.display(), e)
+    }
+}
+
+impl Sink for MessagePackSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        let mut bytes = Vec::new();
+        for item in items {
+            rmp_serde::encode::write_named(&mut bytes, &BinaryRecord::from(item))
+                .map_err(|e| self.error(e))?;
+        }
+        self.file
+            .write_all(&bytes)
+            .await
+            .map_err(|e| self.error(e))?;
+        self.len += bytes.len() as u64;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        self.file.flush().await.map_err(|e| self.error(e))?;
+        self.file.sync_data().await.map_err(|e| self.error(e))?;
+        Ok(Some(self.len))
+    }
+
+    async fn finish(mut self) -> Result<(), String> {
+        self.file.flush().await.map_err(|e| self.error(e))
+    }
+}
+
+/// Reads back the items written by a `MessagePackSink`. Iteration stops after an error.
+pub struct MessagePackReader<R: BufRead> {
+    reader: R,
+    failed: bool,
+}
+
+impl<R: BufRead> MessagePackReader<R> {
+    pub fn new(reader: R) -> Self {
+        MessagePackReader {
+            reader,
+            failed: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MessagePackReader<R> {
+    type Item = Result<DataItem, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let item = match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => rmp_serde::from_read::<_, OwnedBinaryRecord>(&mut self.reader)
+                .map_err(|e| format!("Failed to decode an item: {}", e))
+                .and_then(DataItem::try_from),
+            Err(e) => Err(format!("Failed to read the items: {}", e)),
+        };
+        self.failed = item.is_err();
+        Some(item)
+    }
+}
@@ -0,0 +1,229 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    BooleanBuilder, ListBuilder, StringBuilder, StructBuilder, UInt16Builder, UInt64Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Fields, Schema, SchemaRef};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use parquet::arrow::AsyncArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use tokio::fs::File;
+
+use super::Sink;
+use crate::DataItem;
+
+/// Number of items in a row group by default.
+pub const DEFAULT_ROW_GROUP_ROWS: usize = 128 * 1024;
+
+/// Size in bytes of the buffered items after which a row group is written by default.
+pub const DEFAULT_ROW_GROUP_BYTES: usize = 128 * 1024 * 1024;
+
+/// Row group sizing of a `ParquetSink`. A row group is written as soon as either limit is
+/// reached.
+#[derive(Debug, Clone)]
+pub struct ParquetOptions {
+    /// Maximum number of items in a row group.
+    pub row_group_rows: usize,
+    /// Size in bytes of the buffered items after which the row group is written.
+    pub row_group_bytes: usize,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            row_group_rows: DEFAULT_ROW_GROUP_ROWS,
+            row_group_bytes: DEFAULT_ROW_GROUP_BYTES,
+        }
+    }
+}
+
+/// Writes the items to an Apache Parquet file, one row per item, compressed with zstd.
+///
+/// Columns: `id`, `owner_address`, `target`, `anchor` (base64url, null when absent),
+/// `signature_type`, `bundled_in`, `ancestry` (list of the bundle ids from the root bundle),
+/// `is_bundle`, `offset`, `size`, `data_offset`, `data_size` (positions in the root bundle
+/// data) and `tags`, a list of `name`, `value` structs with the text of the JSON output.
+///
+/// The file is only readable once `finish` wrote its footer, so it cannot be appended to or
+/// resumed.
+pub struct ParquetSink {
+    writer: AsyncArrowWriter<File>,
+    path: PathBuf,
+    schema: SchemaRef,
+    row_group_bytes: usize,
+}
+
+impl std::fmt::Debug for ParquetSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParquetSink")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+fn tag_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("value", DataType::Utf8, false),
+    ])
+}
+
+fn list_item(data_type: DataType) -> Field {
+    Field::new("item", data_type, false)
+}
+
+/// Arrow schema of the rows written by a `ParquetSink`.
+pub fn parquet_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("owner_address", DataType::Utf8, false),
+        Field::new("target", DataType::Utf8, true),
+        Field::new("anchor", DataType::Utf8, true),
+        Field::new("signature_type", DataType::UInt16, false),
+        Field::new("bundled_in", DataType::Utf8, false),
+        Field::new(
+            "ancestry",
+            DataType::List(Arc::new(list_item(DataType::Utf8))),
+            false,
+        ),
+        Field::new("is_bundle", DataType::Boolean, false),
+        Field::new("offset", DataType::UInt64, false),
+        Field::new("size", DataType::UInt64, false),
+        Field::new("data_offset", DataType::UInt64, false),
+        Field::new("data_size", DataType::UInt64, false),
+        Field::new(
+            "tags",
+            DataType::List(Arc::new(list_item(DataType::Struct(tag_fields())))),
+            false,
+        ),
+    ]))
+}
+
+impl ParquetSink {
+    /// Creates the file, truncating it when it exists.
+    pub async fn create(
+        path: impl Into<PathBuf>,
+        options: &ParquetOptions,
+    ) -> Result<Self, String> {
+        let path = path.into();
+        let file = File::create(&path)
+            .await
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(options.row_group_rows.max(1))
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let schema = parquet_schema();
+        let writer = AsyncArrowWriter::try_new(file, schema.clone(), Some(properties))
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Ok(ParquetSink {
+            writer,
+            path,
+            schema,
+            row_group_bytes: options.row_group_bytes,
+        })
+    }
+
+    fn batch(&self, items: &[DataItem]) -> Result<RecordBatch, String> {
+        let encode = |bytes: Option<&[u8; 32]>| bytes.map(|b| BASE64_URL.encode(b));
+        let mut id = StringBuilder::new();
+        let mut owner_address = StringBuilder::new();
+        let mut target = StringBuilder::new();
+        let mut anchor = StringBuilder::new();
+        let mut signature_type = UInt16Builder::with_capacity(items.len());
+        let mut bundled_in = StringBuilder::new();
+        let mut ancestry =
+            ListBuilder::new(StringBuilder::new()).with_field(list_item(DataType::Utf8));
+        let mut is_bundle = BooleanBuilder::with_capacity(items.len());
+        let mut offset = UInt64Builder::with_capacity(items.len());
+        let mut size = UInt64Builder::with_capacity(items.len());
+        let mut data_offset = UInt64Builder::with_capacity(items.len());
+        let mut data_size = UInt64Builder::with_capacity(items.len());
+        let mut tags = ListBuilder::new(StructBuilder::from_fields(tag_fields(), 0))
+            .with_field(list_item(DataType::Struct(tag_fields())));
+
+        for item in items {
+            id.append_value(BASE64_URL.encode(item.id()));
+            owner_address.append_value(item.owner_address());
+            target.append_option(encode(item.target()));
+            anchor.append_option(encode(item.anchor()));
+            signature_type.append_value(item.signature_type());
+            bundled_in.append_value(item.bundled_in());
+            for bundle in item.ancestry() {
+                ancestry.values().append_value(bundle);
+            }
+            ancestry.append(true);
+            is_bundle.append_value(item.is_bundle());
+            offset.append_value(item.offset());
+            size.append_value(item.size());
+            data_offset.append_value(item.data_offset());
+            data_size.append_value(item.data_size());
+
+            let pairs = tags.values();
+            for tag in item.tags() {
+                let (name, value) = tag.to_text();
+                string_field(pairs, 0).append_value(name);
+                string_field(pairs, 1).append_value(value);
+                pairs.append(true);
+            }
+            tags.append(true);
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(id.finish()),
+            Arc::new(owner_address.finish()),
+            Arc::new(target.finish()),
+            Arc::new(anchor.finish()),
+            Arc::new(signature_type.finish()),
+            Arc::new(bundled_in.finish()),
+            Arc::new(ancestry.finish()),
+            Arc::new(is_bundle.finish()),
+            Arc::new(offset.finish()),
+            Arc::new(size.finish()),
+            Arc::new(data_offset.finish()),
+            Arc::new(data_size.finish()),
+            Arc::new(tags.finish()),
+        ];
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        format!("Failed to write {}: {}", self.path.display(), e)
+    }
+}
+
+fn string_field(builder: &mut StructBuilder, index: usize) -> &mut StringBuilder {
+    builder
+        .field_builder::<StringBuilder>(index)
+        .expect("tag fields are strings")
+}
+
+impl Sink for ParquetSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        let batch = self.batch(items)?;
+        self.writer.write(&batch).await.map_err(|e| self.error(e))?;
+        if self.writer.in_progress_size() >= self.row_group_bytes {
+            self.writer.flush().await.map_err(|e| self.error(e))?;
+        }
+        Ok(())
+    }
+
+    // the rows are written as a row group, but the file has no footer before `finish`
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        self.writer.flush().await.map_err(|e| self.error(e))?;
+        Ok(None)
+    }
+
+    async fn finish(self) -> Result<(), String> {
+        let path = self.path;
+        self.writer
+            .close()
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{Client, NoTls, Transaction};
+
+use super::{BundleDone, Sink};
+use crate::{DataItem, RootTransaction};
+
+// Schema changes, applied in order. A database is at the version of the number of migrations
+// recorded in `schema_migrations`, so released migrations are never edited, only added to.
+const MIGRATIONS: &[&str] = &[
+    "
+CREATE TABLE items (
+    id TEXT PRIMARY KEY,
+    signature BYTEA NOT NULL,
+    signature_type INTEGER NOT NULL,
+    owner BYTEA NOT NULL,
+    owner_address TEXT NOT NULL,
+    target TEXT,
+    anchor TEXT,
+    bundled_in TEXT NOT NULL,
+    root_tx TEXT NOT NULL,
+    is_bundle BOOLEAN NOT NULL,
+    \"offset\" BIGINT NOT NULL,
+    size BIGINT NOT NULL,
+    data_offset BIGINT NOT NULL,
+    data_size BIGINT NOT NULL,
+    ancestry TEXT[] NOT NULL
+);
+CREATE INDEX items_owner_address ON items (owner_address);
+CREATE INDEX items_target ON items (target);
+CREATE INDEX items_bundled_in ON items (bundled_in);
+CREATE INDEX items_root_tx ON items (root_tx);
+
+CREATE TABLE tags (
+    item_id TEXT NOT NULL REFERENCES items (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (item_id, position)
+);
+CREATE INDEX tags_name_value ON tags (name, value);
+
+CREATE TABLE root_transactions (
+    id TEXT PRIMARY KEY,
+    owner_address TEXT,
+    data_size BIGINT,
+    block_height BIGINT,
+    block_hash TEXT,
+    block_timestamp BIGINT,
+    status TEXT NOT NULL CHECK (status IN ('indexing', 'indexed')),
+    indexed_to BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+",
+    "
+ALTER TABLE root_transactions DROP CONSTRAINT root_transactions_status_check;
+ALTER TABLE root_transactions ADD CONSTRAINT root_transactions_status_check
+    CHECK (status IN ('indexing', 'indexed', 'failed'));
+ALTER TABLE root_transactions ADD COLUMN items BIGINT;
+ALTER TABLE root_transactions ADD COLUMN errors TEXT[];
+",
+];
+
+// key of the advisory lock held while migrating, so that concurrent sinks migrate once
+const MIGRATION_LOCK: i64 = 1040;
+
+const ITEM_COLUMNS: &str = "id, signature, signature_type, owner, owner_address, target, anchor, \
+     bundled_in, root_tx, is_bundle, \"offset\", size, data_offset, data_size, ancestry";
+
+const ITEM_TYPES: &[Type] = &[
+    Type::TEXT,
+    Type::BYTEA,
+    Type::INT4,
+    Type::BYTEA,
+    Type::TEXT,
+    Type::TEXT,
+    Type::TEXT,
+    Type::TEXT,
+    Type::TEXT,
+    Type::BOOL,
+    Type::INT8,
+    Type::INT8,
+    Type::INT8,
+    Type::INT8,
+    Type::TEXT_ARRAY,
+];
+
+const TAG_TYPES: &[Type] = &[Type::TEXT, Type::INT4, Type::TEXT, Type::TEXT];
+
+// the staged rows replace the stored ones, an item written twice in a batch is stored once
+const UPSERT_ITEMS: &str = "
+INSERT INTO items SELECT DISTINCT ON (id) * FROM staged_items ORDER BY id
+ON CONFLICT (id) DO UPDATE SET
+    signature = excluded.signature,
+    signature_type = excluded.signature_type,
+    owner = excluded.owner,
+    owner_address = excluded.owner_address,
+    target = excluded.target,
+    anchor = excluded.anchor,
+    bundled_in = excluded.bundled_in,
+    root_tx = excluded.root_tx,
+    is_bundle = excluded.is_bundle,
+    \"offset\" = excluded.\"offset\",
+    size = excluded.size,
+    data_offset = excluded.data_offset,
+    data_size = excluded.data_size,
+    ancestry = excluded.ancestry;
+DELETE FROM tags WHERE item_id IN (SELECT id FROM staged_items);
+INSERT INTO tags
+SELECT DISTINCT ON (item_id, position) * FROM staged_tags ORDER BY item_id, position;
+";
+
+// items of a root transaction are being written, until its outcome is recorded
+const UPSERT_ROOT_TX: &str = "
+INSERT INTO root_transactions AS stored (
+    id, owner_address, data_size, block_height, block_hash, block_timestamp, status, indexed_to
+) VALUES ($1, $2, $3, $4, $5, $6, 'indexing', $7)
+ON CONFLICT (id) DO UPDATE SET
+    owner_address = COALESCE(excluded.owner_address, stored.owner_address),
+    data_size = COALESCE(excluded.data_size, stored.data_size),
+    block_height = COALESCE(excluded.block_height, stored.block_height),
+    block_hash = COALESCE(excluded.block_hash, stored.block_hash),
+    block_timestamp = COALESCE(excluded.block_timestamp, stored.block_timestamp),
+    indexed_to = GREATEST(stored.indexed_to, excluded.indexed_to),
+    status = 'indexing',
+    updated_at = now()
+";
+
+// outcome of the processing of a root transaction, with or without items
+const FINISH_ROOT_TX: &str = "
+INSERT INTO root_transactions AS stored (
+    id, owner_address, data_size, block_height, block_hash, block_timestamp, status, indexed_to,
+    items, errors
+) VALUES ($1, $2, $3, $4, $5, $6, $7, 0, $8, $9)
+ON CONFLICT (id) DO UPDATE SET
+    owner_address = COALESCE(excluded.owner_address, stored.owner_address),
+    data_size = COALESCE(excluded.data_size, stored.data_size),
+    block_height = COALESCE(excluded.block_height, stored.block_height),
+    block_hash = COALESCE(excluded.block_hash, stored.block_hash),
+    block_timestamp = COALESCE(excluded.block_timestamp, stored.block_timestamp),
+    status = excluded.status,
+    items = excluded.items,
+    errors = excluded.errors,
+    updated_at = now()
+";
+
+/// Writes the items to a PostgreSQL database, creating or migrating its tables on connection.
+///
+/// Every batch is written in one transaction: the items and their tags are copied in the
+/// binary format to staging tables, then upserted by item id, so that indexing the same bundle
+/// again does not duplicate rows. The same transaction records the progress of every root
+/// transaction in `root_transactions`: its status is `indexing` and `indexed_to` is the end of
+/// the furthest item written. Once the bundle is processed, its last items are written in one
+/// transaction with its outcome: the status turns to `indexed`, or to `failed` when the
+/// processing failed or found errors, listed in `errors`, along with the number of items.
+pub struct PostgresSink {
+    client: Client,
+}
+
+impl std::fmt::Debug for PostgresSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresSink").finish_non_exhaustive()
+    }
+}
+
+// Progress of a root transaction in a batch.
+struct RootProgress<'a> {
+    root_tx: Option<&'a RootTransaction>,
+    indexed_to: u64,
+}
+
+impl PostgresSink {
+    /// Connects to the database, as `host=localhost user=postgres` or
+    /// `postgresql://postgres@localhost`, and applies the missing migrations.
+    pub async fn connect(params: &str) -> Result<Self, String> {
+        let (mut client, connection) = tokio_postgres::connect(params, NoTls)
+            .await
+            .map_err(|e| format!("Failed to connect to the database: {}", describe(&e)))?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::error!("Database connection failed: {}", e);
+            }
+        });
+        migrate(&mut client)
+            .await
+            .map_err(|e| format!("Failed to migrate the database: {}", describe(&e)))?;
+        Ok(PostgresSink { client })
+    }
+
+    /// Number of migrations the sink applies, the version of an up to date database.
+    pub fn schema_version() -> usize {
+        MIGRATIONS.len()
+    }
+}
+
+// The message of the server, which the errors of the client only hold as their source.
+fn describe(e: &tokio_postgres::Error) -> String {
+    match e.as_db_error() {
+        Some(db_error) => db_error.to_string(),
+        None => e.to_string(),
+    }
+}
+
+async fn migrate(client: &mut Client) -> Result<(), tokio_postgres::Error> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])
+        .await?;
+    transaction
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+    let applied: i64 = transaction
+        .query_one("SELECT COUNT(*) FROM schema_migrations", &[])
+        .await?
+        .get(0);
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        transaction.batch_execute(migration).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version) VALUES ($1)",
+                &[&(index as i32 + 1)],
+            )
+            .await?;
+    }
+    transaction.commit().await
+}
+
+async fn copy_items(
+    transaction: &Transaction<'_>,
+    items: &[DataItem],
+) -> Result<(), tokio_postgres::Error> {
+    transaction
+        .batch_execute(
+            "CREATE TEMP TABLE staged_items (LIKE items) ON COMMIT DROP;
+             CREATE TEMP TABLE staged_tags (LIKE tags) ON COMMIT DROP;",
+        )
+        .await?;
+
+    let encode = |bytes: Option<&[u8; 32]>| bytes.map(|b| BASE64_URL.encode(b));
+    let copy = transaction
+        .copy_in(&format!(
+            "COPY staged_items ({}) FROM STDIN BINARY",
+            ITEM_COLUMNS
+        ))
+        .await?;
+    let writer = BinaryCopyInWriter::new(copy, ITEM_TYPES);
+    tokio::pin!(writer);
+    for item in items {
+        let ancestry = ancestry(item);
+        let values: [&(dyn ToSql + Sync); 15] = [
+            &BASE64_URL.encode(item.id()),
+            &item.signature(),
+            &(item.signature_type() as i32),
+            &item.owner(),
+            &item.owner_address(),
+            &encode(item.target()),
+            &encode(item.anchor()),
+            &item.bundled_in(),
+            &ancestry[0],
+            &item.is_bundle(),
+            &(item.offset() as i64),
+            &(item.size() as i64),
+            &(item.data_offset() as i64),
+            &(item.data_size() as i64),
+            &ancestry,
+        ];
+        writer.as_mut().write(&values).await?;
+    }
+    writer.finish().await?;
+
+    let copy = transaction
+        .copy_in("COPY staged_tags (item_id, position, name, value) FROM STDIN BINARY")
+        .await?;
+    let writer = BinaryCopyInWriter::new(copy, TAG_TYPES);
+    tokio::pin!(writer);
+    for item in items {
+        let id = BASE64_URL.encode(item.id());
+        for (position, tag) in item.tags().iter().enumerate() {
+            let (name, value) = tag.to_text();
+            writer
+                .as_mut()
+                .write(&[&id, &(position as i32), &name, &value])
+                .await?;
+        }
+    }
+    writer.finish().await?;
+    Ok(())
+}
+
+// Bundles holding the item, from the root transaction to `bundled_in`.
+fn ancestry(item: &DataItem) -> Vec<String> {
+    match item.ancestry() {
+        [] => vec![item.bundled_in().to_string()],
+        ancestry => ancestry.to_vec(),
+    }
+}
+
+async fn record_progress(
+    transaction: &Transaction<'_>,
+    items: &[DataItem],
+) -> Result<(), tokio_postgres::Error> {
+    let mut roots: BTreeMap<String, RootProgress> = BTreeMap::new();
+    for item in items {
+        let root = ancestry(item).swap_remove(0);
+        let progress = roots.entry(root).or_insert(RootProgress {
+            root_tx: None,
+            indexed_to: 0,
+        });
+        progress.root_tx = progress.root_tx.or(item.root_tx());
+        progress.indexed_to = progress.indexed_to.max(item.offset() + item.size());
+    }
+
+    let statement = transaction.prepare(UPSERT_ROOT_TX).await?;
+    for (id, progress) in &roots {
+        let root_tx = progress.root_tx;
+        let number = |value: Option<u64>| value.map(|value| value as i64);
+        transaction
+            .execute(
+                &statement,
+                &[
+                    id,
+                    &root_tx.map(|root_tx| &root_tx.owner_address),
+                    &number(root_tx.map(|root_tx| root_tx.data_size)),
+                    &number(root_tx.and_then(|root_tx| root_tx.block_height)),
+                    &root_tx.and_then(|root_tx| root_tx.block_hash.as_ref()),
+                    &number(root_tx.and_then(|root_tx| root_tx.block_timestamp)),
+                    &(progress.indexed_to as i64),
+                ],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+async fn record_outcome(
+    transaction: &Transaction<'_>,
+    done: &BundleDone,
+) -> Result<(), tokio_postgres::Error> {
+    let summary = &done.summary;
+    let root_tx = done.root_tx.as_deref();
+    let number = |value: Option<u64>| value.map(|value| value as i64);
+    let status = if summary.errors.is_empty() {
+        "indexed"
+    } else {
+        "failed"
+    };
+    transaction
+        .execute(
+            FINISH_ROOT_TX,
+            &[
+                &summary.tx_id,
+                &root_tx.map(|root_tx| &root_tx.owner_address),
+                &number(root_tx.map(|root_tx| root_tx.data_size)),
+                &number(root_tx.and_then(|root_tx| root_tx.block_height)),
+                &root_tx.and_then(|root_tx| root_tx.block_hash.as_ref()),
+                &number(root_tx.and_then(|root_tx| root_tx.block_timestamp)),
+                &status,
+                &(summary.items as i64),
+                &summary.errors,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+impl PostgresSink {
+    // Writes the items, then the outcome of a bundle, in one transaction.
+    async fn write_transaction(
+        &mut self,
+        items: &[DataItem],
+        done: Option<&BundleDone>,
+    ) -> Result<(), String> {
+        let written = async {
+            let transaction = self.client.transaction().await?;
+            if !items.is_empty() {
+                copy_items(&transaction, items).await?;
+                transaction.batch_execute(UPSERT_ITEMS).await?;
+                record_progress(&transaction, items).await?;
+            }
+            if let Some(done) = done {
+                record_outcome(&transaction, done).await?;
+            }
+            transaction.commit().await
+        };
+        written
+            .await
+            .map_err(|e| format!("Failed to write to the database: {}", describe(&e)))
+    }
+}
+
+impl Sink for PostgresSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        self.write_transaction(items, None).await
+    }
+
+    async fn finish_bundle(&mut self, items: &[DataItem], done: &BundleDone) -> Result<(), String> {
+        self.write_transaction(items, Some(done)).await
+    }
+
+    // every batch is committed by `write`, and rows written again are upserted, so the
+    // database does not need to be cut back
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        Ok(None)
+    }
+
+    async fn finish(self) -> Result<(), String> {
+        Ok(())
+    }
+}
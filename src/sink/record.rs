@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_bytes::{ByteBuf, Bytes};
+
+use crate::tags::Tag;
+use crate::transaction::RootTransaction;
+use crate::DataItem;
+
+// Record written by the binary sinks for every `DataItem`. Unlike the JSON record, the
+// signature, owner, target, anchor and tags keep their raw bytes, and the position of the
+// item is kept so that the `DataItem` can be read back whole.
+#[derive(Serialize)]
+pub(crate) struct BinaryRecord<'a> {
+    signature: &'a Bytes,
+    signature_type: u16,
+    owner: &'a Bytes,
+    target: Option<&'a Bytes>,
+    anchor: Option<&'a Bytes>,
+    tags: Vec<BinaryTag<'a>>,
+    bundled_in: &'a str,
+    is_bundle: bool,
+    root_tx: Option<&'a RootTransaction>,
+    offset: u64,
+    size: u64,
+    header_size: u64,
+    ancestry: &'a [String],
+}
+
+#[derive(Serialize)]
+struct BinaryTag<'a> {
+    name: &'a Bytes,
+    value: &'a Bytes,
+}
+
+impl<'a> From<&'a DataItem> for BinaryRecord<'a> {
+    fn from(item: &'a DataItem) -> Self {
+        let bytes = |bytes: Option<&'a [u8; 32]>| bytes.map(|b| Bytes::new(b));
+        BinaryRecord {
+            signature: Bytes::new(&item.signature),
+            signature_type: item.signature_type,
+            owner: Bytes::new(&item.owner),
+            target: bytes(item.target.as_ref()),
+            anchor: bytes(item.anchor.as_ref()),
+            tags: item
+                .tags
+                .iter()
+                .map(|tag| BinaryTag {
+                    name: Bytes::new(tag.name()),
+                    value: Bytes::new(tag.value()),
+                })
+                .collect(),
+            bundled_in: &item.bundled_in,
+            is_bundle: item.is_bundle,
+            root_tx: item.root_tx.as_deref(),
+            offset: item.offset,
+            size: item.size,
+            header_size: item.header_size,
+            ancestry: &item.ancestry,
+        }
+    }
+}
+
+// `BinaryRecord` as it is read back.
+#[derive(Deserialize)]
+pub(crate) struct OwnedBinaryRecord {
+    signature: ByteBuf,
+    signature_type: u16,
+    owner: ByteBuf,
+    target: Option<ByteBuf>,
+    anchor: Option<ByteBuf>,
+    tags: Vec<Tag>,
+    bundled_in: String,
+    is_bundle: bool,
+    root_tx: Option<RootTransaction>,
+    offset: u64,
+    size: u64,
+    header_size: u64,
+    ancestry: Vec<String>,
+}
+
+impl TryFrom<OwnedBinaryRecord> for DataItem {
+    type Error = String;
+
+    fn try_from(record: OwnedBinaryRecord) -> Result<Self, Self::Error> {
+        let address = |bytes: Option<ByteBuf>, field: &str| {
+            bytes
+                .map(|b| {
+                    <[u8; 32]>::try_from(b.as_slice())
+                        .map_err(|_| format!("The {} of an item is {} bytes long", field, b.len()))
+                })
+                .transpose()
+        };
+        Ok(DataItem {
+            signature: record.signature.into_vec(),
+            owner: record.owner.into_vec(),
+            target: address(record.target, "target")?,
+            anchor: address(record.anchor, "anchor")?,
+            tags: record.tags,
+            bundled_in: record.bundled_in,
+            is_bundle: record.is_bundle,
+            root_tx: record.root_tx.map(Arc::new),
+            signature_type: record.signature_type,
+            offset: record.offset,
+            size: record.size,
+            header_size: record.header_size,
+            ancestry: record.ancestry,
+        })
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use super::Sink;
+use crate::DataItem;
+
+/// Tables of the database written by a `SqliteSink`.
+///
+/// Ids, addresses and the target and anchor are base64url text, tag names and values are the
+/// text of the JSON output. `bundles` has a row for the root transaction and for every nested
+/// bundle, with the number of items and nested bundles written directly in it.
+pub const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS items (
+    id TEXT PRIMARY KEY NOT NULL,
+    signature BLOB NOT NULL,
+    signature_type INTEGER NOT NULL,
+    owner BLOB NOT NULL,
+    owner_address TEXT NOT NULL,
+    target TEXT,
+    anchor TEXT,
+    bundled_in TEXT NOT NULL,
+    root_tx TEXT NOT NULL,
+    is_bundle INTEGER NOT NULL,
+    offset INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    data_offset INTEGER NOT NULL,
+    data_size INTEGER NOT NULL,
+    block_height INTEGER
+);
+CREATE INDEX IF NOT EXISTS items_owner_address ON items (owner_address);
+CREATE INDEX IF NOT EXISTS items_target ON items (target);
+CREATE INDEX IF NOT EXISTS items_bundled_in ON items (bundled_in);
+CREATE INDEX IF NOT EXISTS items_root_tx ON items (root_tx);
+
+CREATE TABLE IF NOT EXISTS tags (
+    item_id TEXT NOT NULL REFERENCES items (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (item_id, position)
+);
+CREATE INDEX IF NOT EXISTS tags_name_value ON tags (name, value);
+
+CREATE TABLE IF NOT EXISTS bundles (
+    id TEXT PRIMARY KEY NOT NULL,
+    root TEXT NOT NULL,
+    parent TEXT,
+    depth INTEGER NOT NULL,
+    item_count INTEGER NOT NULL DEFAULT 0,
+    bundle_count INTEGER NOT NULL DEFAULT 0
+);
+CREATE INDEX IF NOT EXISTS bundles_root ON bundles (root);
+";
+
+const UPSERT_ITEM: &str = "
+INSERT INTO items (
+    id, signature, signature_type, owner, owner_address, target, anchor, bundled_in, root_tx,
+    is_bundle, offset, size, data_offset, data_size, block_height
+) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+ON CONFLICT (id) DO UPDATE SET
+    signature = excluded.signature,
+    signature_type = excluded.signature_type,
+    owner = excluded.owner,
+    owner_address = excluded.owner_address,
+    target = excluded.target,
+    anchor = excluded.anchor,
+    bundled_in = excluded.bundled_in,
+    root_tx = excluded.root_tx,
+    is_bundle = excluded.is_bundle,
+    offset = excluded.offset,
+    size = excluded.size,
+    data_offset = excluded.data_offset,
+    data_size = excluded.data_size,
+    block_height = excluded.block_height
+";
+
+const UPSERT_BUNDLE: &str = "
+INSERT INTO bundles (id, root, parent, depth) VALUES (?1, ?2, ?3, ?4)
+ON CONFLICT (id) DO UPDATE SET
+    root = excluded.root,
+    parent = excluded.parent,
+    depth = excluded.depth
+";
+
+/// Writes the items to a SQLite database, creating the tables of `SQLITE_SCHEMA` when they do
+/// not exist.
+///
+/// Every batch is written in one transaction. Items are upserted by id, replacing their tags,
+/// so that indexing the same bundle again does not duplicate rows, and the database is never
+/// truncated.
+pub struct SqliteSink {
+    // moved to the blocking tasks running the statements
+    connection: Option<Connection>,
+    path: PathBuf,
+}
+
+impl std::fmt::Debug for SqliteSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteSink")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+// Row of the `items` table, with the tags and position of the item.
+struct ItemRow {
+    id: String,
+    signature: Vec<u8>,
+    signature_type: u16,
+    owner: Vec<u8>,
+    owner_address: String,
+    target: Option<String>,
+    anchor: Option<String>,
+    bundled_in: String,
+    is_bundle: bool,
+    offset: u64,
+    size: u64,
+    data_offset: u64,
+    data_size: u64,
+    block_height: Option<u64>,
+    tags: Vec<(String, String)>,
+    // bundles holding the item, from the root transaction to `bundled_in`
+    ancestry: Vec<String>,
+}
+
+impl From<&DataItem> for ItemRow {
+    fn from(item: &DataItem) -> Self {
+        let encode = |bytes: Option<&[u8; 32]>| bytes.map(|b| BASE64_URL.encode(b));
+        let ancestry = match item.ancestry() {
+            [] => vec![item.bundled_in().to_string()],
+            ancestry => ancestry.to_vec(),
+        };
+        ItemRow {
+            id: BASE64_URL.encode(item.id()),
+            signature: item.signature().to_vec(),
+            signature_type: item.signature_type(),
+            owner: item.owner().to_vec(),
+            owner_address: item.owner_address(),
+            target: encode(item.target()),
+            anchor: encode(item.anchor()),
+            bundled_in: item.bundled_in().to_string(),
+            is_bundle: item.is_bundle(),
+            offset: item.offset(),
+            size: item.size(),
+            data_offset: item.data_offset(),
+            data_size: item.data_size(),
+            block_height: item.root_tx().and_then(|root_tx| root_tx.block_height),
+            tags: item.tags().iter().map(|tag| tag.to_text()).collect(),
+            ancestry,
+        }
+    }
+}
+
+// Position of a bundle, and the change of its counts in a batch.
+#[derive(Default)]
+struct BundleRow {
+    position: Option<(String, Option<String>, usize)>,
+    items: i64,
+    bundles: i64,
+}
+
+impl SqliteSink {
+    /// Opens the database, creating it and its tables when needed.
+    pub async fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let opened = {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || {
+                let connection = Connection::open(&path)?;
+                connection.pragma_update(None, "journal_mode", "WAL")?;
+                connection.pragma_update(None, "foreign_keys", true)?;
+                connection.execute_batch(SQLITE_SCHEMA)?;
+                Ok::<_, rusqlite::Error>(connection)
+            })
+            .await
+        };
+        let connection = opened
+            .map_err(|e| e.to_string())
+            .and_then(|connection| connection.map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Ok(SqliteSink {
+            connection: Some(connection),
+            path,
+        })
+    }
+
+    // Runs `statements` on the connection in a blocking task.
+    async fn run<T: Send + 'static>(
+        &mut self,
+        statements: impl FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, String> {
+        let mut connection = self
+            .connection
+            .take()
+            .ok_or_else(|| self.error("the connection was lost by a previous failure"))?;
+        let (connection, result) = tokio::task::spawn_blocking(move || {
+            let result = statements(&mut connection);
+            (connection, result)
+        })
+        .await
+        .map_err(|e| self.error(e))?;
+        self.connection = Some(connection);
+        result.map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        format!("Failed to write {}: {}", self.path.display(), e)
+    }
+}
+
+// Upserts the items, their tags and their bundles in one transaction.
+fn upsert(connection: &mut Connection, rows: &[ItemRow]) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    let mut bundles: BTreeMap<String, BundleRow> = BTreeMap::new();
+    for row in rows {
+        // an item written again is moved out of the bundle it was counted in
+        if let Some((bundled_in, is_bundle)) = previous_bundle(&transaction, &row.id)? {
+            let previous = bundles.entry(bundled_in).or_default();
+            previous.items -= 1;
+            previous.bundles -= is_bundle as i64;
+        }
+        upsert_item(&transaction, row)?;
+
+        let root = &row.ancestry[0];
+        let depth = row.ancestry.len() - 1;
+        let parent = depth
+            .checked_sub(1)
+            .map(|index| row.ancestry[index].clone());
+        let bundle = bundles.entry(row.bundled_in.clone()).or_default();
+        bundle.position = Some((root.clone(), parent, depth));
+        bundle.items += 1;
+        bundle.bundles += row.is_bundle as i64;
+        if row.is_bundle {
+            bundles.entry(row.id.clone()).or_default().position =
+                Some((root.clone(), Some(row.bundled_in.clone()), depth + 1));
+        }
+    }
+
+    {
+        let mut upsert_bundle = transaction.prepare_cached(UPSERT_BUNDLE)?;
+        let mut count = transaction.prepare_cached(
+            "UPDATE bundles SET item_count = item_count + ?2, bundle_count = bundle_count + ?3
+             WHERE id = ?1",
+        )?;
+        for (id, bundle) in &bundles {
+            if let Some((root, parent, depth)) = &bundle.position {
+                upsert_bundle.execute(params![id, root, parent, *depth as i64])?;
+            }
+            count.execute(params![id, bundle.items, bundle.bundles])?;
+        }
+    }
+    transaction.commit()
+}
+
+fn previous_bundle(
+    transaction: &Transaction,
+    id: &str,
+) -> rusqlite::Result<Option<(String, bool)>> {
+    transaction
+        .prepare_cached("SELECT bundled_in, is_bundle FROM items WHERE id = ?1")?
+        .query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .optional()
+}
+
+fn upsert_item(transaction: &Transaction, row: &ItemRow) -> rusqlite::Result<()> {
+    transaction.prepare_cached(UPSERT_ITEM)?.execute(params![
+        row.id,
+        row.signature,
+        row.signature_type,
+        row.owner,
+        row.owner_address,
+        row.target,
+        row.anchor,
+        row.bundled_in,
+        row.ancestry[0],
+        row.is_bundle,
+        row.offset as i64,
+        row.size as i64,
+        row.data_offset as i64,
+        row.data_size as i64,
+        row.block_height.map(|height| height as i64),
+    ])?;
+    transaction
+        .prepare_cached("DELETE FROM tags WHERE item_id = ?1")?
+        .execute([&row.id])?;
+    let mut insert_tag = transaction.prepare_cached(
+        "INSERT INTO tags (item_id, position, name, value) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (position, (name, value)) in row.tags.iter().enumerate() {
+        insert_tag.execute(params![row.id, position as i64, name, value])?;
+    }
+    Ok(())
+}
+
+impl Sink for SqliteSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        let rows: Vec<ItemRow> = items.iter().map(ItemRow::from).collect();
+        self.run(move |connection| upsert(connection, &rows)).await
+    }
+
+    // every batch is committed by `write`, and rows written again are upserted, so the
+    // database does not need to be cut back
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        Ok(None)
+    }
+
+    async fn finish(mut self) -> Result<(), String> {
+        self.run(|connection| connection.execute_batch("PRAGMA optimize"))
+            .await
+    }
+}
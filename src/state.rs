@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+// Reads the JSON state saved at `path`, `None` when there is no such file.
+pub(crate) async fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    let json = match tokio::fs::read(path).await {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+    serde_json::from_slice(&json)
+        .map(Some)
+        .map_err(|e| format!("Invalid state in {}: {}", path.display(), e))
+}
+
+// Writes `value` to `path` as JSON. The file is replaced at once, after the new content
+// reached the disk, so a crash leaves either the previous or the new state.
+pub(crate) async fn save(path: &Path, value: &impl Serialize) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(value).map_err(|e| e.to_string())?;
+    let temporary = path.with_extension("tmp");
+    let written = async {
+        let mut file = tokio::fs::File::create(&temporary).await?;
+        file.write_all(&json).await?;
+        file.sync_all().await
+    };
+    written
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", temporary.display(), e))?;
+    tokio::fs::rename(&temporary, path)
+        .await
+        .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
@@ -0,0 +1,614 @@
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use redb::{Database, ReadTransaction, TableDefinition};
+
+use crate::sink::{BinaryRecord, OwnedBinaryRecord, Sink};
+use crate::DataItem;
+
+// item id to the MessagePack record of the item
+const ITEMS: TableDefinition<&str, &[u8]> = TableDefinition::new("items");
+// secondary indexes, keyed by value then item id so that the ids of a value are read in order
+// from any id
+const OWNERS: TableDefinition<(&str, &str), ()> = TableDefinition::new("owner_address");
+const TARGETS: TableDefinition<(&str, &str), ()> = TableDefinition::new("target");
+const TAGS: TableDefinition<(&str, &str, &str), ()> = TableDefinition::new("tags");
+const BUNDLED_IN: TableDefinition<(&str, &str), ()> = TableDefinition::new("bundled_in");
+const ROOT_TXS: TableDefinition<(&str, &str), ()> = TableDefinition::new("root_tx");
+
+// Number of entries counted per criterion to pick the one a query walks.
+const SELECTIVITY_SAMPLE: usize = 1024;
+
+// redb errors are large, and all end up as messages
+type StoreError = Box<dyn std::error::Error + Send + Sync>;
+// `(value, id)` keys of index entries, in order
+type IndexEntries = Box<dyn Iterator<Item = Result<(String, String), StoreError>>>;
+
+/// Secondary index of an `ItemStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemIndex<'a> {
+    /// Base64url address of the owner of the items.
+    Owner,
+    /// Base64url target of the items.
+    Target,
+    /// Value of the tags with this name, as the text of the JSON output.
+    Tag(&'a str),
+    /// Id of the bundle directly holding the items.
+    BundledIn,
+    /// Id of the layer 1 transaction holding the root bundle of the items.
+    RootTx,
+}
+
+/// Filter of `ItemStore::query`. The items match every criterion that is not empty, and one
+/// of its values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemFilter {
+    pub ids: Vec<String>,
+    pub owners: Vec<String>,
+    pub targets: Vec<String>,
+    /// Tags the items have, all of them unless `any_tag` is set.
+    pub tags: Vec<TagFilter>,
+    pub any_tag: bool,
+    pub bundled_in: Vec<String>,
+    pub root_txs: Vec<String>,
+}
+
+/// Tag with one of several values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagFilter {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+/// Embedded key-value store of the items, in a single redb file, for deployments without a
+/// SQL database.
+///
+/// Every item is stored whole under its base64url id, and indexed by owner address, target,
+/// tag name and value, bundle and root transaction. As a `Sink`, every batch is written in
+/// one transaction; an item written again replaces its record and is moved to its new bundle.
+#[derive(Clone)]
+pub struct ItemStore {
+    database: Arc<Database>,
+    path: PathBuf,
+}
+
+impl std::fmt::Debug for ItemStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ItemStore")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+// Record and index entries of an item.
+struct StoredItem {
+    id: String,
+    record: Vec<u8>,
+    owner_address: String,
+    target: Option<String>,
+    tags: Vec<(String, String)>,
+    bundled_in: String,
+    root_tx: String,
+}
+
+impl StoredItem {
+    fn new(item: &DataItem) -> Result<Self, String> {
+        let record = rmp_serde::to_vec_named(&BinaryRecord::from(item))
+            .map_err(|e| format!("Failed to encode an item: {}", e))?;
+        Ok(StoredItem {
+            id: BASE64_URL.encode(item.id()),
+            record,
+            owner_address: item.owner_address(),
+            target: item.target().map(|target| BASE64_URL.encode(target)),
+            tags: item.tags().iter().map(|tag| tag.to_text()).collect(),
+            bundled_in: item.bundled_in().to_string(),
+            root_tx: root_tx_id(item).to_string(),
+        })
+    }
+}
+
+// Items parsed without a walk have no ancestry, their bundle is the root transaction.
+pub(crate) fn root_tx_id(item: &DataItem) -> &str {
+    item.ancestry()
+        .first()
+        .map_or(item.bundled_in(), |root_tx| root_tx.as_str())
+}
+
+impl ItemStore {
+    /// Opens the store, creating the file and its tables when needed.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let opened = || {
+            let database = Database::create(&path)?;
+            let transaction = database.begin_write()?;
+            transaction.open_table(ITEMS)?;
+            for index in [OWNERS, TARGETS, BUNDLED_IN, ROOT_TXS] {
+                transaction.open_table(index)?;
+            }
+            transaction.open_table(TAGS)?;
+            transaction.commit()?;
+            Ok::<_, StoreError>(database)
+        };
+        let database = opened().map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        Ok(ItemStore {
+            database: Arc::new(database),
+            path,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The item with this base64url id.
+    pub fn get(&self, id: &str) -> Result<Option<DataItem>, String> {
+        let read = || {
+            let transaction = self.database.begin_read()?;
+            get_record(&transaction, id)
+        };
+        let record = read().map_err(|e| self.error(e))?;
+        record.map(|record| decode(&record)).transpose()
+    }
+
+    /// Ids of the items starting with `prefix`, in order, up to `limit` of them.
+    pub fn ids_with_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<String>, String> {
+        let read = || {
+            let transaction = self.database.begin_read()?;
+            let items = transaction.open_table(ITEMS)?;
+            let mut ids = Vec::new();
+            for entry in items.range(prefix..)? {
+                let (id, _) = entry?;
+                if ids.len() == limit || !id.value().starts_with(prefix) {
+                    break;
+                }
+                ids.push(id.value().to_string());
+            }
+            Ok::<_, StoreError>(ids)
+        };
+        read().map_err(|e| self.error(e))
+    }
+
+    /// Ids of the items whose `index` value is `value`, in order.
+    pub fn find(&self, index: ItemIndex, value: &str) -> Result<Vec<String>, String> {
+        let read = || {
+            let transaction = self.database.begin_read()?;
+            scan(&transaction, index, value, usize::MAX, true)
+        };
+        read().map_err(|e| self.error(e))
+    }
+
+    /// Ids of the items whose `index` value starts with `prefix`, ordered by value then id, up
+    /// to `limit` of them.
+    pub fn find_prefix(
+        &self,
+        index: ItemIndex,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, String> {
+        let read = || {
+            let transaction = self.database.begin_read()?;
+            scan(&transaction, index, prefix, limit, false)
+        };
+        read().map_err(|e| self.error(e))
+    }
+
+    /// The items matching `filter`, ordered by id, starting after the id `after` when given,
+    /// up to `limit` of them.
+    ///
+    /// The ids of the most selective criterion are walked in order from `after`, and the other
+    /// criteria are checked for each of them, so a page costs about the number of ids skipped
+    /// before it is full rather than the number of matching items.
+    pub fn query(
+        &self,
+        filter: &ItemFilter,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<DataItem>, String> {
+        let read = || {
+            let transaction = self.database.begin_read()?;
+            let mut records = Vec::new();
+            let criteria = Criterion::of(filter);
+            let mut selectivity = Vec::with_capacity(criteria.len());
+            for criterion in &criteria {
+                selectivity.push(criterion.estimate(&transaction, after)?);
+            }
+            let driver = (0..criteria.len()).min_by_key(|&index| selectivity[index]);
+            let Some(driver) = driver else {
+                // without criteria, every item matches
+                let start = after.map_or(Bound::Unbounded, Bound::Excluded);
+                let items = transaction.open_table(ITEMS)?;
+                for entry in items.range::<&str>((start, Bound::Unbounded))?.take(limit) {
+                    records.push(entry?.1.value().to_vec());
+                }
+                return Ok(records);
+            };
+            'ids: for id in criteria[driver].walk(&transaction, after)? {
+                if records.len() == limit {
+                    break;
+                }
+                let id = id?;
+                for (index, criterion) in criteria.iter().enumerate() {
+                    if index != driver && !criterion.contains(&transaction, &id)? {
+                        continue 'ids;
+                    }
+                }
+                // ids given by the filter may not be stored
+                if let Some(record) = get_record(&transaction, &id)? {
+                    records.push(record);
+                }
+            }
+            Ok::<_, StoreError>(records)
+        };
+        let records = read().map_err(|e| self.error(e))?;
+        records.iter().map(|record| decode(record)).collect()
+    }
+
+    fn insert(&self, items: &[StoredItem]) -> Result<(), StoreError> {
+        let transaction = self.database.begin_write()?;
+        {
+            let mut records = transaction.open_table(ITEMS)?;
+            let mut owners = transaction.open_table(OWNERS)?;
+            let mut targets = transaction.open_table(TARGETS)?;
+            let mut tags = transaction.open_table(TAGS)?;
+            let mut bundles = transaction.open_table(BUNDLED_IN)?;
+            let mut root_txs = transaction.open_table(ROOT_TXS)?;
+            for item in items {
+                let id = item.id.as_str();
+                // the signed fields are the same, but the item may be found in another bundle
+                let previous = records.insert(id, item.record.as_slice())?;
+                if let Some(previous) = previous {
+                    let previous = decode(previous.value())?;
+                    bundles.remove((previous.bundled_in(), id))?;
+                    root_txs.remove((root_tx_id(&previous), id))?;
+                }
+                owners.insert((item.owner_address.as_str(), id), ())?;
+                if let Some(target) = &item.target {
+                    targets.insert((target.as_str(), id), ())?;
+                }
+                for (name, value) in &item.tags {
+                    tags.insert((name.as_str(), value.as_str(), id), ())?;
+                }
+                bundles.insert((item.bundled_in.as_str(), id), ())?;
+                root_txs.insert((item.root_tx.as_str(), id), ())?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> String {
+        format!("Failed to read {}: {}", self.path.display(), e)
+    }
+}
+
+fn get_record(transaction: &ReadTransaction, id: &str) -> Result<Option<Vec<u8>>, StoreError> {
+    let items = transaction.open_table(ITEMS)?;
+    let record = items.get(id)?.map(|record| record.value().to_vec());
+    Ok(record)
+}
+
+// Ids whose `index` value is `prefix`, or starts with it unless `exact`.
+fn scan(
+    transaction: &ReadTransaction,
+    index: ItemIndex,
+    prefix: &str,
+    limit: usize,
+    exact: bool,
+) -> Result<Vec<String>, StoreError> {
+    let mut ids = Vec::new();
+    // the keys are visited in order from the first one that can match, until one cannot
+    // anymore
+    for entry in index_entries(transaction, index, prefix, "")? {
+        let (value, id) = entry?;
+        let matches = if exact {
+            value == prefix
+        } else {
+            value.starts_with(prefix)
+        };
+        if ids.len() == limit || !matches {
+            break;
+        }
+        ids.push(id);
+    }
+    Ok(ids)
+}
+
+// Entries of `index` from the key `(value, id)` on, to the end of the table.
+fn index_entries(
+    transaction: &ReadTransaction,
+    index: ItemIndex,
+    value: &str,
+    id: &str,
+) -> Result<IndexEntries, StoreError> {
+    let table = match index {
+        ItemIndex::Owner => OWNERS,
+        ItemIndex::Target => TARGETS,
+        ItemIndex::BundledIn => BUNDLED_IN,
+        ItemIndex::RootTx => ROOT_TXS,
+        ItemIndex::Tag(name) => {
+            let name = name.to_string();
+            let entries = transaction
+                .open_table(TAGS)?
+                .range((name.as_str(), value, id)..)?;
+            // the entries of the next tag names are not part of the index
+            return Ok(Box::new(entries.map_while(move |entry| {
+                let key = match entry {
+                    Ok((key, _)) => key,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let (key_name, value, id) = key.value();
+                (key_name == name).then(|| Ok((value.to_string(), id.to_string())))
+            })));
+        }
+    };
+    let entries = transaction.open_table(table)?.range((value, id)..)?;
+    Ok(Box::new(entries.map(|entry| {
+        let (key, _) = entry?;
+        let (value, id) = key.value();
+        Ok((value.to_string(), id.to_string()))
+    })))
+}
+
+fn index_contains(
+    transaction: &ReadTransaction,
+    index: ItemIndex,
+    value: &str,
+    id: &str,
+) -> Result<bool, StoreError> {
+    let table = match index {
+        ItemIndex::Owner => OWNERS,
+        ItemIndex::Target => TARGETS,
+        ItemIndex::BundledIn => BUNDLED_IN,
+        ItemIndex::RootTx => ROOT_TXS,
+        ItemIndex::Tag(name) => {
+            let table = transaction.open_table(TAGS)?;
+            return Ok(table.get((name, value, id))?.is_some());
+        }
+    };
+    let table = transaction.open_table(table)?;
+    Ok(table.get((value, id))?.is_some())
+}
+
+// Criterion of an `ItemFilter`, met by the items with one of its values.
+enum Criterion<'a> {
+    // sorted and deduplicated
+    Ids(Vec<&'a str>),
+    AnyOf(Vec<(ItemIndex<'a>, &'a str)>),
+}
+
+impl<'a> Criterion<'a> {
+    fn of(filter: &'a ItemFilter) -> Vec<Self> {
+        let mut criteria = Vec::new();
+        if !filter.ids.is_empty() {
+            let mut ids: Vec<&str> = filter.ids.iter().map(String::as_str).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            criteria.push(Criterion::Ids(ids));
+        }
+        let any_of = |index, values: &'a [String]| {
+            Criterion::AnyOf(values.iter().map(|value| (index, value.as_str())).collect())
+        };
+        for (index, values) in [
+            (ItemIndex::Owner, &filter.owners),
+            (ItemIndex::Target, &filter.targets),
+            (ItemIndex::BundledIn, &filter.bundled_in),
+            (ItemIndex::RootTx, &filter.root_txs),
+        ] {
+            if !values.is_empty() {
+                criteria.push(any_of(index, values));
+            }
+        }
+        let tags = filter
+            .tags
+            .iter()
+            .map(|tag| any_of(ItemIndex::Tag(&tag.name), &tag.values));
+        if filter.any_tag && !filter.tags.is_empty() {
+            criteria.push(Criterion::AnyOf(
+                tags.flat_map(|tag| match tag {
+                    Criterion::AnyOf(values) => values,
+                    Criterion::Ids(_) => Vec::new(),
+                })
+                .collect(),
+            ));
+        } else {
+            criteria.extend(tags);
+        }
+        criteria
+    }
+
+    // Number of ids after `after`, counted up to `SELECTIVITY_SAMPLE`.
+    fn estimate(
+        &self,
+        transaction: &ReadTransaction,
+        after: Option<&str>,
+    ) -> Result<usize, StoreError> {
+        let mut count = 0;
+        for id in self.walk(transaction, after)?.take(SELECTIVITY_SAMPLE) {
+            id?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // Ids meeting the criterion after `after`, in order.
+    fn walk(
+        &self,
+        transaction: &ReadTransaction,
+        after: Option<&str>,
+    ) -> Result<Box<dyn Iterator<Item = Result<String, StoreError>> + '_>, StoreError> {
+        let after = after.unwrap_or_default().to_string();
+        match self {
+            Criterion::Ids(ids) => {
+                let start = ids.partition_point(|id| *id <= after.as_str());
+                Ok(Box::new(ids[start..].iter().map(|id| Ok(id.to_string()))))
+            }
+            Criterion::AnyOf(values) => {
+                let mut streams = Vec::with_capacity(values.len());
+                for &(index, value) in values {
+                    let (value, after) = (value.to_string(), after.clone());
+                    let ids = index_entries(transaction, index, &value, &after)?
+                        .map_while(move |entry| match entry {
+                            Ok((key_value, id)) => (key_value == value).then_some(Ok(id)),
+                            Err(e) => Some(Err(e)),
+                        })
+                        .filter(move |id| !matches!(id, Ok(id) if *id == after));
+                    streams.push(Box::new(ids) as IdStream);
+                }
+                Ok(Box::new(Merge::new(streams)?))
+            }
+        }
+    }
+
+    fn contains(&self, transaction: &ReadTransaction, id: &str) -> Result<bool, StoreError> {
+        match self {
+            Criterion::Ids(ids) => Ok(ids.binary_search(&id).is_ok()),
+            Criterion::AnyOf(values) => {
+                for &(index, value) in values {
+                    if index_contains(transaction, index, value, id)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+type IdStream = Box<dyn Iterator<Item = Result<String, StoreError>>>;
+
+// Union of ordered id streams, in order and without duplicates. It ends after the first error.
+struct Merge {
+    streams: Vec<IdStream>,
+    // next id of every stream
+    heads: Vec<Option<String>>,
+    last: Option<String>,
+    failed: bool,
+}
+
+impl Merge {
+    // Reads the first id of every stream.
+    fn new(mut streams: Vec<IdStream>) -> Result<Self, StoreError> {
+        let heads = streams
+            .iter_mut()
+            .map(|stream| stream.next().transpose())
+            .collect::<Result<_, _>>()?;
+        Ok(Merge {
+            streams,
+            heads,
+            last: None,
+            failed: false,
+        })
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<String, StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let (index, _) = self
+                .heads
+                .iter()
+                .enumerate()
+                .filter_map(|(index, head)| Some((index, head.as_ref()?)))
+                .min_by(|a, b| a.1.cmp(b.1))?;
+            let next = match self.streams[index].next().transpose() {
+                Ok(next) => next,
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            };
+            let id = std::mem::replace(&mut self.heads[index], next)?;
+            // an item can have several of the values
+            if self.last.as_ref() != Some(&id) {
+                self.last = Some(id.clone());
+                return Some(Ok(id));
+            }
+        }
+    }
+}
+
+fn decode(record: &[u8]) -> Result<DataItem, String> {
+    rmp_serde::from_slice::<OwnedBinaryRecord>(record)
+        .map_err(|e| format!("Failed to decode an item: {}", e))
+        .and_then(DataItem::try_from)
+}
+
+impl Sink for ItemStore {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        let items = items
+            .iter()
+            .map(StoredItem::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let store = self.clone();
+        tokio::task::spawn_blocking(move || store.insert(&items))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|inserted| inserted.map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    // every batch is committed by `write`
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        Ok(None)
+    }
+
+    async fn finish(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(ids: Vec<Result<&'static str, &'static str>>) -> IdStream {
+        Box::new(
+            ids.into_iter()
+                .map(|id| id.map(str::to_string).map_err(StoreError::from)),
+        )
+    }
+
+    fn collect(merge: Merge) -> Vec<Result<String, String>> {
+        merge.map(|id| id.map_err(|e| e.to_string())).collect()
+    }
+
+    #[test]
+    fn test_merge_yields_the_union_in_order() {
+        let merge = Merge::new(vec![
+            stream(vec![Ok("a"), Ok("c"), Ok("d")]),
+            stream(vec![]),
+            stream(vec![Ok("b"), Ok("c"), Ok("e")]),
+        ])
+        .unwrap();
+
+        let ids: Vec<String> = collect(merge).into_iter().map(Result::unwrap).collect();
+        assert_eq!(ids, vec!["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn test_merge_ends_after_the_first_error() {
+        let merge = Merge::new(vec![
+            stream(vec![Ok("a"), Err("broken"), Ok("c")]),
+            stream(vec![Ok("b"), Ok("d")]),
+        ])
+        .unwrap();
+
+        // the next id of a stream is read before its head is returned
+        assert_eq!(collect(merge), vec![Err("broken".to_string())]);
+    }
+
+    #[test]
+    fn test_merge_fails_when_a_stream_cannot_start() {
+        let failed = Merge::new(vec![stream(vec![Ok("a")]), stream(vec![Err("broken")])]);
+
+        assert_eq!(failed.err().unwrap().to_string(), "broken");
+    }
+}
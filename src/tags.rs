@@ -45,26 +45,44 @@ impl Serialize for Tag {
         S: Serializer,
     {
         let mut state = serializer.serialize_struct("Tag", 2)?;
+        let (name, value) = self.to_text();
+        state.serialize_field("name", &name)?;
+        state.serialize_field("value", &value)?;
+        state.end()
+    }
+}
+
+impl Tag {
+    pub fn new(name: Vec<u8>, value: Vec<u8>) -> Self {
+        Tag { name, value }
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
 
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// The name and the value as text, both base64url encoded when either is not UTF-8.
+    pub fn to_text(&self) -> (String, String) {
         match (
-            String::from_utf8(self.name.clone()),
-            String::from_utf8(self.value.clone()),
+            std::str::from_utf8(&self.name),
+            std::str::from_utf8(&self.value),
         ) {
-            (Ok(name), Ok(value)) => {
-                state.serialize_field("name", &name)?;
-                state.serialize_field("value", &value)?;
-            }
-            _ => {
-                state.serialize_field("name", &BASE64_URL.encode(&self.name))?;
-                state.serialize_field("value", &BASE64_URL.encode(&self.value))?;
-            }
+            (Ok(name), Ok(value)) => (name.to_string(), value.to_string()),
+            _ => (
+                BASE64_URL.encode(&self.name),
+                BASE64_URL.encode(&self.value),
+            ),
         }
+    }
 
-        state.end()
+    fn is(&self, name: &str, value: &str) -> bool {
+        self.name == name.as_bytes() && self.value == value.as_bytes()
     }
-}
 
-impl Tag {
     pub fn validate(&self) -> Result<(), ValidationError> {
         if self.name.len() > 1024 {
             return Err(ValidationError("name exceeds 1024 bytes".into()));
@@ -86,6 +104,12 @@ impl Tag {
     }
 }
 
+// Bundles are marked by the Bundle-Format and Bundle-Version tags, on data items and on L1 transactions.
+pub(crate) fn marks_bundle(tags: &[Tag]) -> bool {
+    tags.iter().any(|tag| tag.is("Bundle-Format", "binary"))
+        && tags.iter().any(|tag| tag.is("Bundle-Version", "2.0.0"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,259 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::block::Block;
+use crate::node::parse_number;
+use crate::tags::{marks_bundle, Tag};
+
+/// Header of a layer 1 transaction, as served by `/tx/{id}`.
+#[derive(Debug)]
+pub struct TransactionHeader {
+    /// 1 for transactions carrying their data inline, 2 for chunked data.
+    pub format: u32,
+    pub id: String,
+    pub owner: Vec<u8>,
+    pub target: Option<[u8; 32]>,
+    pub data_size: u64,
+    /// Merkle root of the chunked data, format 2 only.
+    pub data_root: Option<[u8; 32]>,
+    pub tags: Vec<Tag>,
+}
+
+/// Block a transaction was mined in, as served by `/tx/{id}/status`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TransactionStatus {
+    pub block_height: u64,
+    pub block_indep_hash: String,
+    pub number_of_confirmations: u64,
+}
+
+/// Metadata of the layer 1 transaction a bundle was posted in, attached to every item.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RootTransaction {
+    pub id: String,
+    /// Base64url SHA-256 hash of the owner public key.
+    pub owner_address: String,
+    /// Size in bytes of the bundle data.
+    pub data_size: u64,
+    /// Height of the block holding the transaction, unknown while it is pending.
+    pub block_height: Option<u64>,
+    pub block_hash: Option<String>,
+    /// Unix time in seconds of the block, known when the transaction was found in a block.
+    pub block_timestamp: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct RawTransaction {
+    #[serde(default = "default_format")]
+    format: u32,
+    id: String,
+    #[serde(default)]
+    owner: String,
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    data_size: String,
+    #[serde(default)]
+    data_root: String,
+    #[serde(default)]
+    tags: Vec<RawTag>,
+}
+
+#[derive(Deserialize)]
+struct RawTag {
+    name: String,
+    value: String,
+}
+
+fn default_format() -> u32 {
+    1
+}
+
+impl TransactionHeader {
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let raw: RawTransaction =
+            serde_json::from_slice(json).map_err(|e| format!("Invalid transaction: {}", e))?;
+
+        let owner = decode("owner", &raw.owner)?;
+        let target = decode_hash("target", &raw.target)?;
+        let data_root = decode_hash("data_root", &raw.data_root)?;
+
+        // format 1 transactions may only carry their data inline
+        let data_size = if raw.data_size.is_empty() {
+            decode("data", &raw.data)?.len() as u64
+        } else {
+            parse_number("data_size", &raw.data_size)?
+        };
+
+        // both formats encode tag names and values in base64url
+        let tags = raw
+            .tags
+            .iter()
+            .map(|tag| {
+                Ok(Tag::new(
+                    decode("tag name", &tag.name)?,
+                    decode("tag value", &tag.value)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(TransactionHeader {
+            format: raw.format,
+            id: raw.id,
+            owner,
+            target,
+            data_size,
+            data_root,
+            tags,
+        })
+    }
+
+    /// Address of the owner, the base64url SHA-256 of its public key.
+    pub fn owner_address(&self) -> String {
+        BASE64_URL.encode(Sha256::digest(&self.owner))
+    }
+
+    /// Whether the transaction is tagged as an ANS-104 binary bundle.
+    pub fn is_bundle(&self) -> bool {
+        marks_bundle(&self.tags)
+    }
+}
+
+impl RootTransaction {
+    pub fn new(header: &TransactionHeader, status: Option<&TransactionStatus>) -> Self {
+        RootTransaction {
+            id: header.id.clone(),
+            owner_address: header.owner_address(),
+            data_size: header.data_size,
+            block_height: status.map(|status| status.block_height),
+            block_hash: status.map(|status| status.block_indep_hash.clone()),
+            block_timestamp: None,
+        }
+    }
+
+    /// The transaction mined in `block`.
+    pub fn in_block(header: &TransactionHeader, block: &Block) -> Self {
+        RootTransaction {
+            id: header.id.clone(),
+            owner_address: header.owner_address(),
+            data_size: header.data_size,
+            block_height: Some(block.height),
+            block_hash: Some(block.indep_hash.clone()),
+            block_timestamp: Some(block.timestamp),
+        }
+    }
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, String> {
+    BASE64_URL
+        .decode(value.trim_end_matches('='))
+        .map_err(|e| format!("Invalid {} encoding: {}", field, e))
+}
+
+fn decode_hash(field: &str, value: &str) -> Result<Option<[u8; 32]>, String> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let bytes = decode(field, value)?;
+    let hash = bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| format!("Invalid {} of {} bytes", field, bytes.len()))?;
+    Ok(Some(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &str) -> String {
+        BASE64_URL.encode(value)
+    }
+
+    fn bundle_tags() -> serde_json::Value {
+        serde_json::json!([
+            { "name": encode("Bundle-Format"), "value": encode("binary") },
+            { "name": encode("Bundle-Version"), "value": encode("2.0.0") },
+        ])
+    }
+
+    #[test]
+    fn test_parse_format_2_bundle() {
+        let owner = vec![5u8; 512];
+        let json = serde_json::json!({
+            "format": 2,
+            "id": "tx-id",
+            "owner": BASE64_URL.encode(&owner),
+            "target": "",
+            "data": "",
+            "data_size": "1234",
+            "data_root": BASE64_URL.encode([3u8; 32]),
+            "tags": bundle_tags(),
+        });
+
+        let header = TransactionHeader::parse(json.to_string().as_bytes()).unwrap();
+
+        assert_eq!(header.format, 2);
+        assert_eq!(header.data_size, 1234);
+        assert_eq!(header.data_root, Some([3u8; 32]));
+        assert_eq!(header.target, None);
+        assert!(header.is_bundle());
+        assert_eq!(
+            header.owner_address(),
+            BASE64_URL.encode(Sha256::digest(&owner))
+        );
+    }
+
+    #[test]
+    fn test_parse_format_1_with_inline_data() {
+        let json = serde_json::json!({
+            "format": 1,
+            "id": "tx-id",
+            "owner": "",
+            "target": BASE64_URL.encode([1u8; 32]),
+            "data": BASE64_URL.encode(b"hello"),
+            "data_root": "",
+            "tags": [{ "name": encode("Content-Type"), "value": encode("text/plain") }],
+        });
+
+        let header = TransactionHeader::parse(json.to_string().as_bytes()).unwrap();
+
+        assert_eq!(header.format, 1);
+        assert_eq!(header.data_size, 5);
+        assert_eq!(header.data_root, None);
+        assert_eq!(header.target, Some([1u8; 32]));
+        assert_eq!(header.tags[0].value(), b"text/plain");
+        assert!(!header.is_bundle());
+    }
+
+    #[test]
+    fn test_bundle_needs_both_tags() {
+        let json = serde_json::json!({
+            "format": 2,
+            "id": "tx-id",
+            "data_size": "0",
+            "tags": [{ "name": encode("Bundle-Format"), "value": encode("binary") }],
+        });
+
+        let header = TransactionHeader::parse(json.to_string().as_bytes()).unwrap();
+
+        assert!(!header.is_bundle());
+    }
+
+    #[test]
+    fn test_invalid_data_root_is_rejected() {
+        let json = serde_json::json!({
+            "id": "tx-id",
+            "data_size": "0",
+            "data_root": BASE64_URL.encode([3u8; 7]),
+        });
+
+        let err = TransactionHeader::parse(json.to_string().as_bytes()).unwrap_err();
+
+        assert!(err.contains("Invalid data_root of 7 bytes"), "{}", err);
+    }
+}
@@ -1,16 +1,139 @@
-pub fn bytes_to_number(bytes: &[u8]) -> Result<usize, String> {
-    let mut value = 0usize;
+use std::fmt;
+
+// Unsigned 256-bit integer, the width of the ANS-104 bundle header fields.
+// Values are only decoded and converted, so no arithmetic beyond formatting is provided.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct U256 {
+    // little-endian 64-bit limbs
+    limbs: [u64; 4],
+}
+
+impl U256 {
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().zip(bytes.chunks_exact(8)) {
+            *limb = u64::from_le_bytes(chunk.try_into().expect("chunk of 8 bytes"));
+        }
+        U256 { limbs }
+    }
+
+    // Decodes a little-endian number of at most 32 bytes.
+    pub fn from_le_slice(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() > 32 {
+            return Err(format!(
+                "Value of {} bytes is wider than 256 bits",
+                bytes.len()
+            ));
+        }
+        let mut padded = [0u8; 32];
+        padded[..bytes.len()].copy_from_slice(bytes);
+        Ok(Self::from_le_bytes(padded))
+    }
+
+    // Number of significant bits.
+    pub fn bits(&self) -> u32 {
+        for (index, limb) in self.limbs.iter().enumerate().rev() {
+            if *limb != 0 {
+                return index as u32 * 64 + (64 - limb.leading_zeros());
+            }
+        }
+        0
+    }
 
-    for &byte in bytes.iter().rev() {
-        value = value
-            .checked_mul(256)
-            .ok_or("Value exceeds usize range: multiplication overflow")?;
-        value = value
-            .checked_add(byte as usize)
-            .ok_or("Value exceeds usize range: addition overflow")?;
+    pub fn to_u64(self) -> Result<u64, NumberTooLarge> {
+        self.to_width("u64", 64)
     }
 
-    Ok(value)
+    pub fn to_usize(self) -> Result<usize, NumberTooLarge> {
+        self.to_width("usize", usize::BITS)
+            .map(|value| value as usize)
+    }
+
+    // The value when it fits in `target_bits` bits, at most 64, whatever the platform.
+    fn to_width(self, target: &'static str, target_bits: u32) -> Result<u64, NumberTooLarge> {
+        if self.bits() > target_bits {
+            return Err(NumberTooLarge {
+                value: self,
+                target,
+                target_bits,
+            });
+        }
+        Ok(self.limbs[0])
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256 {
+            limbs: [value, 0, 0, 0],
+        }
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.bits() <= 64 {
+            return write!(f, "{}", self.limbs[0]);
+        }
+
+        // repeated division by 10^19, the largest power of ten fitting in a limb
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut limbs = self.limbs;
+        let mut chunks = Vec::new();
+        while limbs.iter().any(|limb| *limb != 0) {
+            let mut remainder = 0u128;
+            for limb in limbs.iter_mut().rev() {
+                let current = (remainder << 64) | *limb as u128;
+                *limb = (current / CHUNK as u128) as u64;
+                remainder = current % CHUNK as u128;
+            }
+            chunks.push(remainder as u64);
+        }
+
+        let mut chunks = chunks.into_iter().rev();
+        if let Some(first) = chunks.next() {
+            write!(f, "{}", first)?;
+        }
+        for chunk in chunks {
+            write!(f, "{:019}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "U256({})", self)
+    }
+}
+
+// A header value that does not fit into the integer type it is used as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberTooLarge {
+    pub value: U256,
+    pub target: &'static str,
+    pub target_bits: u32,
+}
+
+impl fmt::Display for NumberTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "value {} ({} bits) exceeds {} ({} bits)",
+            self.value,
+            self.value.bits(),
+            self.target,
+            self.target_bits
+        )
+    }
+}
+
+impl std::error::Error for NumberTooLarge {}
+
+pub fn bytes_to_number(bytes: &[u8]) -> Result<usize, String> {
+    U256::from_le_slice(bytes)?
+        .to_usize()
+        .map_err(|e| e.to_string())
 }
 
 #[cfg(test)]
@@ -49,4 +172,73 @@ mod tests {
             assert_eq!(bytes_to_number(&bytes).unwrap(), usize::MAX);
         }
     }
+
+    #[test]
+    fn test_u256_display_full_width() {
+        let value = U256::from_le_bytes([0xFF; 32]);
+        assert_eq!(
+            value.to_string(),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+        assert_eq!(value.bits(), 256);
+    }
+
+    #[test]
+    fn test_u256_display_crosses_limbs() {
+        let mut bytes = [0u8; 32];
+        bytes[8] = 1;
+        assert_eq!(
+            U256::from_le_bytes(bytes).to_string(),
+            "18446744073709551616"
+        );
+    }
+
+    #[test]
+    fn test_u256_to_u64_reports_value() {
+        let mut bytes = [0u8; 32];
+        bytes[31] = 0x80;
+        let err = U256::from_le_bytes(bytes).to_u64().unwrap_err();
+        assert_eq!(err.target_bits, 64);
+        assert_eq!(
+            err.to_string(),
+            "value 57896044618658097711785492504343953926634992332820282019728792003956564819968 (256 bits) exceeds u64 (64 bits)"
+        );
+    }
+
+    #[test]
+    fn test_u256_from_le_slice_too_wide() {
+        assert!(U256::from_le_slice(&[0u8; 33]).is_err());
+        assert_eq!(U256::from_le_slice(&[2, 1]).unwrap(), U256::from(258));
+    }
+
+    // what `to_usize` does on 32-bit platforms, checked on every platform
+    #[test]
+    fn test_u256_to_32_bit_usize() {
+        let value = U256::from(u32::MAX as u64);
+        assert_eq!(value.to_width("usize", 32).unwrap(), u32::MAX as u64);
+
+        let value = U256::from(u32::MAX as u64 + 1);
+        assert_eq!(value.to_u64().unwrap(), 1 << 32);
+        assert_eq!(
+            value.to_width("usize", 32).unwrap_err().to_string(),
+            "value 4294967296 (33 bits) exceeds usize (32 bits)"
+        );
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_u256_to_usize_on_64_bit() {
+        let value = U256::from(u64::MAX);
+        assert_eq!(value.to_usize().unwrap(), usize::MAX);
+
+        let mut bytes = [0u8; 32];
+        bytes[8] = 1;
+        assert_eq!(
+            U256::from_le_bytes(bytes)
+                .to_usize()
+                .unwrap_err()
+                .to_string(),
+            "value 18446744073709551616 (65 bits) exceeds usize (64 bits)"
+        );
+    }
 }
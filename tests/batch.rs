@@ -0,0 +1,113 @@
+mod common;
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{DataItem, DataSource, Indexer, NodeClient, RetryPolicy};
+use common::node::MockNode;
+use common::TestItem;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+fn bundle(seed: u8, count: usize) -> Vec<TestItem> {
+    (0..count)
+        .map(|index| {
+            TestItem::new(2, vec![seed; 50 + index]).with_signature_seed(seed + index as u8)
+        })
+        .collect()
+}
+
+async fn indexer(node: MockNode) -> Indexer {
+    let url = node.serve().await;
+    let retry = RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    };
+    Indexer::new(
+        NodeClient::new(std::slice::from_ref(&url)).with_retry(retry.clone()),
+        DataSource::Gateways(vec![url]),
+    )
+    .with_retry(retry)
+    .with_concurrency(2)
+}
+
+#[tokio::test]
+async fn test_batch_indexes_every_id_into_one_channel() {
+    let mut node = MockNode::default();
+    node.add_bundle_tx("bundle-a", &bundle(10, 3), &[1u8; 32])
+        .status = Some(json!({
+        "block_height": 42,
+        "block_indep_hash": "block-42",
+        "number_of_confirmations": 3,
+    }));
+    node.add_bundle_tx("bundle-b", &bundle(20, 1), &[2u8; 32]);
+    node.add_bundle_tx("bundle-c", &bundle(30, 2), &[3u8; 32]);
+    node.add_tx("plain", vec![0u8; 10]);
+    let indexer = indexer(node).await;
+
+    let ids: Vec<String> = ["bundle-a", "plain", "bundle-b", "missing", "bundle-c"]
+        .iter()
+        .map(|id| id.to_string())
+        .collect();
+    let (tx, mut rx) = mpsc::channel::<DataItem>(4);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(item);
+        }
+        items
+    });
+    let outcomes = indexer.index_ids(&ids, tx).await;
+    let items = read_handle.await.unwrap();
+
+    let summaries: Vec<_> = outcomes.iter().map(|outcome| outcome.summary()).collect();
+    let counts: Vec<(&str, usize, usize)> = summaries
+        .iter()
+        .map(|summary| (summary.tx_id.as_str(), summary.items, summary.errors.len()))
+        .collect();
+    assert_eq!(
+        counts,
+        vec![
+            ("bundle-a", 3, 0),
+            ("plain", 0, 1),
+            ("bundle-b", 1, 0),
+            ("missing", 0, 1),
+            ("bundle-c", 2, 0),
+        ]
+    );
+    assert!(summaries[1].errors[0].contains("not tagged as an ANS-104 binary bundle"));
+    assert!(summaries[3].errors[0].contains("/tx/missing"));
+
+    assert_eq!(items.len(), 6);
+    let roots: HashSet<&str> = items
+        .iter()
+        .map(|item| item.root_tx().unwrap().id.as_str())
+        .collect();
+    assert_eq!(roots, HashSet::from(["bundle-a", "bundle-b", "bundle-c"]));
+    let in_block = items
+        .iter()
+        .find(|item| item.bundled_in() == "bundle-a")
+        .unwrap();
+    assert_eq!(in_block.root_tx().unwrap().block_height, Some(42));
+}
+
+#[tokio::test]
+async fn test_summary_lists_data_root_mismatches() {
+    let mut node = MockNode::default();
+    node.add_bundle_tx("bundle-a", &bundle(10, 2), &[1u8; 32])
+        .data_root = [0u8; 32];
+    let indexer = indexer(node).await;
+
+    let (tx, _rx) = mpsc::channel::<DataItem>(16);
+    let outcome = indexer.index_id("bundle-a", tx).await;
+    let summary = outcome.summary();
+
+    assert_eq!(summary.items, 2);
+    assert_eq!(summary.errors.len(), 1);
+    assert!(
+        summary.errors[0].contains("data_root mismatch"),
+        "{:?}",
+        summary.errors
+    );
+}
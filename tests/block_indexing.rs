@@ -0,0 +1,234 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{DataItem, DataSource, Indexer, NodeClient, RetryPolicy};
+use common::http::serve;
+use common::node::MockNode;
+use common::TestItem;
+use tokio::sync::mpsc;
+
+const TIMESTAMP: u64 = 1_700_000_000;
+
+fn retry() -> RetryPolicy {
+    RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    }
+}
+
+// Blocks 100 to 102: a bundle and a plain transaction, an empty block, then a nested bundle.
+fn fixture_node() -> MockNode {
+    let mut node = MockNode::default();
+    node.add_bundle_tx(
+        "bundle-a",
+        &[
+            TestItem::new(2, vec![1u8; 300]).with_signature_seed(1),
+            TestItem::new(1, vec![2u8; 50]).with_signature_seed(2),
+        ],
+        &[7u8; 32],
+    );
+    node.add_tx("plain", vec![0u8; 64]);
+    node.add_bundle_tx(
+        "bundle-b",
+        &[TestItem::nested(
+            2,
+            vec![TestItem::new(2, vec![3u8; 10]).with_signature_seed(3)],
+        )],
+        &[8u8; 32],
+    );
+    node.add_block(100, TIMESTAMP, &["bundle-a", "plain"]);
+    node.add_block(101, TIMESTAMP + 120, &[]);
+    node.add_block(102, TIMESTAMP + 240, &["bundle-b"]);
+    node
+}
+
+async fn index(data: impl Fn(String) -> DataSource) -> (Vec<DataItem>, MockNode) {
+    let node = fixture_node();
+    let url = node.clone().serve().await;
+    let indexer = Indexer::new(
+        NodeClient::new(std::slice::from_ref(&url)).with_retry(retry()),
+        data(url),
+    )
+    .with_retry(retry())
+    .with_concurrency(2);
+
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(item);
+        }
+        items
+    });
+    let reports: Vec<_> = indexer
+        .index_range(100, 102, tx)
+        .await
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    let items = read_handle.await.unwrap();
+
+    let heights: Vec<u64> = reports.iter().map(|report| report.height).collect();
+    assert_eq!(heights, vec![100, 101, 102]);
+    let bundles: Vec<Vec<&str>> = reports
+        .iter()
+        .map(|report| {
+            report
+                .bundles
+                .iter()
+                .map(|bundle| bundle.tx_id.as_str())
+                .collect()
+        })
+        .collect();
+    assert_eq!(bundles, vec![vec!["bundle-a"], vec![], vec!["bundle-b"]]);
+    assert_eq!(reports[0].transactions, 2);
+    for bundle in reports.iter().flat_map(|report| &report.bundles) {
+        let report = bundle.result.as_ref().unwrap();
+        assert!(report.data_root.as_ref().unwrap().is_verified());
+    }
+
+    (items, node)
+}
+
+fn assert_items_carry_their_block(items: &[DataItem]) {
+    let mut per_bundle: HashMap<String, usize> = HashMap::new();
+    for item in items {
+        let root_tx = item.root_tx().unwrap();
+        let (height, timestamp) = match root_tx.id.as_str() {
+            "bundle-a" => (100, TIMESTAMP),
+            "bundle-b" => (102, TIMESTAMP + 240),
+            other => panic!("unexpected root transaction {}", other),
+        };
+        assert_eq!(root_tx.block_height, Some(height));
+        assert_eq!(root_tx.block_timestamp, Some(timestamp));
+        assert_eq!(root_tx.block_hash, Some(format!("block-{}", height)));
+        *per_bundle.entry(root_tx.id.clone()).or_default() += 1;
+    }
+    assert_eq!(per_bundle["bundle-a"], 2);
+    // the nested bundle and its item
+    assert_eq!(per_bundle["bundle-b"], 2);
+}
+
+#[tokio::test]
+async fn test_block_range_is_indexed_from_gateways() {
+    let (items, node) = index(|url| DataSource::Gateways(vec![url])).await;
+
+    assert_items_carry_their_block(&items);
+    let requests = node.requests.lock().unwrap();
+    assert!(requests.contains(&"/bundle-a".to_string()));
+    assert!(!requests.contains(&"/plain".to_string()));
+}
+
+#[tokio::test]
+async fn test_block_range_is_indexed_from_node_chunks() {
+    let (items, node) = index(|url| DataSource::Nodes {
+        urls: vec![url],
+        prefetch: 2,
+    })
+    .await;
+
+    assert_items_carry_their_block(&items);
+    let requests = node.requests.lock().unwrap();
+    assert!(requests.iter().any(|path| path.starts_with("/chunk/")));
+    assert!(!requests.contains(&"/tx/plain/offset".to_string()));
+}
+
+#[tokio::test]
+async fn test_missing_block_is_reported_with_the_others() {
+    let url = fixture_node().serve().await;
+    let indexer = Indexer::new(
+        NodeClient::new(std::slice::from_ref(&url)).with_retry(retry()),
+        DataSource::Gateways(vec![url]),
+    );
+
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let reports = indexer.index_range(101, 103, tx).await;
+    let mut items = 0;
+    while rx.recv().await.is_some() {
+        items += 1;
+    }
+
+    assert_eq!(reports.len(), 3);
+    assert_eq!(reports[0].as_ref().unwrap().height, 101);
+    let block = reports[1].as_ref().unwrap();
+    assert_eq!(block.height, 102);
+    assert!(block.bundles[0].result.is_ok());
+    assert_eq!(items, 2);
+    let err = reports[2].as_ref().unwrap_err();
+    assert!(err.contains("/block/height/103"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_missing_header_is_reported_as_a_failed_bundle() {
+    let mut node = fixture_node();
+    node.add_block(103, TIMESTAMP + 360, &["missing", "bundle-a"]);
+    let url = node.serve().await;
+    let indexer = Indexer::new(
+        NodeClient::new(std::slice::from_ref(&url)).with_retry(retry()),
+        DataSource::Gateways(vec![url]),
+    )
+    .with_retry(retry());
+
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let block = indexer.index_block_at(103, tx).await.unwrap();
+    let mut items = 0;
+    while rx.recv().await.is_some() {
+        items += 1;
+    }
+
+    let bundles: Vec<&str> = block
+        .bundles
+        .iter()
+        .map(|bundle| bundle.tx_id.as_str())
+        .collect();
+    assert_eq!(bundles, vec!["missing", "bundle-a"]);
+    let err = block.bundles[0].result.as_ref().unwrap_err();
+    assert!(err.contains("Failed to fetch the header"), "{}", err);
+    assert!(block.bundles[1].result.is_ok());
+    assert_eq!(items, 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn test_header_fetches_share_the_concurrency_limit() {
+    let mut node = MockNode::default();
+    for height in 0..3 {
+        let ids: Vec<String> = (0..4)
+            .map(|tx| format!("plain-{}-{}", height, tx))
+            .collect();
+        for id in &ids {
+            node.add_tx(id, vec![0u8; 16]);
+        }
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        node.add_block(height, TIMESTAMP, &ids);
+    }
+    // counts the header requests answered at the same time
+    let (in_flight, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let (counted, seen) = (in_flight.clone(), most.clone());
+    let url = serve(move |request| {
+        if !request.path.starts_with("/tx/") {
+            return node.handle(&request);
+        }
+        let now = counted.fetch_add(1, Ordering::SeqCst) + 1;
+        seen.fetch_max(now, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(20));
+        counted.fetch_sub(1, Ordering::SeqCst);
+        node.handle(&request)
+    })
+    .await;
+    let indexer = Indexer::new(
+        NodeClient::new(std::slice::from_ref(&url)).with_retry(retry()),
+        DataSource::Gateways(vec![url]),
+    )
+    .with_concurrency(2);
+
+    let (tx, _rx) = mpsc::channel::<DataItem>(16);
+    let reports = indexer.index_range(0, 2, tx).await;
+
+    assert!(reports.iter().all(|report| report.is_ok()));
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use arweave_ans_1040_indexer::{process_bundle_with_options, DataItem, ProcessOptions};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{
+    actual_item, bundle_bytes, expected_items, lengths, ExpectedItem, TestData, TestItem,
+};
+use proptest::prelude::*;
+use std::io::Cursor;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+fn run_process_bundle(bytes: Vec<u8>) -> Result<Vec<ExpectedItem>, String> {
+    run_with_options(bytes, ProcessOptions::default())
+}
+
+fn run_with_options(bytes: Vec<u8>, options: ProcessOptions) -> Result<Vec<ExpectedItem>, String> {
+    let items = run_items(bytes, options)?;
+    Ok(items.iter().map(actual_item).collect())
+}
+
+fn run_items(bytes: Vec<u8>, options: ProcessOptions) -> Result<Vec<DataItem>, String> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .expect("Failed to build runtime");
+
+    runtime.block_on(async move {
+        let mut cursor = Cursor::new(bytes);
+        let (tx, mut rx) = mpsc::channel(16);
+
+        let read_handle = tokio::spawn(async move {
+            let mut items = Vec::new();
+            while let Some(item) = rx.recv().await {
+                items.push(item);
+            }
+            items
+        });
+
+        process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options).await?;
+        Ok(read_handle.await.unwrap())
+    })
+}
+
+fn tag_strategy() -> impl Strategy<Value = (Vec<u8>, Vec<u8>)> {
+    (
+        prop::collection::vec(any::<u8>(), 1..32),
+        prop::collection::vec(any::<u8>(), 1..64),
+    )
+        .prop_filter(
+            "bundle marker tags are reserved for nested bundles",
+            |(name, _)| name.as_slice() != b"Bundle-Format" && name.as_slice() != b"Bundle-Version",
+        )
+}
+
+fn header_strategy() -> impl Strategy<Value = TestItem> {
+    prop_oneof![Just(1u16), Just(2u16)].prop_flat_map(|signature_type| {
+        let (sig_len, owner_len) = lengths(signature_type);
+        (
+            prop::collection::vec(any::<u8>(), sig_len),
+            prop::collection::vec(any::<u8>(), owner_len),
+            prop::option::of(any::<[u8; 32]>()),
+            prop::option::of(any::<[u8; 32]>()),
+            prop::collection::vec(tag_strategy(), 0..8),
+        )
+            .prop_map(move |(signature, owner, target, anchor, tags)| TestItem {
+                signature_type,
+                signature,
+                owner,
+                target,
+                anchor,
+                tags,
+                data: TestData::Raw(Vec::new()),
+            })
+    })
+}
+
+fn item_strategy() -> impl Strategy<Value = TestItem> {
+    let leaf = (
+        header_strategy(),
+        prop::collection::vec(any::<u8>(), 0..2048),
+    )
+        .prop_map(|(mut item, data)| {
+            item.data = TestData::Raw(data);
+            item
+        });
+
+    leaf.prop_recursive(3, 32, 6, |inner| {
+        (header_strategy(), prop::collection::vec(inner, 0..6)).prop_map(|(mut item, children)| {
+            item.tags.extend(common::bundle_tags());
+            item.data = TestData::Bundle(children);
+            item
+        })
+    })
+}
+
+// The generated items in stream order, with the ids of the bundles holding them.
+fn flatten<'a>(
+    items: &'a [TestItem],
+    ancestry: &[String],
+    out: &mut Vec<(&'a TestItem, Vec<String>)>,
+) {
+    for item in items {
+        out.push((item, ancestry.to_vec()));
+        if let TestData::Bundle(nested) = &item.data {
+            let mut inner = ancestry.to_vec();
+            inner.push(BASE64_URL.encode(item.id()));
+            flatten(nested, &inner, out);
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn process_bundle_yields_generated_items(items in prop::collection::vec(item_strategy(), 0..8)) {
+        let actual = run_process_bundle(bundle_bytes(&items)).unwrap();
+        let expected = expected_items(&items, ROOT_TX);
+
+        prop_assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn items_point_at_their_bytes(items in prop::collection::vec(item_strategy(), 0..8)) {
+        let bytes = bundle_bytes(&items);
+        let actual = run_items(bytes.clone(), ProcessOptions::default()).unwrap();
+        let mut expected = Vec::new();
+        flatten(&items, &[ROOT_TX.to_string()], &mut expected);
+
+        prop_assert_eq!(actual.len(), expected.len());
+        for (item, (generated, ancestry)) in actual.iter().zip(expected) {
+            let start = item.offset() as usize;
+            prop_assert_eq!(&bytes[start..start + item.size() as usize], &generated.to_bytes()[..]);
+            let data = &bytes[item.data_offset() as usize..][..item.data_size() as usize];
+            match &generated.data {
+                TestData::Raw(raw) => prop_assert_eq!(data, &raw[..]),
+                TestData::Bundle(nested) => prop_assert_eq!(data, &bundle_bytes(nested)[..]),
+            }
+            prop_assert_eq!(item.signature_type(), generated.signature_type);
+            prop_assert_eq!(item.ancestry(), &ancestry[..]);
+        }
+    }
+
+    #[test]
+    fn salvage_mode_yields_the_same_items(items in prop::collection::vec(item_strategy(), 0..8)) {
+        let bytes = bundle_bytes(&items);
+        let options = ProcessOptions {
+            salvage: true,
+            ..ProcessOptions::default()
+        };
+
+        prop_assert_eq!(
+            run_with_options(bytes.clone(), options).unwrap(),
+            run_process_bundle(bytes).unwrap()
+        );
+    }
+
+    #[test]
+    fn process_bundle_fails_on_truncated_stream(
+        items in prop::collection::vec(item_strategy(), 1..4),
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let bytes = bundle_bytes(&items);
+        let truncated = bytes[..cut.index(bytes.len())].to_vec();
+
+        prop_assert!(run_process_bundle(truncated).is_err());
+    }
+}
+
+#[test]
+fn process_bundle_handles_item_without_tags() {
+    let mut item = TestItem::new(2, b"no tags".to_vec());
+    item.tags.clear();
+    let items = vec![item, TestItem::new(1, b"after".to_vec())];
+
+    let actual = run_process_bundle(bundle_bytes(&items)).unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, resume_bundle, Checkpoint, ChunkSource, Compression, FileSource,
+    GatewaySource, ProcessOptions, ResumableSource, RetryPolicy,
+};
+use common::node::MockNode;
+use common::{actual_item, bundle_bytes, ExpectedItem, TestItem};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+// Two nested bundles, one of them holding another, between plain items.
+fn sample_items() -> Vec<TestItem> {
+    let inner = TestItem::nested(
+        1,
+        vec![
+            TestItem::new(2, vec![5u8; 80]).with_signature_seed(5),
+            TestItem::new(2, vec![6u8; 90]).with_signature_seed(6),
+        ],
+    )
+    .with_signature_seed(7);
+    vec![
+        TestItem::new(2, vec![1u8; 300]).with_signature_seed(1),
+        TestItem::nested(
+            2,
+            vec![
+                TestItem::new(1, vec![2u8; 200]).with_signature_seed(2),
+                inner,
+                TestItem::new(2, vec![3u8; 70]).with_signature_seed(3),
+            ],
+        )
+        .with_signature_seed(8),
+        TestItem::new(2, vec![4u8; 50]).with_signature_seed(4),
+        TestItem::nested(
+            2,
+            vec![TestItem::new(2, vec![9u8; 60]).with_signature_seed(9)],
+        )
+        .with_signature_seed(10),
+    ]
+}
+
+// Reads the whole bundle, returning its items and a checkpoint taken before every entry.
+async fn run(bytes: Vec<u8>) -> (Vec<ExpectedItem>, Vec<Checkpoint>) {
+    let size = bytes.len() as u64;
+    let (tx, mut rx) = mpsc::channel(16);
+    let (checkpoints, mut checkpoint_rx) = mpsc::channel(1024);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(actual_item(&item));
+        }
+        items
+    });
+
+    let options = ProcessOptions {
+        expected_size: Some(size),
+        checkpoints: Some(checkpoints),
+        checkpoint_interval: 1,
+        ..ProcessOptions::default()
+    };
+    let mut cursor = Cursor::new(bytes);
+    process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options)
+        .await
+        .unwrap();
+    drop(options);
+    let items = read_handle.await.unwrap();
+
+    let mut saved = Vec::new();
+    while let Some(checkpoint) = checkpoint_rx.recv().await {
+        saved.push(checkpoint);
+    }
+    (items, saved)
+}
+
+async fn resume(
+    source: &impl ResumableSource,
+    checkpoint: &Checkpoint,
+) -> Result<Vec<ExpectedItem>, String> {
+    let (tx, mut rx) = mpsc::channel(16);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(actual_item(&item));
+        }
+        items
+    });
+
+    let report = resume_bundle(source, tx, checkpoint, &ProcessOptions::default()).await;
+    let items = read_handle.await.unwrap();
+    report.map(|_| items)
+}
+
+// The checkpoint taken inside the most deeply nested bundle.
+fn deepest(checkpoints: &[Checkpoint]) -> &Checkpoint {
+    checkpoints
+        .iter()
+        .max_by_key(|checkpoint| checkpoint.stack.len())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_checkpoints_follow_the_walk() {
+    let (items, checkpoints) = run(bundle_bytes(&sample_items())).await;
+
+    // one per entry of every bundle
+    assert_eq!(checkpoints.len(), 4 + 3 + 2 + 1);
+    assert!(checkpoints
+        .windows(2)
+        .all(|pair| pair[0].offset < pair[1].offset));
+    assert!(checkpoints
+        .windows(2)
+        .all(|pair| pair[0].items_emitted <= pair[1].items_emitted));
+    assert!(checkpoints.iter().all(
+        |checkpoint| checkpoint.root_tx == ROOT_TX && checkpoint.stack[0].bundle_id == ROOT_TX
+    ));
+
+    let deepest = deepest(&checkpoints);
+    assert_eq!(deepest.stack.len(), 3);
+    let next = &items[deepest.items_emitted as usize];
+    assert_eq!(next.bundled_in, deepest.stack[2].bundle_id);
+    assert_eq!(deepest.stack[1].entry, 1);
+    assert_eq!(deepest.stack[0].entry, 1);
+}
+
+#[tokio::test]
+async fn test_resuming_a_file_yields_the_remaining_items() {
+    let bytes = bundle_bytes(&sample_items());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bundle.bin");
+    std::fs::write(&path, &bytes).unwrap();
+    let (items, checkpoints) = run(bytes).await;
+    let source = FileSource::new(&path, Compression::None);
+
+    for checkpoint in &checkpoints {
+        let resumed = resume(&source, checkpoint).await.unwrap();
+        assert_eq!(
+            resumed,
+            items[checkpoint.items_emitted as usize..],
+            "resumed from {:?}",
+            checkpoint
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_resuming_from_a_saved_checkpoint() {
+    let bytes = bundle_bytes(&sample_items());
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bundle.bin");
+    std::fs::write(&path, &bytes).unwrap();
+    let (items, checkpoints) = run(bytes).await;
+
+    let checkpoint_path = dir.path().join("checkpoint.json");
+    deepest(&checkpoints).save(&checkpoint_path).await.unwrap();
+    let loaded = Checkpoint::load(&checkpoint_path).await.unwrap().unwrap();
+    assert_eq!(&loaded, deepest(&checkpoints));
+
+    let source = FileSource::new(&path, Compression::None);
+    let resumed = resume(&source, &loaded).await.unwrap();
+    assert_eq!(resumed, items[loaded.items_emitted as usize..]);
+}
+
+#[tokio::test]
+async fn test_resuming_through_nodes_and_gateways() {
+    let bytes = bundle_bytes(&sample_items());
+    let mut node = MockNode::default();
+    node.add_tx(ROOT_TX, bytes.clone());
+    let url = node.serve().await;
+    let (items, checkpoints) = run(bytes).await;
+    let checkpoint = deepest(&checkpoints);
+    let retry = RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    };
+
+    let chunks = ChunkSource::new(std::slice::from_ref(&url), ROOT_TX).with_retry(retry.clone());
+    let resumed = resume(&chunks, checkpoint).await.unwrap();
+    assert_eq!(resumed, items[checkpoint.items_emitted as usize..]);
+
+    let gateway = GatewaySource::new(&url, ROOT_TX).with_retry(retry);
+    let resumed = resume(&gateway, checkpoint).await.unwrap();
+    assert_eq!(resumed, items[checkpoint.items_emitted as usize..]);
+}
+
+#[tokio::test]
+async fn test_checkpoints_are_rejected_in_salvage_mode() {
+    let (tx, _rx) = mpsc::channel(16);
+    let (checkpoints, _checkpoint_rx) = mpsc::channel(16);
+    let options = ProcessOptions {
+        salvage: true,
+        checkpoints: Some(checkpoints),
+        ..ProcessOptions::default()
+    };
+    let mut cursor = Cursor::new(bundle_bytes(&sample_items()));
+
+    let err = process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options)
+        .await
+        .unwrap_err();
+
+    assert!(err.contains("salvage mode"), "{}", err);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, ChunkSource, InputSource, ProcessOptions, RetryPolicy,
+};
+use common::node::MockNode;
+use common::{actual_item, bundle_bytes, expected_items, ExpectedItem, TestItem};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+// Large enough to span several chunks.
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; 300 * 1024]).with_signature_seed(1),
+        TestItem::new(1, vec![2u8; 250 * 1024]).with_signature_seed(2),
+        TestItem::new(2, vec![3u8; 100 * 1024]).with_signature_seed(3),
+    ]
+}
+
+fn fast_retry(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+    }
+}
+
+async fn run(source: ChunkSource) -> Result<Vec<ExpectedItem>, String> {
+    let input = source.open().await?;
+    let (tx, mut rx) = mpsc::channel(16);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(actual_item(&item));
+        }
+        items
+    });
+
+    let options = ProcessOptions {
+        expected_size: input.size,
+        ..ProcessOptions::default()
+    };
+    let mut reader = input.reader;
+    let report = process_bundle_with_options(&mut reader, tx, ROOT_TX, &options).await;
+    let items = read_handle.await.unwrap();
+    report.map(|_| items)
+}
+
+#[tokio::test]
+async fn test_chunk_source_yields_verified_items() {
+    let items = sample_items();
+    let mut node = MockNode::default();
+    node.add_tx("other-tx", vec![7u8; 1000]);
+    node.add_tx(ROOT_TX, bundle_bytes(&items));
+    let requests = node.requests.clone();
+    let chunk_count = node.txs[1].proofs.len();
+    let url = node.serve().await;
+
+    let source = ChunkSource::new(&[url], ROOT_TX)
+        .with_prefetch(2)
+        .with_retry(fast_retry(0));
+    let actual = run(source).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+    let chunk_requests = requests
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|path| path.starts_with("/chunk/"))
+        .count();
+    assert_eq!(chunk_count, 3);
+    assert_eq!(chunk_requests, chunk_count);
+}
+
+#[tokio::test]
+async fn test_corrupted_chunk_is_rejected() {
+    let mut node = MockNode::default();
+    node.add_tx(ROOT_TX, bundle_bytes(&sample_items()));
+    node.corrupt_chunks = true;
+    let url = node.serve().await;
+
+    let source = ChunkSource::new(&[url], ROOT_TX).with_retry(fast_retry(1));
+    let err = run(source).await.unwrap_err();
+
+    assert!(err.contains("does not match its proven hash"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_corrupted_node_fails_over_to_the_next_one() {
+    let items = sample_items();
+    let mut honest = MockNode::default();
+    honest.add_tx(ROOT_TX, bundle_bytes(&items));
+    let mut corrupted = honest.clone();
+    corrupted.corrupt_chunks = true;
+    corrupted.requests = Default::default();
+
+    let urls = [corrupted.serve().await, honest.serve().await];
+    let source = ChunkSource::new(&urls, ROOT_TX).with_retry(fast_retry(1));
+    let actual = run(source).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+}
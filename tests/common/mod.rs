@@ -0,0 +1,209 @@
+// Minimal ANS-104 writer used to build bundles for tests without the network.
+// It only produces the binary layout, signatures are random bytes and are never verified.
+#![allow(dead_code)]
+
+pub mod http;
+pub mod node;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone)]
+pub struct TestItem {
+    pub signature_type: u16,
+    pub signature: Vec<u8>,
+    pub owner: Vec<u8>,
+    pub target: Option<[u8; 32]>,
+    pub anchor: Option<[u8; 32]>,
+    pub tags: Vec<(Vec<u8>, Vec<u8>)>,
+    pub data: TestData,
+}
+
+#[derive(Debug, Clone)]
+pub enum TestData {
+    Raw(Vec<u8>),
+    Bundle(Vec<TestItem>),
+}
+
+/// Item the parser is expected to emit, flattened in stream order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpectedItem {
+    pub id: [u8; 32],
+    pub signature: Vec<u8>,
+    pub owner: Vec<u8>,
+    pub target: Option<[u8; 32]>,
+    pub anchor: Option<[u8; 32]>,
+    pub tags: Vec<(Vec<u8>, Vec<u8>)>,
+    pub bundled_in: String,
+    pub is_bundle: bool,
+}
+
+impl TestItem {
+    pub fn new(signature_type: u16, data: Vec<u8>) -> Self {
+        let (sig_len, owner_len) = lengths(signature_type);
+        let seed = Sha256::digest(&data);
+        TestItem {
+            signature_type,
+            signature: seed.iter().cycle().take(sig_len).copied().collect(),
+            owner: vec![7u8; owner_len],
+            target: None,
+            anchor: None,
+            tags: vec![(b"Content-Type".to_vec(), b"text/plain".to_vec())],
+            data: TestData::Raw(data),
+        }
+    }
+
+    pub fn with_signature_seed(mut self, seed: u8) -> Self {
+        for (i, byte) in self.signature.iter_mut().enumerate() {
+            *byte = seed.wrapping_add(i as u8);
+        }
+        self
+    }
+
+    pub fn nested(signature_type: u16, items: Vec<TestItem>) -> Self {
+        let mut item = TestItem::new(signature_type, Vec::new());
+        item.tags = bundle_tags();
+        item.data = TestData::Bundle(items);
+        item
+    }
+
+    pub fn id(&self) -> [u8; 32] {
+        Sha256::digest(&self.signature).into()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.signature_type.to_le_bytes());
+        out.extend_from_slice(&self.signature);
+        out.extend_from_slice(&self.owner);
+        push_optional(&mut out, &self.target);
+        push_optional(&mut out, &self.anchor);
+
+        let tags = encode_avro_tags(&self.tags);
+        out.extend_from_slice(&(self.tags.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+        out.extend_from_slice(&tags);
+
+        match &self.data {
+            TestData::Raw(data) => out.extend_from_slice(data),
+            TestData::Bundle(items) => out.extend_from_slice(&bundle_bytes(items)),
+        }
+        out
+    }
+}
+
+pub fn lengths(signature_type: u16) -> (usize, usize) {
+    match signature_type {
+        1 => (512, 512),
+        2 => (64, 32),
+        _ => panic!("unsupported signature type {}", signature_type),
+    }
+}
+
+pub fn bundle_tags() -> Vec<(Vec<u8>, Vec<u8>)> {
+    vec![
+        (b"Bundle-Format".to_vec(), b"binary".to_vec()),
+        (b"Bundle-Version".to_vec(), b"2.0.0".to_vec()),
+    ]
+}
+
+pub fn bundle_bytes(items: &[TestItem]) -> Vec<u8> {
+    let encoded: Vec<(Vec<u8>, [u8; 32])> = items
+        .iter()
+        .map(|item| (item.to_bytes(), item.id()))
+        .collect();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&number_bytes(items.len()));
+    for (bytes, id) in &encoded {
+        out.extend_from_slice(&number_bytes(bytes.len()));
+        out.extend_from_slice(id);
+    }
+    for (bytes, _) in encoded {
+        out.extend_from_slice(&bytes);
+    }
+    out
+}
+
+pub fn number_bytes(value: usize) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out[..8].copy_from_slice(&(value as u64).to_le_bytes());
+    out
+}
+
+pub fn expected_items(items: &[TestItem], bundled_in: &str) -> Vec<ExpectedItem> {
+    let mut out = Vec::new();
+    for item in items {
+        let is_bundle = matches!(item.data, TestData::Bundle(_));
+        out.push(ExpectedItem {
+            id: item.id(),
+            signature: item.signature.clone(),
+            owner: item.owner.clone(),
+            target: item.target,
+            anchor: item.anchor,
+            tags: item.tags.clone(),
+            bundled_in: bundled_in.to_string(),
+            is_bundle,
+        });
+        if let TestData::Bundle(children) = &item.data {
+            out.extend(expected_items(children, &BASE64_URL.encode(item.id())));
+        }
+    }
+    out
+}
+
+pub fn actual_item(item: &arweave_ans_1040_indexer::DataItem) -> ExpectedItem {
+    ExpectedItem {
+        id: item.id(),
+        signature: item.signature().to_vec(),
+        owner: item.owner().to_vec(),
+        target: item.target().copied(),
+        anchor: item.anchor().copied(),
+        tags: item
+            .tags()
+            .iter()
+            .map(|tag| (tag.name().to_vec(), tag.value().to_vec()))
+            .collect(),
+        bundled_in: item.bundled_in().to_string(),
+        is_bundle: item.is_bundle(),
+    }
+}
+
+fn push_optional(out: &mut Vec<u8>, value: &Option<[u8; 32]>) {
+    match value {
+        Some(bytes) => {
+            out.push(1);
+            out.extend_from_slice(bytes);
+        }
+        None => out.push(0),
+    }
+}
+
+fn encode_avro_tags(tags: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if tags.is_empty() {
+        return out;
+    }
+    push_zigzag(&mut out, tags.len() as i64);
+    for (name, value) in tags {
+        push_zigzag(&mut out, name.len() as i64);
+        out.extend_from_slice(name);
+        push_zigzag(&mut out, value.len() as i64);
+        out.extend_from_slice(value);
+    }
+    out.push(0);
+    out
+}
+
+fn push_zigzag(out: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    loop {
+        if n & !0x7F == 0 {
+            out.push(n as u8);
+            break;
+        }
+        out.push((n & 0x7F) as u8 | 0x80);
+        n >>= 7;
+    }
+}
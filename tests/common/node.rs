@@ -0,0 +1,212 @@
+// Mock Arweave node serving transactions and their chunks with Merkle proofs.
+
+use std::sync::{Arc, Mutex};
+
+use arweave_ans_1040_indexer::{chunk_proofs, ChunkProof};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use serde_json::json;
+
+use super::http::{range_start, serve, Request, Response};
+use super::{bundle_tags, TestItem};
+
+// Weave offset of the first byte of the first transaction.
+const WEAVE_START: u64 = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct MockTx {
+    pub id: String,
+    pub data: Vec<u8>,
+    pub data_root: [u8; 32],
+    pub proofs: Vec<ChunkProof>,
+    // weave offset of the first data byte
+    pub start: u64,
+    // extra fields merged into the `/tx/{id}` response
+    pub fields: serde_json::Value,
+    // `/tx/{id}/status` response, pending when missing
+    pub status: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockNode {
+    pub txs: Vec<MockTx>,
+    // `/block/height/{height}` responses
+    pub blocks: Vec<serde_json::Value>,
+    // flip a byte of every chunk served
+    pub corrupt_chunks: bool,
+    pub requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockNode {
+    pub fn add_tx(&mut self, id: &str, data: Vec<u8>) -> &mut MockTx {
+        let start = self
+            .txs
+            .last()
+            .map(|tx| tx.start + tx.data.len() as u64)
+            .unwrap_or(WEAVE_START);
+        let (data_root, proofs) = chunk_proofs(&data);
+        self.txs.push(MockTx {
+            id: id.to_string(),
+            data,
+            data_root,
+            proofs,
+            start,
+            fields: json!({}),
+            status: None,
+        });
+        self.txs.last_mut().unwrap()
+    }
+
+    // Adds a transaction holding `items`, tagged as a bundle and owned by `owner`.
+    pub fn add_bundle_tx(&mut self, id: &str, items: &[TestItem], owner: &[u8]) -> &mut MockTx {
+        let tags: Vec<serde_json::Value> = bundle_tags()
+            .iter()
+            .map(|(name, value)| {
+                json!({ "name": BASE64_URL.encode(name), "value": BASE64_URL.encode(value) })
+            })
+            .collect();
+        let tx = self.add_tx(id, super::bundle_bytes(items));
+        tx.fields = json!({ "owner": BASE64_URL.encode(owner), "tags": tags });
+        tx
+    }
+
+    // Adds block `height` with hash `block-{height}`, following `block-{height - 1}`.
+    pub fn add_block(
+        &mut self,
+        height: u64,
+        timestamp: u64,
+        tx_ids: &[&str],
+    ) -> &mut serde_json::Value {
+        let previous_block = if height == 0 {
+            String::new()
+        } else {
+            format!("block-{}", height - 1)
+        };
+        self.blocks.push(json!({
+            "indep_hash": format!("block-{}", height),
+            "height": height,
+            "timestamp": timestamp,
+            "previous_block": previous_block,
+            "txs": tx_ids,
+        }));
+        self.blocks.last_mut().unwrap()
+    }
+
+    pub fn tx_json(tx: &MockTx) -> serde_json::Value {
+        let mut header = json!({
+            "format": 2,
+            "id": tx.id,
+            "data_root": BASE64_URL.encode(tx.data_root),
+            "data_size": tx.data.len().to_string(),
+        });
+        if let serde_json::Value::Object(fields) = &tx.fields {
+            for (key, value) in fields {
+                header[key] = value.clone();
+            }
+        }
+        header
+    }
+
+    pub fn handle(&self, request: &Request) -> Response {
+        self.requests.lock().unwrap().push(request.path.clone());
+        let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+
+        match segments.as_slice() {
+            ["info"] => match self
+                .blocks
+                .iter()
+                .max_by_key(|block| block["height"].as_u64())
+            {
+                Some(tip) => json_response(&json!({
+                    "network": "arweave.N.1",
+                    "height": tip["height"],
+                    "current": tip["indep_hash"],
+                })),
+                None => Response::status(404),
+            },
+            ["block", "height", height] => match self
+                .blocks
+                .iter()
+                .find(|block| block["height"].as_u64() == height.parse().ok())
+            {
+                Some(block) => json_response(block),
+                None => Response::status(404),
+            },
+            ["tx", id] => match self.tx(id) {
+                Some(tx) => json_response(&Self::tx_json(tx)),
+                None => Response::status(404),
+            },
+            ["tx", id, "status"] => match self.tx(id) {
+                Some(MockTx {
+                    status: Some(status),
+                    ..
+                }) => json_response(status),
+                Some(_) => Response {
+                    status: 202,
+                    ..Response::ok(b"Pending".to_vec())
+                },
+                None => Response::status(404),
+            },
+            ["tx", id, "offset"] => match self.tx(id) {
+                Some(tx) => json_response(&json!({
+                    "size": tx.data.len().to_string(),
+                    "offset": (tx.start + tx.data.len() as u64 - 1).to_string(),
+                })),
+                None => Response::status(404),
+            },
+            ["chunk", offset] => match offset.parse().ok().and_then(|o| self.chunk(o)) {
+                Some(response) => response,
+                None => Response::status(404),
+            },
+            // raw data, as served by gateways
+            [id] => match (self.tx(id), range_start(request)) {
+                (Some(tx), Some(start)) if start < tx.data.len() => Response {
+                    status: 206,
+                    ..Response::ok(tx.data[start..].to_vec())
+                }
+                .with_header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, tx.data.len() - 1, tx.data.len()),
+                ),
+                (Some(tx), _) => Response::ok(tx.data.clone()),
+                (None, _) => Response::status(404),
+            },
+            _ => Response::status(404),
+        }
+    }
+
+    pub async fn serve(self) -> String {
+        serve(move |request| self.handle(&request)).await
+    }
+
+    fn tx(&self, id: &str) -> Option<&MockTx> {
+        self.txs.iter().find(|tx| tx.id == id)
+    }
+
+    fn chunk(&self, offset: u64) -> Option<Response> {
+        let tx = self
+            .txs
+            .iter()
+            .find(|tx| (tx.start..tx.start + tx.data.len() as u64).contains(&offset))?;
+        let relative = offset - tx.start;
+        let proof = tx
+            .proofs
+            .iter()
+            .find(|proof| (proof.start..proof.end).contains(&relative))?;
+
+        let mut chunk = tx.data[proof.start as usize..proof.end as usize].to_vec();
+        if self.corrupt_chunks {
+            chunk[0] ^= 0xFF;
+        }
+        Some(json_response(&json!({
+            "chunk": BASE64_URL.encode(chunk),
+            "data_path": BASE64_URL.encode(&proof.data_path),
+            "tx_path": "",
+            "packing": "unpacked",
+        })))
+    }
+}
+
+pub fn json_response(value: &serde_json::Value) -> Response {
+    Response::ok(value.to_string().into_bytes()).with_header("Content-Type", "application/json")
+}
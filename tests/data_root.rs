@@ -0,0 +1,137 @@
+mod common;
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{
+    data_root, process_bundle_with_options, BundleReport, DataItem, DataRootCheck, NodeClient,
+    ProcessOptions, RetryPolicy, MAX_CHUNK_SIZE,
+};
+use common::node::MockNode;
+use common::{bundle_bytes, TestItem};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+// Spans several chunks, the first payload ends in the middle of the second one.
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; MAX_CHUNK_SIZE as usize + 1000]).with_signature_seed(1),
+        TestItem::new(1, vec![2u8; 80]).with_signature_seed(2),
+        TestItem::nested(2, vec![TestItem::new(2, vec![3u8; 40_000])]),
+    ]
+}
+
+async fn process(data: Vec<u8>, options: ProcessOptions) -> Result<(BundleReport, usize), String> {
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let read_handle = tokio::spawn(async move {
+        let mut count = 0;
+        while rx.recv().await.is_some() {
+            count += 1;
+        }
+        count
+    });
+    let mut cursor = Cursor::new(data);
+    let result = process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options).await;
+    let count = read_handle.await.unwrap();
+    result.map(|report| (report, count))
+}
+
+#[tokio::test]
+async fn test_data_root_of_the_transaction_header_is_verified() {
+    let items = sample_items();
+    let mut node = MockNode::default();
+    node.add_bundle_tx(ROOT_TX, &items, &[9u8; 32]);
+    let client = NodeClient::new(&[node.serve().await]).with_retry(RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    });
+    let header = client.transaction(ROOT_TX).await.unwrap();
+
+    let options = ProcessOptions {
+        expected_size: Some(header.data_size),
+        data_root: header.data_root,
+        ..ProcessOptions::default()
+    };
+    let (report, count) = process(bundle_bytes(&items), options).await.unwrap();
+
+    assert_eq!(report.data_root, Some(DataRootCheck::Verified));
+    assert_eq!(count, 4);
+}
+
+#[tokio::test]
+async fn test_tampered_payload_is_reported_as_mismatch() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let expected = data_root(&bytes);
+
+    // payload bytes are not covered by the item ids, the items still parse
+    let mut tampered = bytes.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 0xFF;
+
+    let options = ProcessOptions {
+        expected_size: Some(bytes.len() as u64),
+        data_root: Some(expected),
+        ..ProcessOptions::default()
+    };
+    let (report, count) = process(tampered.clone(), options).await.unwrap();
+
+    assert_eq!(count, 4);
+    assert_eq!(
+        report.data_root,
+        Some(DataRootCheck::Mismatch {
+            expected,
+            computed: data_root(&tampered),
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_bytes_after_the_last_entry_are_hashed() {
+    let mut bytes = bundle_bytes(&sample_items());
+    bytes.extend_from_slice(&[7u8; 5000]);
+
+    let options = ProcessOptions {
+        expected_size: Some(bytes.len() as u64),
+        data_root: Some(data_root(&bytes)),
+        ..ProcessOptions::default()
+    };
+    let (report, _) = process(bytes, options).await.unwrap();
+
+    assert_eq!(report.data_root, Some(DataRootCheck::Verified));
+}
+
+#[tokio::test]
+async fn test_stream_longer_than_the_data_size_is_incomplete() {
+    let bytes = bundle_bytes(&sample_items());
+    let mut longer = bytes.clone();
+    longer.extend_from_slice(&[0u8; 10]);
+
+    let options = ProcessOptions {
+        expected_size: Some(bytes.len() as u64),
+        data_root: Some(data_root(&bytes)),
+        ..ProcessOptions::default()
+    };
+    let (report, _) = process(longer, options).await.unwrap();
+
+    assert!(
+        matches!(report.data_root, Some(DataRootCheck::Incomplete(_))),
+        "{:?}",
+        report.data_root
+    );
+}
+
+#[tokio::test]
+async fn test_data_root_without_size_is_rejected() {
+    let bytes = bundle_bytes(&sample_items());
+    let options = ProcessOptions {
+        data_root: Some(data_root(&bytes)),
+        ..ProcessOptions::default()
+    };
+
+    let err = process(bytes, options).await.unwrap_err();
+
+    assert!(err.contains("expected size"), "{}", err);
+}
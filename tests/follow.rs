@@ -0,0 +1,350 @@
+mod common;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{
+    write_items_with_events, DataItem, DataSource, FollowEvent, FollowState, Follower, Indexer,
+    NodeClient, RetryPolicy, Sink,
+};
+use common::http::serve;
+use common::node::MockNode;
+use common::TestItem;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+const TIMESTAMP: u64 = 1_700_000_000;
+
+fn bundle(seed: u8) -> Vec<TestItem> {
+    vec![TestItem::new(2, vec![seed; 100]).with_signature_seed(seed)]
+}
+
+// Blocks 10 to `tip`, with bundles in blocks 10 and 11.
+fn chain(tip: u64) -> MockNode {
+    let mut node = MockNode::default();
+    node.add_bundle_tx("bundle-a", &bundle(1), &[1u8; 32]);
+    node.add_bundle_tx("bundle-b", &bundle(2), &[2u8; 32]);
+    node.add_bundle_tx("bundle-c", &bundle(3), &[3u8; 32]);
+    node.add_block(10, TIMESTAMP, &["bundle-a"]);
+    node.add_block(11, TIMESTAMP + 120, &["bundle-b"]);
+    for height in 12..=tip {
+        node.add_block(height, TIMESTAMP + 120 * (height - 10), &[]);
+    }
+    node
+}
+
+// `chain(tip)` where the blocks from `from` are replaced by another branch, holding bundle-c.
+fn fork(tip: u64, from: u64) -> MockNode {
+    let mut node = chain(tip);
+    for block in node.blocks.iter_mut() {
+        let height = block["height"].as_u64().unwrap();
+        if height >= from {
+            block["indep_hash"] = format!("fork-{}", height).into();
+            block["txs"] = serde_json::json!([]);
+        }
+        if height > from {
+            block["previous_block"] = format!("fork-{}", height - 1).into();
+        }
+    }
+    node.blocks[(from - 10) as usize]["txs"] = serde_json::json!(["bundle-c"]);
+    node
+}
+
+// Serves `before` until the returned flag is set, then `after`.
+async fn switching_node(before: MockNode, after: MockNode) -> (String, Arc<AtomicBool>) {
+    let switched = Arc::new(AtomicBool::new(false));
+    let flag = switched.clone();
+    let url = serve(move |request| {
+        if flag.load(Ordering::SeqCst) {
+            after.handle(&request)
+        } else {
+            before.handle(&request)
+        }
+    })
+    .await;
+    (url, switched)
+}
+
+fn indexer(url: String) -> Indexer {
+    let retry = RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    };
+    Indexer::new(
+        NodeClient::new(std::slice::from_ref(&url)).with_retry(retry.clone()),
+        DataSource::Gateways(vec![url]),
+    )
+    .with_retry(retry)
+}
+
+async fn step(follower: &mut Follower) -> (Vec<u64>, Vec<u64>, Vec<DataItem>) {
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(item);
+        }
+        items
+    });
+    let step = follower.step(tx).await.unwrap();
+    let items = read_handle.await.unwrap();
+    (
+        step.orphaned.iter().map(|block| block.height).collect(),
+        step.blocks.iter().map(|block| block.height).collect(),
+        items,
+    )
+}
+
+// Number of items written and next height of the saved state, at a flush.
+type Flush = (usize, Option<u64>);
+
+// Records every flush.
+struct StateCheckingSink {
+    state_path: PathBuf,
+    written: usize,
+    flushes: Arc<Mutex<Vec<Flush>>>,
+}
+
+impl Sink for StateCheckingSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        self.written += items.len();
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        let saved = FollowState::load(&self.state_path).await.unwrap();
+        self.flushes
+            .lock()
+            .unwrap()
+            .push((self.written, saved.map(|state| state.next_height)));
+        Ok(None)
+    }
+
+    async fn finish(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn hashes(state: &FollowState) -> Vec<&str> {
+    state
+        .recent
+        .iter()
+        .map(|block| block.hash.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_orphaned_blocks_are_indexed_again() {
+    let (url, switched) = switching_node(chain(12), fork(13, 11)).await;
+    let dir = tempfile::tempdir().unwrap();
+    let state_path = dir.path().join("state.json");
+    let mut follower = Follower::new(indexer(url), FollowState::starting_at(10))
+        .with_confirmations(3)
+        .with_state_file(&state_path);
+
+    let (orphaned, blocks, items) = step(&mut follower).await;
+    assert!(orphaned.is_empty());
+    assert_eq!(blocks, vec![10, 11, 12]);
+    assert_eq!(items.len(), 2);
+    assert_eq!(follower.state().recent[1].bundles, vec!["bundle-b"]);
+
+    let (orphaned, blocks, _) = step(&mut follower).await;
+    assert!(orphaned.is_empty());
+    assert!(blocks.is_empty());
+
+    switched.store(true, Ordering::SeqCst);
+    let (orphaned, blocks, items) = step(&mut follower).await;
+    assert_eq!(orphaned, vec![11, 12]);
+    assert_eq!(blocks, vec![11, 12, 13]);
+    assert_eq!(items.len(), 1);
+    let root_tx = items[0].root_tx().unwrap();
+    assert_eq!(root_tx.id, "bundle-c");
+    assert_eq!(root_tx.block_hash.as_deref(), Some("fork-11"));
+
+    let saved = FollowState::load(&state_path).await.unwrap().unwrap();
+    assert_eq!(&saved, follower.state());
+    assert_eq!(saved.next_height, 14);
+    assert_eq!(hashes(&saved), vec!["fork-11", "fork-12", "fork-13"]);
+}
+
+#[tokio::test]
+async fn test_fork_deeper_than_confirmations_fails() {
+    let (url, switched) = switching_node(chain(12), fork(12, 11)).await;
+    let mut follower =
+        Follower::new(indexer(url), FollowState::starting_at(10)).with_confirmations(2);
+    step(&mut follower).await;
+
+    switched.store(true, Ordering::SeqCst);
+    let (tx, _rx) = mpsc::channel::<DataItem>(16);
+    let err = follower.step(tx.clone()).await.unwrap_err();
+    assert!(err.contains("Fork of at least 2 blocks"), "{}", err);
+
+    // the orphaned blocks are still remembered, the follower does not move on
+    assert_eq!(hashes(follower.state()), vec!["block-11", "block-12"]);
+    let err = follower.step(tx).await.unwrap_err();
+    assert!(err.contains("Fork of at least 2 blocks"), "{}", err);
+    assert_eq!(follower.state().next_height, 13);
+}
+
+#[tokio::test]
+async fn test_failed_bundles_are_retried() {
+    // the header of bundle-b cannot be fetched at first
+    let mut broken = chain(12);
+    broken.txs.retain(|tx| tx.id != "bundle-b");
+    let (url, switched) = switching_node(broken, chain(12)).await;
+    let mut follower =
+        Follower::new(indexer(url), FollowState::starting_at(10)).with_bundle_retries(2);
+
+    let (_, blocks, items) = step(&mut follower).await;
+    assert_eq!(blocks, vec![10, 11, 12]);
+    assert_eq!(items.len(), 1);
+    let failed: Vec<(&str, u64)> = follower
+        .state()
+        .failed
+        .iter()
+        .map(|bundle| (bundle.tx_id.as_str(), bundle.height))
+        .collect();
+    assert_eq!(failed, vec![("bundle-b", 11)]);
+
+    // still failing, it is kept for one more retry
+    let (tx, rx) = mpsc::channel::<DataItem>(16);
+    drop(rx);
+    let step_report = follower.step(tx).await.unwrap();
+    assert!(step_report.retried[0].result.is_err());
+    assert_eq!(follower.state().failed[0].retries, 1);
+
+    switched.store(true, Ordering::SeqCst);
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let step_report = follower.step(tx).await.unwrap();
+    let item = rx.recv().await.unwrap();
+    assert_eq!(
+        step_report.events(),
+        vec![FollowEvent::Retried {
+            tx_id: "bundle-b".to_string()
+        }]
+    );
+    assert!(step_report.retried[0].result.is_ok());
+    assert!(step_report.blocks.is_empty());
+    assert!(follower.state().failed.is_empty());
+    let root_tx = item.root_tx().unwrap();
+    assert_eq!(root_tx.id, "bundle-b");
+    assert_eq!(root_tx.block_height, Some(11));
+}
+
+#[tokio::test]
+async fn test_orphaned_blocks_are_reported_with_their_bundles() {
+    let (url, switched) = switching_node(chain(12), fork(13, 11)).await;
+    let mut follower =
+        Follower::new(indexer(url), FollowState::starting_at(10)).with_confirmations(3);
+    step(&mut follower).await;
+
+    switched.store(true, Ordering::SeqCst);
+    let (tx, _rx) = mpsc::channel::<DataItem>(16);
+    let events = follower.step(tx).await.unwrap().events();
+
+    let orphaned: Vec<(u64, &str, Vec<String>)> = events
+        .iter()
+        .map(|event| match event {
+            FollowEvent::Orphaned(block) => {
+                (block.height, block.hash.as_str(), block.bundles.clone())
+            }
+            FollowEvent::Retried { tx_id } => panic!("{} was not retried", tx_id),
+        })
+        .collect();
+    assert_eq!(
+        orphaned,
+        vec![
+            (11, "block-11", vec!["bundle-b".to_string()]),
+            (12, "block-12", vec![]),
+        ]
+    );
+    let line = serde_json::to_value(&events[0]).unwrap();
+    assert_eq!(line["event"], "orphaned");
+    assert_eq!(line["bundles"], serde_json::json!(["bundle-b"]));
+}
+
+#[tokio::test]
+async fn test_state_is_saved_once_the_items_are_flushed() {
+    let url = chain(12).serve().await;
+    let dir = tempfile::tempdir().unwrap();
+    let state_path = dir.path().join("state.json");
+    let (tx, rx) = mpsc::channel(16);
+    let (events, events_rx) = mpsc::channel(16);
+    let flushes = Arc::new(Mutex::new(Vec::new()));
+    let sink = StateCheckingSink {
+        state_path: state_path.clone(),
+        written: 0,
+        flushes: flushes.clone(),
+    };
+    let writer = tokio::spawn(write_items_with_events(rx, sink, 16, None, Some(events_rx)));
+    let mut follower = Follower::new(
+        indexer(url).with_events(events),
+        FollowState::starting_at(10),
+    )
+    .with_state_file(&state_path);
+
+    follower.step(tx).await.unwrap();
+    drop(follower);
+    writer.await.unwrap().unwrap();
+
+    // every block is flushed with its items before it is saved
+    assert_eq!(
+        *flushes.lock().unwrap(),
+        vec![(1, None), (2, Some(11)), (2, Some(12))]
+    );
+    let saved = FollowState::load(&state_path).await.unwrap().unwrap();
+    assert_eq!(saved.next_height, 13);
+}
+
+#[tokio::test]
+async fn test_cancelled_follower_stops_between_blocks() {
+    let url = chain(12).serve().await;
+    let cancel = CancellationToken::new();
+    let mut follower =
+        Follower::new(indexer(url), FollowState::starting_at(10)).with_cancellation(cancel.clone());
+    cancel.cancel();
+
+    let (_, blocks, items) = step(&mut follower).await;
+
+    assert!(blocks.is_empty());
+    assert!(items.is_empty());
+    assert_eq!(follower.state().next_height, 10);
+}
+
+#[tokio::test]
+async fn test_restart_resumes_from_the_saved_state() {
+    let (url, switched) = switching_node(chain(12), chain(13)).await;
+    let dir = tempfile::tempdir().unwrap();
+    let state_path = dir.path().join("state.json");
+    let mut follower = Follower::new(indexer(url.clone()), FollowState::starting_at(10))
+        .with_state_file(&state_path);
+    step(&mut follower).await;
+    drop(follower);
+
+    switched.store(true, Ordering::SeqCst);
+    let state = FollowState::load(&state_path).await.unwrap().unwrap();
+    let mut follower = Follower::new(indexer(url), state).with_state_file(&state_path);
+    let (orphaned, blocks, items) = step(&mut follower).await;
+
+    assert!(orphaned.is_empty());
+    assert_eq!(blocks, vec![13]);
+    assert!(items.is_empty());
+    assert_eq!(
+        hashes(follower.state()),
+        vec!["block-10", "block-11", "block-12", "block-13"]
+    );
+}
+
+#[tokio::test]
+async fn test_missing_state_file_loads_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let state = FollowState::load(&dir.path().join("missing.json"))
+        .await
+        .unwrap();
+
+    assert_eq!(state, None);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, GatewaySource, InputSource, ProcessOptions, RetryPolicy,
+};
+use common::http::{range_start, serve, Request, Response};
+use common::{actual_item, bundle_bytes, expected_items, ExpectedItem, TestItem};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; 700]).with_signature_seed(1),
+        TestItem::new(1, vec![2u8; 900]).with_signature_seed(2),
+        TestItem::new(2, vec![3u8; 300]).with_signature_seed(3),
+    ]
+}
+
+fn fast_retry(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(5),
+    }
+}
+
+// Serves `bytes` with Range support, dropping every connection after `chunk` body bytes.
+fn flaky(
+    bytes: Vec<u8>,
+    chunk: usize,
+    requests: Arc<Mutex<Vec<Request>>>,
+) -> impl Fn(Request) -> Response {
+    move |request| {
+        requests.lock().unwrap().push(request.clone());
+        match range_start(&request) {
+            Some(start) => Response::status(206)
+                .with_header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, bytes.len() - 1, bytes.len()),
+                )
+                .with_body(bytes[start..].to_vec())
+                .cut_after(chunk),
+            None => Response::ok(bytes.clone()).cut_after(chunk),
+        }
+    }
+}
+
+async fn run(source: GatewaySource) -> Result<Vec<ExpectedItem>, String> {
+    let input = source.open().await?;
+    let (tx, mut rx) = mpsc::channel(16);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(actual_item(&item));
+        }
+        items
+    });
+
+    let options = ProcessOptions {
+        expected_size: input.size,
+        ..ProcessOptions::default()
+    };
+    let mut reader = input.reader;
+    let report = process_bundle_with_options(&mut reader, tx, ROOT_TX, &options).await;
+    let items = read_handle.await.unwrap();
+    report.map(|_| items)
+}
+
+#[tokio::test]
+async fn test_dropped_connections_resume_with_range_requests() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let base_url = serve(flaky(bytes.clone(), 500, requests.clone())).await;
+
+    let source = GatewaySource::new(&base_url, ROOT_TX).with_retry(fast_retry(2));
+    let actual = run(source).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+    let starts: Vec<Option<usize>> = requests.lock().unwrap().iter().map(range_start).collect();
+    let expected_starts: Vec<Option<usize>> = (0..bytes.len())
+        .step_by(500)
+        .map(|start| (start > 0).then_some(start))
+        .collect();
+    assert_eq!(starts, expected_starts);
+}
+
+#[tokio::test]
+async fn test_failing_gateway_fails_over_to_the_next_one() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+
+    let broken_requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = broken_requests.clone();
+    let broken = serve(move |request| {
+        recorded.lock().unwrap().push(request);
+        Response::status(502)
+    })
+    .await;
+    let healthy = serve(flaky(bytes.clone(), 1000, Arc::new(Mutex::new(Vec::new())))).await;
+
+    let source =
+        GatewaySource::with_gateways(&[broken, healthy], ROOT_TX).with_retry(fast_retry(4));
+    let actual = run(source).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+    // every other attempt lands on the broken gateway, each one resuming where the last ended
+    let starts: Vec<Option<usize>> = broken_requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(range_start)
+        .collect();
+    let expected_starts: Vec<Option<usize>> = (0..bytes.len())
+        .step_by(1000)
+        .map(|start| (start > 0).then_some(start))
+        .collect();
+    assert_eq!(starts, expected_starts);
+}
+
+#[tokio::test]
+async fn test_gateway_ignoring_range_is_resumed_by_discarding() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let attempts = Arc::new(Mutex::new(0usize));
+    let served = bytes.clone();
+    let base_url = serve(move |_| {
+        let mut attempts = attempts.lock().unwrap();
+        *attempts += 1;
+        // always the whole body from the start, a bit more of it every time
+        Response::ok(served.clone()).cut_after(*attempts * 800)
+    })
+    .await;
+
+    let source = GatewaySource::new(&base_url, ROOT_TX).with_retry(fast_retry(1));
+    let actual = run(source).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+}
+
+#[tokio::test]
+async fn test_exhausted_retries_fail_the_run() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let served = bytes.clone();
+    let base_url = serve(move |request| match range_start(&request) {
+        Some(_) => Response::status(503),
+        None => Response::ok(served.clone()).cut_after(600),
+    })
+    .await;
+
+    let source = GatewaySource::new(&base_url, ROOT_TX).with_retry(fast_retry(3));
+    let err = run(source).await.unwrap_err();
+
+    assert!(err.contains("after 4 attempts"), "{}", err);
+}
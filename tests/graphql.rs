@@ -0,0 +1,164 @@
+mod common;
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use arweave_ans_1040_indexer::{
+    gateway_schema, process_bundle_with_options, write_items, DataItem, GatewaySchema, ItemStore,
+    ProcessOptions, RootTransaction,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, TestItem};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+fn leaf(seed: u8) -> TestItem {
+    TestItem::new(2, vec![seed; 40]).with_signature_seed(seed)
+}
+
+// A root bundle holding an item and a nested bundle of two items, one of them tagged.
+fn nested_sample() -> Vec<TestItem> {
+    let mut tagged = leaf(3);
+    tagged
+        .tags
+        .push((b"App-Name".to_vec(), b"indexer".to_vec()));
+    vec![
+        leaf(1),
+        TestItem::nested(2, vec![leaf(2), tagged]).with_signature_seed(4),
+    ]
+}
+
+async fn parse(items: &[TestItem]) -> Vec<DataItem> {
+    let options = ProcessOptions {
+        root_tx: Some(Arc::new(RootTransaction {
+            id: ROOT_TX.to_string(),
+            owner_address: "owner".to_string(),
+            data_size: 1000,
+            block_height: Some(42),
+            block_hash: Some("hash".to_string()),
+            block_timestamp: Some(1700000000),
+        })),
+        ..ProcessOptions::default()
+    };
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cursor = Cursor::new(bundle_bytes(items));
+    process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options)
+        .await
+        .unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+// Indexes the sample into a new store, returns its schema and the ids of the items.
+async fn schema(dir: &tempfile::TempDir) -> (GatewaySchema, Vec<String>) {
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    let items = parse(&nested_sample()).await;
+    let ids = items
+        .iter()
+        .map(|item| BASE64_URL.encode(item.id()))
+        .collect();
+    let (tx, rx) = mpsc::channel(16);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    write_items(rx, store.clone(), 2, None).await.unwrap();
+    (gateway_schema(store), ids)
+}
+
+async fn execute(schema: &GatewaySchema, query: &str) -> Value {
+    let response = schema.execute(query).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().unwrap()
+}
+
+#[tokio::test]
+async fn test_transactions_are_filtered_like_on_gateways() {
+    let dir = tempfile::tempdir().unwrap();
+    let (schema, ids) = schema(&dir).await;
+    let (nested, tagged) = (&ids[1], &ids[3]);
+
+    let query = format!(
+        r#"{{
+            transactions(bundledIn: ["{}"], tags: [{{name: "App-Name", values: ["other", "indexer"]}}]) {{
+                edges {{ node {{
+                    id
+                    data {{ size type }}
+                    tags {{ name value }}
+                    block {{ id height timestamp }}
+                    bundledIn {{ id }}
+                }} }}
+            }}
+        }}"#,
+        nested
+    );
+    let data = execute(&schema, &query).await;
+    assert_eq!(
+        data["transactions"]["edges"],
+        json!([{ "node": {
+            "id": tagged,
+            "data": { "size": "40", "type": "text/plain" },
+            "tags": [
+                { "name": "Content-Type", "value": "text/plain" },
+                { "name": "App-Name", "value": "indexer" },
+            ],
+            "block": { "id": "hash", "height": 42, "timestamp": 1700000000 },
+            "bundledIn": { "id": nested },
+        } }])
+    );
+
+    let query = format!(
+        r#"{{ transactions(ids: ["{}", "{}"], owners: ["unknown"]) {{ edges {{ cursor }} }} }}"#,
+        ids[0], tagged
+    );
+    let data = execute(&schema, &query).await;
+    assert_eq!(data["transactions"]["edges"], json!([]));
+
+    let query = format!(r#"{{ transaction(id: "{}") {{ id }} }}"#, tagged);
+    assert_eq!(
+        execute(&schema, &query).await["transaction"]["id"],
+        json!(tagged)
+    );
+    let data = execute(&schema, r#"{ transaction(id: "missing") { id } }"#).await;
+    assert_eq!(data["transaction"], Value::Null);
+}
+
+#[tokio::test]
+async fn test_transactions_are_paginated_by_cursor() {
+    let dir = tempfile::tempdir().unwrap();
+    let (schema, mut ids) = schema(&dir).await;
+    ids.sort();
+
+    let mut paged = Vec::new();
+    let mut after = String::new();
+    loop {
+        let query = format!(
+            r#"{{ transactions(first: 3, after: {}) {{
+                pageInfo {{ hasNextPage }}
+                edges {{ cursor node {{ id }} }}
+            }} }}"#,
+            if after.is_empty() {
+                "null".to_string()
+            } else {
+                format!("\"{}\"", after)
+            }
+        );
+        let data = execute(&schema, &query).await;
+        let page = &data["transactions"];
+        for edge in page["edges"].as_array().unwrap() {
+            assert_eq!(edge["cursor"], edge["node"]["id"]);
+            paged.push(edge["node"]["id"].as_str().unwrap().to_string());
+            after = edge["cursor"].as_str().unwrap().to_string();
+        }
+        if !page["pageInfo"]["hasNextPage"].as_bool().unwrap() {
+            break;
+        }
+    }
+    assert_eq!(paged, ids);
+}
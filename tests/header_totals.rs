@@ -0,0 +1,231 @@
+mod common;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, BundleReport, DataItem, ProcessOptions, SizeMismatch,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, bundle_tags, TestData, TestItem};
+use std::io::Cursor;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+async fn run(
+    bytes: Vec<u8>,
+    expected_size: Option<u64>,
+) -> Result<(Vec<[u8; 32]>, BundleReport), String> {
+    let options = ProcessOptions {
+        expected_size,
+        ..ProcessOptions::default()
+    };
+    run_with_options(bytes, options).await
+}
+
+async fn run_with_options(
+    bytes: Vec<u8>,
+    options: ProcessOptions,
+) -> Result<(Vec<[u8; 32]>, BundleReport), String> {
+    let mut cursor = Cursor::new(bytes);
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+
+    let read_handle = tokio::spawn(async move {
+        let mut ids = Vec::new();
+        while let Some(item) = rx.recv().await {
+            ids.push(item.id());
+        }
+        ids
+    });
+
+    let report = process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options).await;
+    let ids = read_handle.await.unwrap();
+    report.map(|report| (ids, report))
+}
+
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; 120]).with_signature_seed(1),
+        TestItem::new(1, vec![2u8; 80]).with_signature_seed(2),
+    ]
+}
+
+#[tokio::test]
+async fn test_matching_size_reports_nothing() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let size = bytes.len() as u64;
+
+    let (ids, report) = run(bytes, Some(size)).await.unwrap();
+
+    assert_eq!(ids, vec![items[0].id(), items[1].id()]);
+    assert!(report.size_mismatches.is_empty());
+}
+
+#[tokio::test]
+async fn test_trailing_bytes_are_reported() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    let declared = bytes.len() as u64;
+    bytes.extend_from_slice(&[0xEE; 17]);
+    let size = bytes.len() as u64;
+
+    let (ids, report) = run(bytes, Some(size)).await.unwrap();
+
+    assert_eq!(ids.len(), 2);
+    assert_eq!(
+        report.size_mismatches,
+        vec![(
+            ROOT_TX.to_string(),
+            SizeMismatch::TrailingBytes {
+                declared,
+                expected: size
+            }
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_truncated_bundle_is_classified_before_walking() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    let declared = bytes.len() as u64;
+    bytes.truncate(bytes.len() - 30);
+    let size = bytes.len() as u64;
+
+    let options = ProcessOptions {
+        expected_size: Some(size),
+        salvage: true,
+        ..ProcessOptions::default()
+    };
+    let (ids, report) = run_with_options(bytes, options).await.unwrap();
+
+    assert_eq!(ids, vec![items[0].id()]);
+    assert_eq!(
+        report.size_mismatches,
+        vec![(
+            ROOT_TX.to_string(),
+            SizeMismatch::Truncated {
+                declared,
+                expected: size
+            }
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_overlong_entry_table_is_rejected() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+
+    let result = run(bytes, Some(100)).await;
+
+    assert!(result.unwrap_err().contains("entry table of 160 bytes"));
+}
+
+#[tokio::test]
+async fn test_entry_count_above_u64_is_reported_exactly() {
+    let mut bytes = vec![0u8; 32];
+    bytes[8] = 1;
+
+    let result = run(bytes, Some(100)).await;
+
+    assert!(result
+        .unwrap_err()
+        .contains("entry table of 18446744073709551616 entries does not fit in 100 bytes"));
+}
+
+#[tokio::test]
+async fn test_nested_trailing_bytes_do_not_desync_parent() {
+    let inner = sample_items();
+    let mut data = bundle_bytes(&inner);
+    data.extend_from_slice(&[0xEE; 9]);
+
+    let mut nested = TestItem::new(2, Vec::new()).with_signature_seed(3);
+    nested.tags = bundle_tags();
+    nested.data = TestData::Raw(data);
+
+    let after = TestItem::new(2, vec![4u8; 10]).with_signature_seed(4);
+    let items = vec![nested.clone(), after.clone()];
+
+    let (ids, report) = run(bundle_bytes(&items), None).await.unwrap();
+
+    assert_eq!(
+        ids,
+        vec![nested.id(), inner[0].id(), inner[1].id(), after.id()]
+    );
+    assert_eq!(report.size_mismatches.len(), 1);
+    assert_eq!(report.size_mismatches[0].0, BASE64_URL.encode(nested.id()));
+}
+
+#[tokio::test]
+async fn test_oversized_item_count_is_reported_precisely() {
+    let mut bytes = vec![0u8; 32];
+    bytes[12] = 1;
+
+    let err = run(bytes, None).await.unwrap_err();
+
+    assert!(
+        err.contains(
+            "Bundle item count unsupported: value 79228162514264337593543950336 (97 bits)"
+        ),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_oversized_entry_size_is_reported_precisely() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    // size field of the second entry
+    bytes[32 + 64 + 31] = 0x01;
+
+    let err = run(bytes, None).await.unwrap_err();
+
+    assert!(
+        err.contains("Size of entry 1 unsupported:") && err.contains("(249 bits) exceeds u64"),
+        "{}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_spilled_entry_table_yields_all_items() {
+    let items: Vec<TestItem> = (0..40u8)
+        .map(|seed| TestItem::new(2, vec![seed; seed as usize]).with_signature_seed(seed))
+        .collect();
+    let bytes = bundle_bytes(&items);
+
+    let options = ProcessOptions {
+        spill_threshold: 4,
+        expected_size: Some(bytes.len() as u64),
+        ..ProcessOptions::default()
+    };
+    let (ids, report) = run_with_options(bytes, options).await.unwrap();
+
+    assert_eq!(ids, items.iter().map(TestItem::id).collect::<Vec<_>>());
+    assert!(report.size_mismatches.is_empty());
+}
+
+#[tokio::test]
+async fn test_salvage_relocates_with_spilled_entry_table() {
+    let items: Vec<TestItem> = (0..10u8)
+        .map(|seed| TestItem::new(2, vec![seed; 50]).with_signature_seed(seed))
+        .collect();
+    let mut bytes = bundle_bytes(&items);
+    // break the signature type of the fourth entry
+    let start = 32 + 64 * items.len() + 3 * items[0].to_bytes().len();
+    bytes[start] = 0xFF;
+
+    let options = ProcessOptions {
+        salvage: true,
+        spill_threshold: 2,
+        ..ProcessOptions::default()
+    };
+    let (ids, report) = run_with_options(bytes, options).await.unwrap();
+
+    let mut expected: Vec<[u8; 32]> = items.iter().map(TestItem::id).collect();
+    expected.remove(3);
+    assert_eq!(ids, expected);
+    assert_eq!(report.skipped, 1);
+}
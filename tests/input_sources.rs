@@ -0,0 +1,99 @@
+mod common;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, Compression, FileSource, GatewaySource, Input, InputSource,
+    ProcessOptions, RetryPolicy,
+};
+use async_compression::tokio::write::ZstdEncoder;
+use common::http::{serve, Response};
+use common::{actual_item, bundle_bytes, expected_items, ExpectedItem, TestItem};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; 120]).with_signature_seed(1),
+        TestItem::nested(
+            1,
+            vec![TestItem::new(2, vec![2u8; 30]).with_signature_seed(2)],
+        )
+        .with_signature_seed(3),
+    ]
+}
+
+async fn run(input: Input) -> Vec<ExpectedItem> {
+    let (tx, mut rx) = mpsc::channel(16);
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(actual_item(&item));
+        }
+        items
+    });
+
+    let options = ProcessOptions {
+        expected_size: input.size,
+        ..ProcessOptions::default()
+    };
+    let mut reader = input.reader;
+    let report = process_bundle_with_options(&mut reader, tx, ROOT_TX, &options)
+        .await
+        .unwrap();
+    assert!(report.size_mismatches.is_empty());
+    read_handle.await.unwrap()
+}
+
+#[tokio::test]
+async fn test_gateway_source_fetches_from_base_url() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let served = bytes.clone();
+    let base_url = serve(move |request| {
+        if request.path == format!("/{}", ROOT_TX) {
+            Response::ok(served.clone())
+        } else {
+            Response::status(404)
+        }
+    })
+    .await;
+
+    let input = GatewaySource::new(&base_url, ROOT_TX).open().await.unwrap();
+    assert_eq!(input.size, Some(bytes.len() as u64));
+    assert_eq!(run(input).await, expected_items(&items, ROOT_TX));
+
+    let missing = GatewaySource::new(&base_url, "missing")
+        .with_retry(RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        })
+        .open()
+        .await;
+    assert!(missing.unwrap_err().contains("404"));
+}
+
+#[tokio::test]
+async fn test_file_sources_yield_the_same_items() {
+    let items = sample_items();
+    let bytes = bundle_bytes(&items);
+    let dir = tempfile::tempdir().unwrap();
+
+    let plain = dir.path().join("bundle");
+    tokio::fs::write(&plain, &bytes).await.unwrap();
+
+    // no recognised extension, so the compression is given explicitly
+    let zstd = dir.path().join("bundle.archive");
+    let mut encoder = ZstdEncoder::new(tokio::fs::File::create(&zstd).await.unwrap());
+    encoder.write_all(&bytes).await.unwrap();
+    encoder.shutdown().await.unwrap();
+
+    let sources = [
+        FileSource::detect(&plain),
+        FileSource::new(&zstd, Compression::Zstd),
+    ];
+    for source in sources {
+        let input = source.open().await.unwrap();
+        assert_eq!(run(input).await, expected_items(&items, ROOT_TX));
+    }
+}
@@ -0,0 +1,85 @@
+// Peak RSS is process wide, so this file holds a single test to keep other tests from
+// allocating while it measures.
+#![cfg(all(target_os = "linux", target_pointer_width = "64"))]
+
+mod common;
+
+use arweave_ans_1040_indexer::{process_bundle, DataItem};
+use common::{number_bytes, TestItem};
+use std::io::Cursor;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::sync::mpsc;
+
+const MIB: u64 = 1024 * 1024;
+
+// Peak resident set size of this process in KiB.
+fn peak_rss_kib() -> u64 {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap()
+}
+
+// Endless zero bytes, filled a whole buffer at a time.
+struct Zeros;
+
+impl AsyncRead for Zeros {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let len = buf.remaining();
+        buf.initialize_unfilled().fill(0);
+        buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Streams a bundle holding one item with `payload` zero bytes of data without keeping
+// the payload in memory.
+async fn process_single_item(payload: u64) -> Vec<DataItem> {
+    let item = TestItem::new(2, Vec::new()).with_signature_seed(1);
+    let item_header = item.to_bytes();
+
+    let mut prefix = number_bytes(1).to_vec();
+    prefix.extend_from_slice(&number_bytes(item_header.len() + payload as usize));
+    prefix.extend_from_slice(&item.id());
+    prefix.extend_from_slice(&item_header);
+
+    let mut stream = Cursor::new(prefix).chain(Zeros.take(payload));
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(item);
+        }
+        items
+    });
+
+    process_bundle(&mut stream, tx, "root-tx").await.unwrap();
+    read_handle.await.unwrap()
+}
+
+#[tokio::test]
+async fn test_peak_rss_does_not_grow_with_item_size() {
+    let items = process_single_item(MIB).await;
+    assert_eq!(items.len(), 1);
+    let baseline = peak_rss_kib();
+
+    let items = process_single_item(4 * 1024 * MIB).await;
+    assert_eq!(items.len(), 1);
+    let peak = peak_rss_kib();
+
+    assert!(
+        peak - baseline < 16 * 1024,
+        "peak RSS grew from {} KiB to {} KiB while skipping a 4 GiB payload",
+        baseline,
+        peak
+    );
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use std::io::Cursor;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{UInt16Type, UInt64Type};
+use arrow_array::{Array, RecordBatch};
+use arweave_ans_1040_indexer::{
+    parquet_schema, process_bundle, DataItem, ParquetOptions, ParquetSink, Sink,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, TestItem};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+async fn parse(items: &[TestItem]) -> Vec<DataItem> {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cursor = Cursor::new(bundle_bytes(items));
+    process_bundle(&mut cursor, tx, ROOT_TX).await.unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+fn nested_sample() -> Vec<TestItem> {
+    let mut tagged = TestItem::new(2, vec![3u8; 40]).with_signature_seed(3);
+    tagged.target = Some([9u8; 32]);
+    tagged.tags.push((b"App-Name".to_vec(), vec![0xff, 0xfe]));
+    vec![
+        TestItem::new(1, vec![1u8; 40]).with_signature_seed(1),
+        TestItem::nested(
+            2,
+            vec![
+                TestItem::new(2, vec![2u8; 40]).with_signature_seed(2),
+                tagged,
+            ],
+        )
+        .with_signature_seed(4),
+    ]
+}
+
+async fn write(path: &std::path::Path, items: &[DataItem], options: &ParquetOptions) {
+    let mut sink = ParquetSink::create(path, options).await.unwrap();
+    for item in items {
+        sink.write(std::slice::from_ref(item)).await.unwrap();
+    }
+    sink.finish().await.unwrap();
+}
+
+fn read(path: &std::path::Path) -> (Vec<RecordBatch>, usize) {
+    let file = std::fs::File::open(path).unwrap();
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+    let row_groups = builder.metadata().num_row_groups();
+    let batches = builder
+        .build()
+        .unwrap()
+        .map(|batch| batch.unwrap())
+        .collect();
+    (batches, row_groups)
+}
+
+fn strings(batch: &RecordBatch, name: &str) -> Vec<Option<String>> {
+    batch
+        .column_by_name(name)
+        .unwrap()
+        .as_string::<i32>()
+        .iter()
+        .map(|value| value.map(str::to_string))
+        .collect()
+}
+
+fn numbers(batch: &RecordBatch, name: &str) -> Vec<u64> {
+    batch
+        .column_by_name(name)
+        .unwrap()
+        .as_primitive::<UInt64Type>()
+        .values()
+        .to_vec()
+}
+
+#[tokio::test]
+async fn test_rows_hold_the_item_fields_and_tags() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.parquet");
+    let items = parse(&nested_sample()).await;
+    write(&path, &items, &ParquetOptions::default()).await;
+
+    let (batches, row_groups) = read(&path);
+    assert_eq!((batches.len(), row_groups), (1, 1));
+    let batch = &batches[0];
+    assert_eq!(batch.schema(), parquet_schema());
+    assert_eq!(batch.num_rows(), items.len());
+
+    let ids: Vec<String> = items
+        .iter()
+        .map(|item| BASE64_URL.encode(item.id()))
+        .collect();
+    assert_eq!(
+        strings(batch, "id"),
+        ids.iter().cloned().map(Some).collect::<Vec<_>>()
+    );
+    let bundled_in: Vec<Option<String>> = items
+        .iter()
+        .map(|item| Some(item.bundled_in().to_string()))
+        .collect();
+    assert_eq!(strings(batch, "bundled_in"), bundled_in);
+    assert_eq!(
+        strings(batch, "owner_address")[0],
+        Some(items[0].owner_address())
+    );
+    assert_eq!(
+        strings(batch, "target"),
+        vec![None, None, None, Some(BASE64_URL.encode([9u8; 32]))]
+    );
+    assert_eq!(
+        batch
+            .column_by_name("signature_type")
+            .unwrap()
+            .as_primitive::<UInt16Type>()
+            .values()
+            .to_vec(),
+        vec![1, 2, 2, 2]
+    );
+    let is_bundle = batch.column_by_name("is_bundle").unwrap().as_boolean();
+    assert_eq!(
+        (0..items.len())
+            .map(|row| is_bundle.value(row))
+            .collect::<Vec<_>>(),
+        vec![false, true, false, false]
+    );
+
+    let offsets: Vec<u64> = items.iter().map(DataItem::offset).collect();
+    assert_eq!(numbers(batch, "offset"), offsets);
+    let sizes: Vec<u64> = items.iter().map(DataItem::size).collect();
+    assert_eq!(numbers(batch, "size"), sizes);
+    let data_offsets: Vec<u64> = items.iter().map(DataItem::data_offset).collect();
+    assert_eq!(numbers(batch, "data_offset"), data_offsets);
+    let data_sizes: Vec<u64> = items.iter().map(DataItem::data_size).collect();
+    assert_eq!(numbers(batch, "data_size"), data_sizes);
+
+    let ancestry = batch.column_by_name("ancestry").unwrap().as_list::<i32>();
+    for (row, item) in items.iter().enumerate() {
+        let bundles = ancestry.value(row);
+        let bundles: Vec<&str> = bundles.as_string::<i32>().iter().flatten().collect();
+        assert_eq!(bundles, item.ancestry());
+    }
+    assert_eq!(ancestry.value(3).len(), 2);
+
+    // the tags are read back with the text of the JSON output
+    let tags = batch.column_by_name("tags").unwrap().as_list::<i32>();
+    for (row, item) in items.iter().enumerate() {
+        let pairs = tags.value(row);
+        let pairs = pairs.as_struct();
+        let names: Vec<&str> = pairs
+            .column(0)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .collect();
+        let values: Vec<&str> = pairs
+            .column(1)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .collect();
+        let expected: Vec<(String, String)> = item.tags().iter().map(|tag| tag.to_text()).collect();
+        let actual: Vec<(String, String)> = names
+            .into_iter()
+            .zip(values)
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+    assert_eq!(tags.value(3).len(), 2);
+}
+
+#[tokio::test]
+async fn test_row_groups_follow_the_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let items = parse(&nested_sample()).await;
+
+    let path = dir.path().join("rows.parquet");
+    let options = ParquetOptions {
+        row_group_rows: 3,
+        ..ParquetOptions::default()
+    };
+    write(&path, &items, &options).await;
+    let (batches, row_groups) = read(&path);
+    assert_eq!(row_groups, 2);
+    assert_eq!(batches.iter().map(RecordBatch::num_rows).sum::<usize>(), 4);
+
+    // every write goes past the byte limit
+    let path = dir.path().join("bytes.parquet");
+    let options = ParquetOptions {
+        row_group_bytes: 1,
+        ..ParquetOptions::default()
+    };
+    write(&path, &items, &options).await;
+    let (_, row_groups) = read(&path);
+    assert_eq!(row_groups, items.len());
+}
+
+#[tokio::test]
+async fn test_empty_runs_write_a_readable_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.parquet");
+    write(&path, &[], &ParquetOptions::default()).await;
+
+    let (batches, row_groups) = read(&path);
+    assert_eq!(row_groups, 0);
+    assert!(batches.is_empty());
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use std::io::Cursor;
+
+use arweave_ans_1040_indexer::{data_router, process_bundle, write_items, ItemStore};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, TestItem};
+use reqwest::StatusCode;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+fn payload(seed: u8) -> Vec<u8> {
+    (0..100)
+        .map(|i| seed.wrapping_mul(31).wrapping_add(i))
+        .collect()
+}
+
+// A root bundle holding an item without a content type and a nested bundle of a JSON item.
+fn nested_sample() -> Vec<TestItem> {
+    let mut untyped = TestItem::new(2, payload(1)).with_signature_seed(1);
+    untyped.tags = vec![(b"App-Name".to_vec(), b"indexer".to_vec())];
+    let mut json = TestItem::new(2, payload(2)).with_signature_seed(2);
+    json.tags = vec![(b"Content-Type".to_vec(), b"application/json".to_vec())];
+    vec![
+        untyped,
+        TestItem::nested(2, vec![json]).with_signature_seed(3),
+    ]
+}
+
+// Indexes the sample, stores its bundle in a directory and serves it, returns the base url
+// and the ids of the items.
+async fn serve(dir: &tempfile::TempDir) -> (String, Vec<String>) {
+    let bytes = bundle_bytes(&nested_sample());
+    std::fs::create_dir(dir.path().join("bundles")).unwrap();
+    std::fs::write(dir.path().join("bundles").join(ROOT_TX), &bytes).unwrap();
+
+    let (tx, mut rx) = mpsc::channel(16);
+    process_bundle(&mut Cursor::new(bytes), tx, ROOT_TX)
+        .await
+        .unwrap();
+    let mut items = Vec::new();
+    while let Some(item) = rx.recv().await {
+        items.push(item);
+    }
+    let ids = items
+        .iter()
+        .map(|item| BASE64_URL.encode(item.id()))
+        .collect();
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    let (tx, rx) = mpsc::channel(16);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    write_items(rx, store.clone(), 16, None).await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let router = data_router(store, dir.path().join("bundles"));
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, ids)
+}
+
+async fn fetch(url: &str, range: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(range) = range {
+        request = request.header("Range", range);
+    }
+    request.send().await.unwrap()
+}
+
+fn header(response: &reqwest::Response, name: &str) -> String {
+    response.headers()[name].to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_items_are_served_from_the_bundle_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (url, ids) = serve(&dir).await;
+
+    let response = fetch(&format!("{}/{}", url, ids[0]), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        header(&response, "content-type"),
+        "application/octet-stream"
+    );
+    assert_eq!(header(&response, "accept-ranges"), "bytes");
+    assert_eq!(response.bytes().await.unwrap(), payload(1));
+
+    // the data of a nested item is found at its offset in the root bundle
+    let response = fetch(&format!("{}/{}", url, ids[2]), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(header(&response, "content-type"), "application/json");
+    assert_eq!(response.bytes().await.unwrap(), payload(2));
+
+    let response = fetch(&format!("{}/{}", url, BASE64_URL.encode([0u8; 32])), None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    std::fs::remove_file(dir.path().join("bundles").join(ROOT_TX)).unwrap();
+    let response = fetch(&format!("{}/{}", url, ids[0]), None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_range_requests_get_partial_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let (url, ids) = serve(&dir).await;
+    let url = format!("{}/{}", url, ids[2]);
+    let data = payload(2);
+
+    for (range, start, end) in [
+        ("bytes=10-19", 10, 19),
+        ("bytes=90-", 90, 99),
+        ("bytes=-5", 95, 99),
+        ("bytes=95-1000", 95, 99),
+    ] {
+        let response = fetch(&url, Some(range)).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{}", range);
+        assert_eq!(
+            header(&response, "content-range"),
+            format!("bytes {}-{}/100", start, end)
+        );
+        assert_eq!(
+            response.bytes().await.unwrap(),
+            data[start..=end],
+            "{}",
+            range
+        );
+    }
+
+    let response = fetch(&url, Some("bytes=100-")).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(header(&response, "content-range"), "bytes */100");
+
+    // several ranges are not supported, the whole data is served
+    let response = fetch(&url, Some("bytes=0-1,5-6")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.bytes().await.unwrap(), data);
+}
@@ -0,0 +1,304 @@
+// These tests need a local PostgreSQL server, given as a key-value connection string by
+// `POSTGRES_TEST_URL`, e.g. `host=localhost user=postgres`, whose user may create databases.
+// They are skipped when it is not set.
+mod common;
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, write_items, write_items_with_events, BundleDone, BundleSummary,
+    DataItem, PostgresSink, ProcessOptions, RootTransaction, Sink, WriterEvent,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, TestItem};
+use tokio::sync::mpsc;
+use tokio_postgres::{Client, NoTls};
+
+const ROOT_TX: &str = "root-tx";
+
+// Creates an empty database for a test, returns its connection string and a client.
+async fn database(name: &str) -> Option<(String, Client)> {
+    let Ok(server) = std::env::var("POSTGRES_TEST_URL") else {
+        eprintln!("POSTGRES_TEST_URL is not set, skipping");
+        return None;
+    };
+    let admin = connect(&server).await;
+    let name = format!("indexer_test_{}", name);
+    for statement in ["DROP DATABASE IF EXISTS", "CREATE DATABASE"] {
+        admin
+            .batch_execute(&format!("{} {}", statement, name))
+            .await
+            .unwrap();
+    }
+    let params = format!("{} dbname={}", server, name);
+    let client = connect(&params).await;
+    Some((params, client))
+}
+
+async fn connect(params: &str) -> Client {
+    let (client, connection) = tokio_postgres::connect(params, NoTls).await.unwrap();
+    tokio::spawn(connection);
+    client
+}
+
+fn leaf(seed: u8) -> TestItem {
+    TestItem::new(2, vec![seed; 40]).with_signature_seed(seed)
+}
+
+fn nested_sample() -> Vec<TestItem> {
+    let mut tagged = leaf(3);
+    tagged
+        .tags
+        .push((b"App-Name".to_vec(), b"indexer".to_vec()));
+    vec![
+        leaf(1),
+        TestItem::nested(2, vec![leaf(2), tagged]).with_signature_seed(4),
+    ]
+}
+
+fn root_tx(data_size: usize) -> Arc<RootTransaction> {
+    Arc::new(RootTransaction {
+        id: ROOT_TX.to_string(),
+        owner_address: "owner".to_string(),
+        data_size: data_size as u64,
+        block_height: Some(42),
+        block_hash: None,
+        block_timestamp: None,
+    })
+}
+
+// Outcome of the root bundle, failed when there are `errors`.
+fn done(data_size: usize, items: usize, errors: &[&str]) -> BundleDone {
+    BundleDone {
+        root_tx: Some(root_tx(data_size)),
+        summary: BundleSummary {
+            tx_id: ROOT_TX.to_string(),
+            items,
+            skipped: 0,
+            errors: errors.iter().map(|e| e.to_string()).collect(),
+            duration_secs: 0.0,
+        },
+    }
+}
+
+// Parses the items of the sample with a root transaction as long as the bundle.
+async fn parse(items: &[TestItem]) -> Vec<DataItem> {
+    let bytes = bundle_bytes(items);
+    let options = ProcessOptions {
+        root_tx: Some(root_tx(bytes.len())),
+        ..ProcessOptions::default()
+    };
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cursor = Cursor::new(bytes);
+    process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options)
+        .await
+        .unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+async fn index(params: &str, items: Vec<DataItem>) {
+    let (tx, rx) = mpsc::channel(16);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    let sink = PostgresSink::connect(params).await.unwrap();
+    write_items(rx, sink, 2, None).await.unwrap();
+}
+
+async fn count(client: &Client, table: &str) -> i64 {
+    client
+        .query_one(&format!("SELECT COUNT(*) FROM {}", table), &[])
+        .await
+        .unwrap()
+        .get(0)
+}
+
+async fn status(client: &Client) -> (String, i64, Option<i64>) {
+    let row = client
+        .query_one(
+            "SELECT status, indexed_to, block_height FROM root_transactions WHERE id = $1",
+            &[&ROOT_TX],
+        )
+        .await
+        .unwrap();
+    (row.get(0), row.get(1), row.get(2))
+}
+
+async fn outcome(client: &Client) -> (String, Option<i64>, Option<Vec<String>>) {
+    let row = client
+        .query_one(
+            "SELECT status, items, errors FROM root_transactions WHERE id = $1",
+            &[&ROOT_TX],
+        )
+        .await
+        .unwrap();
+    (row.get(0), row.get(1), row.get(2))
+}
+
+#[tokio::test]
+async fn test_items_and_tags_are_copied() {
+    let Some((params, client)) = database("copy").await else {
+        return;
+    };
+    let items = parse(&nested_sample()).await;
+    let nested_id = BASE64_URL.encode(items[1].id());
+    let tagged_id = BASE64_URL.encode(items[3].id());
+    index(&params, items).await;
+
+    assert_eq!(count(&client, "items").await, 4);
+    let row = client
+        .query_one(
+            "SELECT bundled_in, root_tx, ancestry, data_size FROM items WHERE id = $1",
+            &[&tagged_id],
+        )
+        .await
+        .unwrap();
+    assert_eq!(row.get::<_, String>(0), nested_id);
+    assert_eq!(row.get::<_, String>(1), ROOT_TX);
+    assert_eq!(
+        row.get::<_, Vec<String>>(2),
+        vec![ROOT_TX.to_string(), nested_id]
+    );
+    assert_eq!(row.get::<_, i64>(3), 40);
+
+    let found: Vec<String> = client
+        .query(
+            "SELECT item_id FROM tags WHERE name = $1 AND value = $2",
+            &[&"App-Name", &"indexer"],
+        )
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    assert_eq!(found, vec![tagged_id]);
+}
+
+#[tokio::test]
+async fn test_indexing_again_does_not_duplicate_rows() {
+    let Some((params, client)) = database("dedupe").await else {
+        return;
+    };
+    let mut items = parse(&nested_sample()).await;
+    // an item repeated in the same batch
+    items.extend(parse(&[leaf(1)]).await);
+    let mut sink = PostgresSink::connect(&params).await.unwrap();
+    sink.write(&items).await.unwrap();
+    sink.finish().await.unwrap();
+    index(&params, parse(&nested_sample()).await).await;
+
+    assert_eq!(count(&client, "items").await, 4);
+    assert_eq!(count(&client, "tags").await, 6);
+    assert_eq!(count(&client, "root_transactions").await, 1);
+    // the schema is only migrated once
+    assert_eq!(
+        count(&client, "schema_migrations").await,
+        PostgresSink::schema_version() as i64
+    );
+}
+
+#[tokio::test]
+async fn test_root_transaction_status_follows_the_batches() {
+    let Some((params, client)) = database("status").await else {
+        return;
+    };
+    let items = parse(&nested_sample()).await;
+    let end = items[1].offset() + items[1].size();
+    let mut sink = PostgresSink::connect(&params).await.unwrap();
+
+    sink.write(&items[..1]).await.unwrap();
+    let (state, indexed_to, height) = status(&client).await;
+    assert_eq!(state, "indexing");
+    assert_eq!(indexed_to, (items[0].offset() + items[0].size()) as i64);
+    assert_eq!(height, Some(42));
+
+    // every byte of the bundle is written, but the bundle is only indexed once it is finished
+    sink.write(&items[1..]).await.unwrap();
+    assert_eq!(
+        status(&client).await,
+        ("indexing".to_string(), end as i64, Some(42))
+    );
+
+    let size = bundle_bytes(&nested_sample()).len();
+    sink.finish_bundle(&[], &done(size, 4, &[])).await.unwrap();
+    sink.finish().await.unwrap();
+    assert_eq!(status(&client).await.0, "indexed");
+    assert_eq!(status(&client).await.1, end as i64);
+    assert_eq!(
+        outcome(&client).await,
+        ("indexed".to_string(), Some(4), Some(vec![]))
+    );
+}
+
+#[tokio::test]
+async fn test_nested_bundle_at_the_end_is_not_indexed_before_its_items() {
+    let Some((params, client)) = database("nested_end").await else {
+        return;
+    };
+    let items = parse(&nested_sample()).await;
+    let size = bundle_bytes(&nested_sample()).len();
+    // the nested bundle is the last entry of the root bundle and reaches its end
+    assert_eq!(items[1].offset() + items[1].size(), size as u64);
+    let mut sink = PostgresSink::connect(&params).await.unwrap();
+
+    sink.write(&items[..2]).await.unwrap();
+    assert_eq!(status(&client).await.0, "indexing");
+    // its items arrive in a later batch, written along with the outcome
+    sink.finish_bundle(&items[2..], &done(size, 4, &[]))
+        .await
+        .unwrap();
+    assert_eq!(count(&client, "items").await, 4);
+    assert_eq!(outcome(&client).await.0, "indexed");
+
+    // or never arrive
+    let (params, client) = database("nested_end_lost").await.unwrap();
+    let mut sink = PostgresSink::connect(&params).await.unwrap();
+    sink.write(&items[..2]).await.unwrap();
+    let lost = "Lost entries [\"nested\"]";
+    sink.finish_bundle(&[], &done(size, 2, &[lost]))
+        .await
+        .unwrap();
+    sink.finish().await.unwrap();
+    assert_eq!(count(&client, "items").await, 2);
+    assert_eq!(
+        outcome(&client).await,
+        ("failed".to_string(), Some(2), Some(vec![lost.to_string()]))
+    );
+}
+
+#[tokio::test]
+async fn test_outcome_of_bundles_without_items_is_recorded() {
+    let Some((params, client)) = database("no_items").await else {
+        return;
+    };
+    let (tx, rx) = mpsc::channel(16);
+    let (events, events_rx) = mpsc::channel(16);
+    let failed = "Failed to fetch the bundle: 404";
+    events
+        .send(WriterEvent::BundleDone(done(0, 0, &[failed])))
+        .await
+        .unwrap();
+    drop((tx, events));
+    let sink = PostgresSink::connect(&params).await.unwrap();
+    write_items_with_events(rx, sink, 2, None, Some(events_rx))
+        .await
+        .unwrap();
+
+    assert_eq!(count(&client, "items").await, 0);
+    assert_eq!(
+        outcome(&client).await,
+        (
+            "failed".to_string(),
+            Some(0),
+            Some(vec![failed.to_string()])
+        )
+    );
+    assert_eq!(status(&client).await.1, 0);
+}
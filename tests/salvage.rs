@@ -0,0 +1,160 @@
+mod common;
+
+use arweave_ans_1040_indexer::{process_bundle_with_options, BundleReport, ProcessOptions};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{actual_item, bundle_bytes, expected_items, ExpectedItem, TestItem};
+use std::io::Cursor;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+async fn run(bytes: Vec<u8>, salvage: bool) -> Result<(Vec<ExpectedItem>, BundleReport), String> {
+    let mut cursor = Cursor::new(bytes);
+    let (tx, mut rx) = mpsc::channel(16);
+
+    let read_handle = tokio::spawn(async move {
+        let mut items = Vec::new();
+        while let Some(item) = rx.recv().await {
+            items.push(actual_item(&item));
+        }
+        items
+    });
+
+    let options = ProcessOptions {
+        salvage,
+        ..ProcessOptions::default()
+    };
+    let report = process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options).await;
+    let items = read_handle.await.unwrap();
+    report.map(|report| (items, report))
+}
+
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; 300]).with_signature_seed(1),
+        TestItem::new(1, vec![2u8; 700]).with_signature_seed(2),
+        TestItem::new(2, vec![3u8; 100]).with_signature_seed(3),
+        TestItem::new(1, vec![4u8; 50]).with_signature_seed(4),
+    ]
+}
+
+// Offset right after the last byte of the `index` entry.
+fn entry_end(items: &[TestItem], index: usize) -> usize {
+    let header = 32 + 64 * items.len();
+    header
+        + items[..=index]
+            .iter()
+            .map(|item| item.to_bytes().len())
+            .sum::<usize>()
+}
+
+fn id(item: &TestItem) -> String {
+    BASE64_URL.encode(item.id())
+}
+
+// Offset of the first byte of the `index` entry.
+fn entry_start(items: &[TestItem], index: usize) -> usize {
+    entry_end(items, index) - items[index].to_bytes().len()
+}
+
+#[tokio::test]
+async fn test_salvage_skips_entry_with_broken_signature() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    let start = entry_start(&items, 1);
+    bytes.drain(start + 10..start + 50);
+
+    assert!(run(bytes.clone(), false).await.is_err());
+
+    let (actual, report) = run(bytes, true).await.unwrap();
+    let expected = expected_items(&items, ROOT_TX);
+
+    assert_eq!(
+        actual,
+        vec![
+            expected[0].clone(),
+            expected[2].clone(),
+            expected[3].clone()
+        ]
+    );
+    assert_eq!(report.items, 3);
+    assert_eq!(report.lost, vec![id(&items[1])]);
+    assert_eq!(report.recovered, vec![id(&items[2])]);
+}
+
+#[tokio::test]
+async fn test_salvage_recovers_after_truncated_payload() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    let end = entry_end(&items, 1);
+    bytes.drain(end - 40..end);
+
+    assert!(run(bytes.clone(), false).await.is_err());
+
+    let (actual, report) = run(bytes, true).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+    assert!(report.lost.is_empty());
+    assert_eq!(report.recovered, vec![id(&items[2])]);
+}
+
+#[tokio::test]
+async fn test_salvage_recovers_after_overlong_entry() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    let end = entry_end(&items, 0);
+    bytes.splice(end..end, vec![0xAA; 25]);
+
+    let (actual, report) = run(bytes, true).await.unwrap();
+
+    assert_eq!(actual, expected_items(&items, ROOT_TX));
+    assert!(report.lost.is_empty());
+    assert_eq!(report.recovered, vec![id(&items[1])]);
+}
+
+#[tokio::test]
+async fn test_salvage_recovers_inside_nested_bundle() {
+    let inner = sample_items();
+    let items = vec![
+        TestItem::nested(2, inner.clone()).with_signature_seed(9),
+        TestItem::new(2, vec![5u8; 10]).with_signature_seed(10),
+    ];
+    let mut bytes = bundle_bytes(&items);
+    // the nested bundle starts after the outer header and the nested item header
+    let nested_start = entry_end(&items, 0) - bundle_bytes(&inner).len();
+    let end = nested_start + entry_end(&inner, 1);
+    bytes.drain(end - 40..end);
+
+    let (actual, report) = run(bytes, true).await.unwrap();
+    let ids: Vec<[u8; 32]> = actual.iter().map(|item| item.id).collect();
+
+    // the nested entry after the damage is relocated inside the nested bundle,
+    // and the outer entry following the shortened nested bundle is relocated by the parent
+    assert_eq!(
+        ids,
+        vec![
+            items[0].id(),
+            inner[0].id(),
+            inner[1].id(),
+            inner[2].id(),
+            inner[3].id(),
+            items[1].id()
+        ]
+    );
+    assert!(report.lost.is_empty());
+    assert_eq!(report.recovered, vec![id(&inner[2]), id(&items[1])]);
+}
+
+#[tokio::test]
+async fn test_salvage_reports_lost_tail() {
+    let items = sample_items();
+    let mut bytes = bundle_bytes(&items);
+    bytes.truncate(bytes.len() - 10);
+
+    let (actual, report) = run(bytes, true).await.unwrap();
+
+    assert_eq!(actual.len(), 3);
+    assert_eq!(report.lost, vec![id(&items[3])]);
+    assert!(report.recovered.is_empty());
+}
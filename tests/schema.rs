@@ -0,0 +1,131 @@
+mod common;
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use arweave_ans_1040_indexer::{
+    data_item_schema, process_bundle_with_options, DataItem, ProcessOptions, RootTransaction,
+    DATA_ITEM_SCHEMA_VERSION,
+};
+use common::{bundle_bytes, TestItem};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+async fn parse(root_tx: Option<RootTransaction>) -> Vec<DataItem> {
+    let items = vec![
+        TestItem::new(2, vec![1u8; 40]).with_signature_seed(1),
+        TestItem::nested(
+            1,
+            vec![TestItem::new(2, vec![2u8; 40]).with_signature_seed(2)],
+        )
+        .with_signature_seed(3),
+    ];
+    let (tx, mut rx) = mpsc::channel(16);
+    let options = ProcessOptions {
+        root_tx: root_tx.map(Arc::new),
+        ..ProcessOptions::default()
+    };
+    let mut cursor = Cursor::new(bundle_bytes(&items));
+    process_bundle_with_options(&mut cursor, tx, "root-tx", &options)
+        .await
+        .unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+// Checks the fields of `record` against the properties of `schema`, following references.
+fn check(record: &Value, schema: &Value, root: &Value) {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/$defs/");
+        return check(record, &root["$defs"][name], root);
+    }
+    if let Some(variants) = schema["anyOf"].as_array() {
+        let variant = if record.is_null() {
+            variants.iter().find(|v| v["type"] == "null").unwrap()
+        } else {
+            variants.iter().find(|v| v["type"] != "null").unwrap()
+        };
+        return check(record, variant, root);
+    }
+    match record {
+        Value::Object(fields) => {
+            let properties = schema["properties"].as_object().unwrap();
+            for name in fields.keys() {
+                assert!(
+                    properties.contains_key(name),
+                    "{} is not in the schema",
+                    name
+                );
+            }
+            for name in schema["required"].as_array().unwrap() {
+                assert!(
+                    fields.contains_key(name.as_str().unwrap()),
+                    "{} is missing",
+                    name
+                );
+            }
+            for (name, value) in fields {
+                check(value, &properties[name], root);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                check(item, &schema["items"], root);
+            }
+        }
+        value => {
+            let kind = match value {
+                Value::String(_) => "string",
+                Value::Bool(_) => "boolean",
+                Value::Number(_) => "integer",
+                _ => "null",
+            };
+            let allowed = match &schema["type"] {
+                Value::Array(kinds) => kinds.clone(),
+                kind => vec![kind.clone()],
+            };
+            assert!(
+                allowed.contains(&kind.into()),
+                "{} is not a {:?}",
+                value,
+                allowed
+            );
+        }
+    }
+}
+
+#[test]
+fn test_published_schema_matches_the_types() {
+    let path = format!(
+        "{}/schema/data-item.v{}.schema.json",
+        env!("CARGO_MANIFEST_DIR"),
+        DATA_ITEM_SCHEMA_VERSION
+    );
+    let published: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+
+    // a failure means the records changed: publish the new schema with `schema`, and bump
+    // the version when the change is incompatible
+    assert_eq!(published, data_item_schema());
+}
+
+#[tokio::test]
+async fn test_records_follow_the_schema() {
+    let schema = data_item_schema();
+    let root_tx = RootTransaction {
+        id: "root-tx".to_string(),
+        owner_address: "owner".to_string(),
+        data_size: 1000,
+        block_height: Some(42),
+        block_hash: None,
+        block_timestamp: None,
+    };
+
+    for items in [parse(None).await, parse(Some(root_tx)).await] {
+        for item in &items {
+            check(&serde_json::to_value(item).unwrap(), &schema, &schema);
+        }
+    }
+}
@@ -0,0 +1,474 @@
+mod common;
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use arweave_ans_1040_indexer::{
+    process_bundle, process_bundle_with_options, write_items, write_items_with_events, AvroReader,
+    AvroSink, BundleDone, BundleSummary, Checkpoint, CheckpointLevel, CheckpointLog, DataItem,
+    JsonFormat, JsonSink, MessagePackReader, MessagePackSink, ProcessOptions, RootTransaction,
+    Sink, WriterEvent,
+};
+use common::{bundle_bytes, TestItem};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+async fn parse(count: usize) -> Vec<DataItem> {
+    let items: Vec<TestItem> = (0..count)
+        .map(|index| TestItem::new(2, vec![index as u8; 40]).with_signature_seed(index as u8))
+        .collect();
+    let (tx, mut rx) = mpsc::channel(count.max(1));
+    let mut cursor = Cursor::new(bundle_bytes(&items));
+    process_bundle(&mut cursor, tx, ROOT_TX).await.unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+// Items with a target, a tag that is not UTF-8, a nested bundle and a root transaction.
+async fn parse_nested() -> Vec<DataItem> {
+    let mut tagged = TestItem::new(2, vec![3u8; 40]).with_signature_seed(3);
+    tagged.target = Some([9u8; 32]);
+    tagged.anchor = Some([8u8; 32]);
+    tagged.tags.push((b"App-Name".to_vec(), vec![0xff, 0xfe]));
+    let items = vec![
+        TestItem::new(1, vec![1u8; 40]).with_signature_seed(1),
+        TestItem::nested(2, vec![tagged]).with_signature_seed(2),
+    ];
+    let options = ProcessOptions {
+        root_tx: Some(Arc::new(RootTransaction {
+            id: ROOT_TX.to_string(),
+            owner_address: "owner".to_string(),
+            data_size: 1000,
+            block_height: Some(42),
+            block_hash: Some("hash".to_string()),
+            block_timestamp: None,
+        })),
+        ..ProcessOptions::default()
+    };
+    let (tx, mut rx) = mpsc::channel(16);
+    let mut cursor = Cursor::new(bundle_bytes(&items));
+    process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options)
+        .await
+        .unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+// Checks that the items read back from a binary output are the written ones.
+fn assert_same_items(read: Vec<Result<DataItem, String>>, written: &[DataItem]) {
+    let read: Vec<DataItem> = read.into_iter().map(|item| item.unwrap()).collect();
+    assert_eq!(values(&read), values(written));
+    for (read, written) in read.iter().zip(written) {
+        assert_eq!(read.signature(), written.signature());
+        assert_eq!(read.owner(), written.owner());
+        assert_eq!(read.signature_type(), written.signature_type());
+        assert_eq!(read.root_tx(), written.root_tx());
+        assert_eq!(read.ancestry(), written.ancestry());
+        assert_eq!(
+            (read.offset(), read.size(), read.data_offset()),
+            (written.offset(), written.size(), written.data_offset())
+        );
+        let tags = |item: &DataItem| {
+            item.tags()
+                .iter()
+                .map(|tag| (tag.name().to_vec(), tag.value().to_vec()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tags(read), tags(written));
+    }
+}
+
+fn read_avro(path: &std::path::Path) -> Vec<Result<DataItem, String>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    AvroReader::new(file).unwrap().collect()
+}
+
+fn read_msgpack(path: &std::path::Path) -> Vec<Result<DataItem, String>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    MessagePackReader::new(file).collect()
+}
+
+// Records the calls it receives.
+#[derive(Default, Clone)]
+struct RecordingSink {
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl Sink for RecordingSink {
+    async fn write(&mut self, items: &[DataItem]) -> Result<(), String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("write {}", items.len()));
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<Option<u64>, String> {
+        self.calls.lock().unwrap().push("flush".to_string());
+        Ok(None)
+    }
+
+    async fn finish_bundle(&mut self, items: &[DataItem], done: &BundleDone) -> Result<(), String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("done {} {}", done.summary.tx_id, items.len()));
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<(), String> {
+        self.calls.lock().unwrap().push("finish".to_string());
+        Ok(())
+    }
+}
+
+fn checkpoint(items_emitted: u64) -> Checkpoint {
+    Checkpoint {
+        root_tx: ROOT_TX.to_string(),
+        offset: 1000 + items_emitted,
+        stack: vec![CheckpointLevel {
+            bundle_id: ROOT_TX.to_string(),
+            start: 0,
+            size: None,
+            entry: items_emitted,
+            entry_start: 1000 + items_emitted,
+        }],
+        items_emitted,
+        output_len: None,
+    }
+}
+
+fn values(items: &[DataItem]) -> Vec<serde_json::Value> {
+    items
+        .iter()
+        .map(|item| serde_json::to_value(item).unwrap())
+        .collect()
+}
+
+fn json_values(bytes: &[u8]) -> Vec<serde_json::Value> {
+    serde_json::Deserializer::from_slice(bytes)
+        .into_iter()
+        .map(|value| value.unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_items_are_written_in_batches_then_finished() {
+    let items = parse(7).await;
+    let (tx, rx) = mpsc::channel(16);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    let sink = RecordingSink::default();
+    let calls = sink.calls.clone();
+
+    let written = write_items(rx, sink, 3, None).await.unwrap();
+
+    assert_eq!(written, 7);
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec!["write 3", "write 3", "write 1", "finish"]
+    );
+}
+
+fn bundle_done(tx_id: &str) -> WriterEvent {
+    WriterEvent::BundleDone(BundleDone {
+        root_tx: None,
+        summary: BundleSummary {
+            tx_id: tx_id.to_string(),
+            items: 0,
+            skipped: 0,
+            errors: Vec::new(),
+            duration_secs: 0.0,
+        },
+    })
+}
+
+#[tokio::test]
+async fn test_bundles_are_finished_after_their_items() {
+    let mut items = parse(6).await.into_iter();
+    let (tx, rx) = mpsc::channel(16);
+    let (events, events_rx) = mpsc::channel(16);
+    for item in items.by_ref().take(5) {
+        tx.send(item).await.unwrap();
+    }
+    events.send(bundle_done("first")).await.unwrap();
+    tx.send(items.next().unwrap()).await.unwrap();
+    events.send(bundle_done("second")).await.unwrap();
+    // a bundle without items, finished after the last item
+    events.send(bundle_done("empty")).await.unwrap();
+    drop((tx, events));
+    let sink = RecordingSink::default();
+    let calls = sink.calls.clone();
+
+    let written = write_items_with_events(rx, sink, 3, None, Some(events_rx))
+        .await
+        .unwrap();
+
+    assert_eq!(written, 6);
+    // number of items written when each bundle is finished
+    let mut count = 0;
+    let mut finished = Vec::new();
+    for call in calls.lock().unwrap().iter() {
+        let words: Vec<&str> = call.split(' ').collect();
+        match words[..] {
+            ["write", items] => count += items.parse::<usize>().unwrap(),
+            ["done", tx_id, items] => {
+                count += items.parse::<usize>().unwrap();
+                finished.push((tx_id.to_string(), count));
+            }
+            _ => assert_eq!(call, "finish"),
+        }
+    }
+    assert_eq!(finished.len(), 3);
+    assert_eq!(finished[0].0, "first");
+    assert!(finished[0].1 >= 5);
+    assert_eq!(finished[1], ("second".to_string(), 6));
+    assert_eq!(finished[2], ("empty".to_string(), 6));
+}
+
+#[tokio::test]
+async fn test_ndjson_sink_creates_and_appends() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.json");
+    std::fs::write(&path, b"stale").unwrap();
+    let items = parse(3).await;
+
+    let mut sink = JsonSink::create(&path, JsonFormat::Lines).await.unwrap();
+    sink.write(&items[..2]).await.unwrap();
+    sink.finish().await.unwrap();
+    let mut sink = JsonSink::append(&path, JsonFormat::Lines).await.unwrap();
+    sink.write(&items[2..]).await.unwrap();
+    sink.finish().await.unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    let lines: Vec<serde_json::Value> = bytes
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(lines, values(&items));
+}
+
+#[tokio::test]
+async fn test_json_array_sink_continues_the_array() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.json");
+    let items = parse(4).await;
+
+    let sink = JsonSink::create(&path, JsonFormat::Array).await.unwrap();
+    sink.finish().await.unwrap();
+    let array: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(array, serde_json::json!([]));
+
+    let mut sink = JsonSink::append(&path, JsonFormat::Array).await.unwrap();
+    sink.write(&items[..1]).await.unwrap();
+    sink.write(&items[1..2]).await.unwrap();
+    sink.finish().await.unwrap();
+    // a run that stopped before closing the array
+    let mut sink = JsonSink::append(&path, JsonFormat::Array).await.unwrap();
+    sink.write(&items[2..3]).await.unwrap();
+    let len = sink.flush().await.unwrap().unwrap();
+    drop(sink);
+    let mut sink = JsonSink::append(&path, JsonFormat::Array).await.unwrap();
+    sink.write(&items[3..]).await.unwrap();
+    sink.finish().await.unwrap();
+
+    let array: Vec<serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(array, values(&items));
+
+    let sink = JsonSink::resume(&path, JsonFormat::Array, len)
+        .await
+        .unwrap();
+    sink.finish().await.unwrap();
+    let array: Vec<serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(array, values(&items[..3]));
+}
+
+#[tokio::test]
+async fn test_appending_to_another_file_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.json");
+    std::fs::write(&path, b"not an array").unwrap();
+
+    let err = JsonSink::append(&path, JsonFormat::Array)
+        .await
+        .unwrap_err();
+
+    assert!(err.contains("does not end like a JSON array"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_checkpoints_record_the_output_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.json");
+    let checkpoint_path = dir.path().join("checkpoint.json");
+    let items = parse(5).await;
+    let expected = values(&items);
+
+    let (tx, rx) = mpsc::channel(16);
+    let (checkpoints, checkpoint_rx) = mpsc::channel(16);
+    checkpoints.send(checkpoint(3)).await.unwrap();
+    drop(checkpoints);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    let log = CheckpointLog {
+        checkpoints: checkpoint_rx,
+        path: checkpoint_path.clone(),
+        items: 0,
+    };
+    let sink = JsonSink::create(&path, JsonFormat::Lines).await.unwrap();
+    write_items(rx, sink, 2, Some(log)).await.unwrap();
+
+    let saved = Checkpoint::load(&checkpoint_path).await.unwrap().unwrap();
+    assert_eq!(saved.items_emitted, 3);
+    let len = saved.output_len.unwrap() as usize;
+    let bytes = std::fs::read(&path).unwrap();
+    // the saved output holds exactly the counted items
+    assert_eq!(json_values(&bytes[..len]), expected[..3]);
+
+    // a resumed run drops what was written after the checkpoint
+    let mut sink = JsonSink::resume(&path, JsonFormat::Lines, len as u64)
+        .await
+        .unwrap();
+    let rest = parse(5).await;
+    sink.write(&rest[3..]).await.unwrap();
+    sink.finish().await.unwrap();
+    assert_eq!(json_values(&std::fs::read(&path).unwrap()), expected);
+}
+
+#[tokio::test]
+async fn test_checkpoints_wait_for_their_items() {
+    let dir = tempfile::tempdir().unwrap();
+    let checkpoint_path = dir.path().join("checkpoint.json");
+    let items = parse(2).await;
+
+    let (tx, rx) = mpsc::channel(16);
+    let (checkpoints, checkpoint_rx) = mpsc::channel(16);
+    checkpoints.send(checkpoint(12)).await.unwrap();
+    drop(checkpoints);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    let log = CheckpointLog {
+        checkpoints: checkpoint_rx,
+        path: checkpoint_path.clone(),
+        items: 10,
+    };
+    let sink = RecordingSink::default();
+    let calls = sink.calls.clone();
+    write_items(rx, sink, 1, Some(log)).await.unwrap();
+
+    let saved = Checkpoint::load(&checkpoint_path).await.unwrap().unwrap();
+    assert_eq!(saved.items_emitted, 12);
+    assert_eq!(saved.output_len, None);
+    let calls = calls.lock().unwrap();
+    let flush = calls.iter().position(|call| call == "flush").unwrap();
+    assert_eq!(calls[..flush], ["write 1", "write 1"]);
+}
+
+#[tokio::test]
+async fn test_avro_sink_round_trips_the_items() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.avro");
+    let items = parse_nested().await;
+
+    let mut sink = AvroSink::create(&path).await.unwrap();
+    sink.write(&items).await.unwrap();
+    sink.finish().await.unwrap();
+
+    assert_same_items(read_avro(&path), &items);
+    // the tag that is not UTF-8 keeps its bytes
+    assert_eq!(
+        read_avro(&path)[2].as_ref().unwrap().tags()[1].value(),
+        [0xff, 0xfe]
+    );
+}
+
+#[tokio::test]
+async fn test_avro_sink_appends_and_resumes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.avro");
+    let items = parse(5).await;
+
+    let mut sink = AvroSink::create(&path).await.unwrap();
+    sink.write(&items[..1]).await.unwrap();
+    sink.finish().await.unwrap();
+    let mut sink = AvroSink::append(&path).await.unwrap();
+    sink.write(&items[1..3]).await.unwrap();
+    let len = sink.flush().await.unwrap().unwrap();
+    sink.write(&items[3..]).await.unwrap();
+    sink.finish().await.unwrap();
+    assert_same_items(read_avro(&path), &items);
+
+    // every flush leaves a complete file
+    let sink = AvroSink::resume(&path, len).await.unwrap();
+    sink.finish().await.unwrap();
+    assert_same_items(read_avro(&path), &items[..3]);
+}
+
+#[tokio::test]
+async fn test_appending_to_another_avro_file_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.avro");
+    std::fs::write(&path, vec![7u8; 100]).unwrap();
+
+    let err = AvroSink::append(&path).await.unwrap_err();
+
+    assert!(err.contains("is not an Avro file of items"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_msgpack_sink_round_trips_the_items() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.msgpack");
+    let items = parse_nested().await;
+    let more = parse(3).await;
+
+    let mut sink = MessagePackSink::create(&path).await.unwrap();
+    sink.write(&items).await.unwrap();
+    let len = sink.flush().await.unwrap().unwrap();
+    sink.write(&more[..1]).await.unwrap();
+    sink.finish().await.unwrap();
+    let mut sink = MessagePackSink::resume(&path, len).await.unwrap();
+    sink.write(&more[1..2]).await.unwrap();
+    sink.finish().await.unwrap();
+    let mut sink = MessagePackSink::append(&path).await.unwrap();
+    sink.write(&more[2..]).await.unwrap();
+    sink.finish().await.unwrap();
+
+    let mut written = parse_nested().await;
+    written.extend(parse(3).await.into_iter().skip(1));
+    assert_same_items(read_msgpack(&path), &written);
+}
+
+#[tokio::test]
+async fn test_msgpack_reader_stops_on_damaged_items() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.msgpack");
+    let items = parse(2).await;
+    let mut sink = MessagePackSink::create(&path).await.unwrap();
+    sink.write(&items).await.unwrap();
+    sink.finish().await.unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+
+    let read = read_msgpack(&path);
+
+    assert_eq!(read.len(), 2);
+    assert!(read[0].is_ok());
+    assert!(read[1].is_err());
+}
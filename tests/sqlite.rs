@@ -0,0 +1,179 @@
+mod common;
+
+use std::io::Cursor;
+
+use arweave_ans_1040_indexer::{process_bundle, write_items, DataItem, Sink, SqliteSink};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, TestItem};
+use rusqlite::Connection;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+async fn parse(items: &[TestItem], root_tx: &str) -> Vec<DataItem> {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cursor = Cursor::new(bundle_bytes(items));
+    process_bundle(&mut cursor, tx, root_tx).await.unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+fn leaf(seed: u8) -> TestItem {
+    TestItem::new(2, vec![seed; 40]).with_signature_seed(seed)
+}
+
+// A root bundle holding an item and a nested bundle of two items, one of them tagged.
+fn nested_sample() -> Vec<TestItem> {
+    let mut tagged = leaf(3);
+    tagged
+        .tags
+        .push((b"App-Name".to_vec(), b"indexer".to_vec()));
+    vec![
+        leaf(1),
+        TestItem::nested(2, vec![leaf(2), tagged]).with_signature_seed(4),
+    ]
+}
+
+async fn index(path: &std::path::Path, items: Vec<DataItem>) {
+    let (tx, rx) = mpsc::channel(16);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    let sink = SqliteSink::open(path).await.unwrap();
+    write_items(rx, sink, 2, None).await.unwrap();
+}
+
+fn count(connection: &Connection, table: &str) -> i64 {
+    connection
+        .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+}
+
+type BundleRow = (String, String, Option<String>, i64, i64, i64);
+
+fn bundles(connection: &Connection) -> Vec<BundleRow> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, root, parent, depth, item_count, bundle_count FROM bundles ORDER BY depth",
+        )
+        .unwrap();
+    statement
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_tables_hold_the_items_tags_and_bundles() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index.sqlite");
+    let items = parse(&nested_sample(), ROOT_TX).await;
+    let nested_id = BASE64_URL.encode(items[1].id());
+    let tagged_id = BASE64_URL.encode(items[3].id());
+    index(&path, items).await;
+
+    let connection = Connection::open(&path).unwrap();
+    assert_eq!(count(&connection, "items"), 4);
+    assert_eq!(
+        bundles(&connection),
+        vec![
+            (ROOT_TX.to_string(), ROOT_TX.to_string(), None, 0, 2, 1),
+            (
+                nested_id.clone(),
+                ROOT_TX.to_string(),
+                Some(ROOT_TX.to_string()),
+                1,
+                2,
+                0
+            ),
+        ]
+    );
+
+    let (bundled_in, root_tx, data_size): (String, String, i64) = connection
+        .query_row(
+            "SELECT bundled_in, root_tx, data_size FROM items WHERE id = ?1",
+            [&tagged_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!(
+        (bundled_in, root_tx, data_size),
+        (nested_id, ROOT_TX.to_string(), 40)
+    );
+
+    let found: Vec<String> = connection
+        .prepare("SELECT item_id FROM tags WHERE name = ?1 AND value = ?2")
+        .unwrap()
+        .query_map(["App-Name", "indexer"], |row| row.get(0))
+        .unwrap()
+        .map(|id| id.unwrap())
+        .collect();
+    assert_eq!(found, vec![tagged_id]);
+}
+
+#[tokio::test]
+async fn test_indexing_again_does_not_duplicate_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index.sqlite");
+    index(&path, parse(&nested_sample(), ROOT_TX).await).await;
+    let connection = Connection::open(&path).unwrap();
+    let first = (
+        count(&connection, "items"),
+        count(&connection, "tags"),
+        bundles(&connection),
+    );
+
+    index(&path, parse(&nested_sample(), ROOT_TX).await).await;
+
+    let again = (
+        count(&connection, "items"),
+        count(&connection, "tags"),
+        bundles(&connection),
+    );
+    assert_eq!(again, first);
+    assert_eq!(first.0, 4);
+}
+
+#[tokio::test]
+async fn test_items_found_in_another_bundle_move_to_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index.sqlite");
+    index(&path, parse(&[leaf(1), leaf(2)], ROOT_TX).await).await;
+
+    // the same item posted again in another transaction
+    let mut sink = SqliteSink::open(&path).await.unwrap();
+    sink.write(&parse(&[leaf(2)], "other-tx").await)
+        .await
+        .unwrap();
+    sink.finish().await.unwrap();
+
+    let connection = Connection::open(&path).unwrap();
+    assert_eq!(count(&connection, "items"), 2);
+    let counts: Vec<(String, i64)> = bundles(&connection)
+        .into_iter()
+        .map(|bundle| (bundle.0, bundle.4))
+        .collect();
+    assert!(counts.contains(&(ROOT_TX.to_string(), 1)), "{:?}", counts);
+    assert!(
+        counts.contains(&("other-tx".to_string(), 1)),
+        "{:?}",
+        counts
+    );
+}
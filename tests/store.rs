@@ -0,0 +1,307 @@
+mod common;
+
+use std::io::Cursor;
+
+use arweave_ans_1040_indexer::{
+    process_bundle, write_items, DataItem, ItemFilter, ItemIndex, ItemStore, Sink, TagFilter,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::{bundle_bytes, TestItem};
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+async fn parse(items: &[TestItem]) -> Vec<DataItem> {
+    let (tx, mut rx) = mpsc::channel(64);
+    let mut cursor = Cursor::new(bundle_bytes(items));
+    process_bundle(&mut cursor, tx, ROOT_TX).await.unwrap();
+    let mut parsed = Vec::new();
+    while let Some(item) = rx.recv().await {
+        parsed.push(item);
+    }
+    parsed
+}
+
+fn leaf(seed: u8) -> TestItem {
+    TestItem::new(2, vec![seed; 40]).with_signature_seed(seed)
+}
+
+// A root bundle holding an item and a nested bundle of two items, one of them with a target
+// and an App-Name tag.
+fn nested_sample() -> Vec<TestItem> {
+    let mut tagged = leaf(3);
+    tagged.target = Some([9u8; 32]);
+    tagged
+        .tags
+        .push((b"App-Name".to_vec(), b"indexer".to_vec()));
+    let mut other = leaf(5);
+    other
+        .tags
+        .push((b"App-Name".to_vec(), b"index-viewer".to_vec()));
+    vec![
+        leaf(1),
+        TestItem::nested(2, vec![other, tagged]).with_signature_seed(4),
+    ]
+}
+
+async fn index(store: &ItemStore, items: Vec<DataItem>) {
+    let (tx, rx) = mpsc::channel(16);
+    for item in items {
+        tx.send(item).await.unwrap();
+    }
+    drop(tx);
+    write_items(rx, store.clone(), 2, None).await.unwrap();
+}
+
+fn id(item: &DataItem) -> String {
+    BASE64_URL.encode(item.id())
+}
+
+#[tokio::test]
+async fn test_items_are_found_by_id() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    let items = parse(&nested_sample()).await;
+    index(&store, parse(&nested_sample()).await).await;
+
+    for item in &items {
+        let found = store.get(&id(item)).unwrap().unwrap();
+        assert_eq!(found.id(), item.id());
+        assert_eq!(found.signature(), item.signature());
+        assert_eq!(found.bundled_in(), item.bundled_in());
+        assert_eq!(found.ancestry(), item.ancestry());
+        assert_eq!(
+            (found.offset(), found.size(), found.data_offset()),
+            (item.offset(), item.size(), item.data_offset())
+        );
+        assert_eq!(found.tags().len(), item.tags().len());
+    }
+    assert!(store.get("missing").unwrap().is_none());
+
+    let prefix = &id(&items[2])[..4];
+    let found = store.ids_with_prefix(prefix, 10).unwrap();
+    assert!(found.contains(&id(&items[2])), "{:?}", found);
+    assert!(found.iter().all(|found| found.starts_with(prefix)));
+    assert_eq!(store.ids_with_prefix("", 3).unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_items_are_found_by_owner_target_and_tag() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    let items = parse(&nested_sample()).await;
+    index(&store, parse(&nested_sample()).await).await;
+    let (other, tagged) = (id(&items[2]), id(&items[3]));
+
+    assert_eq!(
+        store.find(ItemIndex::Tag("App-Name"), "indexer").unwrap(),
+        vec![tagged.clone()]
+    );
+    let mut prefixed = vec![other.clone(), tagged.clone()];
+    prefixed.sort();
+    let mut found = store
+        .find_prefix(ItemIndex::Tag("App-Name"), "index", 10)
+        .unwrap();
+    found.sort();
+    assert_eq!(found, prefixed);
+    assert_eq!(
+        store
+            .find_prefix(ItemIndex::Tag("App-Name"), "index", 1)
+            .unwrap()
+            .len(),
+        1
+    );
+    // the value of another tag name is not matched
+    assert!(store
+        .find(ItemIndex::Tag("Content-Type"), "indexer")
+        .unwrap()
+        .is_empty());
+
+    let target = BASE64_URL.encode([9u8; 32]);
+    assert_eq!(
+        store.find(ItemIndex::Target, &target).unwrap(),
+        vec![tagged.clone()]
+    );
+    assert_eq!(
+        store
+            .find_prefix(ItemIndex::Target, &target[..5], 10)
+            .unwrap(),
+        vec![tagged.clone()]
+    );
+
+    let owner = items[3].owner_address();
+    let owned = store.find(ItemIndex::Owner, &owner).unwrap();
+    let expected: Vec<String> = items
+        .iter()
+        .filter(|item| item.owner_address() == owner)
+        .map(id)
+        .collect();
+    assert_eq!(owned.len(), expected.len());
+    assert!(expected.iter().all(|id| owned.contains(id)));
+}
+
+#[tokio::test]
+async fn test_indexing_again_does_not_duplicate_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("items.redb");
+    let store = ItemStore::open(&path).unwrap();
+    index(&store, parse(&nested_sample()).await).await;
+    drop(store);
+
+    let mut store = ItemStore::open(&path).unwrap();
+    let items = parse(&nested_sample()).await;
+    store.write(&items).await.unwrap();
+    store.write(&items).await.unwrap();
+    store.finish().await.unwrap();
+
+    let store = ItemStore::open(&path).unwrap();
+    assert_eq!(store.ids_with_prefix("", usize::MAX).unwrap().len(), 4);
+    assert_eq!(
+        store
+            .find_prefix(ItemIndex::Tag("App-Name"), "", usize::MAX)
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+fn tag(name: &str, values: &[&str]) -> TagFilter {
+    TagFilter {
+        name: name.to_string(),
+        values: values.iter().map(|value| value.to_string()).collect(),
+    }
+}
+
+fn ids(items: &[DataItem]) -> Vec<String> {
+    items.iter().map(id).collect()
+}
+
+#[tokio::test]
+async fn test_query_matches_every_criterion() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    let items = parse(&nested_sample()).await;
+    index(&store, parse(&nested_sample()).await).await;
+    let (nested, other, tagged) = (id(&items[1]), id(&items[2]), id(&items[3]));
+    let sorted = |mut ids: Vec<String>| {
+        ids.sort();
+        ids
+    };
+    let query = |filter: &ItemFilter| ids(&store.query(filter, None, usize::MAX).unwrap());
+
+    assert_eq!(query(&ItemFilter::default()), sorted(ids(&items)));
+    let in_nested = ItemFilter {
+        bundled_in: vec![nested.clone()],
+        ..ItemFilter::default()
+    };
+    assert_eq!(
+        query(&in_nested),
+        sorted(vec![other.clone(), tagged.clone()])
+    );
+    let in_root = ItemFilter {
+        root_txs: vec![ROOT_TX.to_string()],
+        ..ItemFilter::default()
+    };
+    assert_eq!(query(&in_root).len(), 4);
+
+    // values of a tag match any of them, tags all of them unless any_tag is set
+    let tagged_filter = ItemFilter {
+        tags: vec![
+            tag("App-Name", &["indexer", "index-viewer"]),
+            tag("Content-Type", &["text/plain"]),
+        ],
+        ..in_nested.clone()
+    };
+    assert_eq!(
+        query(&tagged_filter),
+        sorted(vec![other.clone(), tagged.clone()])
+    );
+    let both = ItemFilter {
+        tags: vec![tag("App-Name", &["indexer"]), tag("Missing", &["value"])],
+        ..ItemFilter::default()
+    };
+    assert!(query(&both).is_empty());
+    assert_eq!(
+        query(&ItemFilter {
+            any_tag: true,
+            ..both
+        }),
+        vec![tagged.clone()]
+    );
+
+    let ids_and_target = ItemFilter {
+        ids: vec![other.clone(), tagged.clone(), "missing".to_string()],
+        targets: vec![BASE64_URL.encode([9u8; 32])],
+        ..ItemFilter::default()
+    };
+    assert_eq!(query(&ids_and_target), vec![tagged.clone()]);
+}
+
+#[tokio::test]
+async fn test_query_pages_follow_the_ids() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    index(&store, parse(&nested_sample()).await).await;
+    let in_root = ItemFilter {
+        root_txs: vec![ROOT_TX.to_string()],
+        ..ItemFilter::default()
+    };
+
+    // items with several of the tags are walked once
+    let any_tag = ItemFilter {
+        tags: vec![
+            tag("App-Name", &["indexer", "index-viewer"]),
+            tag("Content-Type", &["text/plain"]),
+        ],
+        any_tag: true,
+        ..ItemFilter::default()
+    };
+
+    for filter in [ItemFilter::default(), in_root, any_tag] {
+        let all = ids(&store.query(&filter, None, usize::MAX).unwrap());
+        assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+        for size in [1, 3] {
+            let mut paged = Vec::new();
+            let mut after = None;
+            loop {
+                let page = ids(&store.query(&filter, after.as_deref(), size).unwrap());
+                let Some(last) = page.last().cloned() else {
+                    break;
+                };
+                paged.extend(page);
+                after = Some(last);
+            }
+            assert_eq!(paged, all);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_items_found_in_another_bundle_move_to_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = ItemStore::open(dir.path().join("items.redb")).unwrap();
+    index(&store, parse(&[leaf(1), leaf(2)]).await).await;
+
+    // the same item posted again in another transaction
+    let (tx, mut rx) = mpsc::channel(4);
+    let mut cursor = Cursor::new(bundle_bytes(&[leaf(2)]));
+    process_bundle(&mut cursor, tx, "other-tx").await.unwrap();
+    let moved = rx.recv().await.unwrap();
+    let moved_id = id(&moved);
+    index(&store, vec![moved]).await;
+
+    assert_eq!(store.find(ItemIndex::BundledIn, ROOT_TX).unwrap().len(), 1);
+    assert_eq!(
+        store.find(ItemIndex::BundledIn, "other-tx").unwrap(),
+        vec![moved_id.clone()]
+    );
+    assert_eq!(
+        store.find(ItemIndex::RootTx, "other-tx").unwrap(),
+        vec![moved_id.clone()]
+    );
+    assert_eq!(
+        store.get(&moved_id).unwrap().unwrap().bundled_in(),
+        "other-tx"
+    );
+}
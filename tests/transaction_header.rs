@@ -0,0 +1,110 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use arweave_ans_1040_indexer::{
+    process_bundle_with_options, DataItem, NodeClient, ProcessOptions, RetryPolicy, RootTransaction,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL;
+use base64::Engine;
+use common::node::MockNode;
+use common::{bundle_bytes, TestItem};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+use tokio::sync::mpsc;
+
+const ROOT_TX: &str = "root-tx";
+
+fn sample_items() -> Vec<TestItem> {
+    vec![
+        TestItem::new(2, vec![1u8; 120]).with_signature_seed(1),
+        TestItem::new(1, vec![2u8; 80]).with_signature_seed(2),
+    ]
+}
+
+fn client(url: String) -> NodeClient {
+    NodeClient::new(&[url]).with_retry(RetryPolicy {
+        max_retries: 0,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    })
+}
+
+#[tokio::test]
+async fn test_root_transaction_is_attached_to_every_item() {
+    let items = sample_items();
+    let owner = vec![9u8; 512];
+    let mut node = MockNode::default();
+    node.add_bundle_tx(ROOT_TX, &items, &owner).status = Some(json!({
+        "block_height": 1_234_567,
+        "block_indep_hash": "block-hash",
+        "number_of_confirmations": 10,
+    }));
+    let client = client(node.serve().await);
+
+    let header = client.transaction(ROOT_TX).await.unwrap();
+    assert!(header.is_bundle());
+    assert_eq!(header.data_size, bundle_bytes(&items).len() as u64);
+
+    let status = client.transaction_status(ROOT_TX).await.unwrap();
+    let root_tx = RootTransaction::new(&header, status.as_ref());
+    let owner_address = BASE64_URL.encode(Sha256::digest(&owner));
+    assert_eq!(
+        root_tx,
+        RootTransaction {
+            id: ROOT_TX.to_string(),
+            owner_address: owner_address.clone(),
+            data_size: header.data_size,
+            block_height: Some(1_234_567),
+            block_hash: Some("block-hash".to_string()),
+            block_timestamp: None,
+        }
+    );
+
+    let (tx, mut rx) = mpsc::channel::<DataItem>(16);
+    let read_handle = tokio::spawn(async move {
+        let mut values = Vec::new();
+        while let Some(item) = rx.recv().await {
+            values.push(serde_json::to_value(&item).unwrap());
+        }
+        values
+    });
+    let options = ProcessOptions {
+        expected_size: Some(header.data_size),
+        root_tx: Some(Arc::new(root_tx)),
+        ..ProcessOptions::default()
+    };
+    let mut cursor = Cursor::new(bundle_bytes(&items));
+    process_bundle_with_options(&mut cursor, tx, ROOT_TX, &options)
+        .await
+        .unwrap();
+    let values = read_handle.await.unwrap();
+
+    assert_eq!(values.len(), 2);
+    for value in values {
+        assert_eq!(value["root_tx"]["owner_address"], json!(owner_address));
+        assert_eq!(value["root_tx"]["block_height"], json!(1_234_567));
+    }
+}
+
+#[tokio::test]
+async fn test_pending_transaction_has_no_block() {
+    let mut node = MockNode::default();
+    node.add_bundle_tx(ROOT_TX, &sample_items(), &[1u8; 32]);
+    let client = client(node.serve().await);
+
+    assert_eq!(client.transaction_status(ROOT_TX).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_untagged_transaction_is_not_a_bundle() {
+    let mut node = MockNode::default();
+    node.add_tx(ROOT_TX, bundle_bytes(&sample_items()));
+    let client = client(node.serve().await);
+
+    let header = client.transaction(ROOT_TX).await.unwrap();
+
+    assert!(!header.is_bundle());
+}